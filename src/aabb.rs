@@ -0,0 +1,94 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    min: Point3,
+    max: Point3,
+}
+
+impl Aabb {
+    // Boxes thinner than this along an axis are padded out to it, so flat shapes still
+    // have some volume for rays to hit
+    const MIN_THICKNESS: f32 = 1e-4;
+
+    /// Box spanning two opposite corners, given in any order
+    pub fn from(a: Point3, b: Point3) -> Self {
+        let mut min = Vec3::from(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let mut max = Vec3::from(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+
+        let pad = |lo: f32, hi: f32| {
+            if hi - lo < Self::MIN_THICKNESS {
+                let mid = (lo + hi) / 2.;
+                (mid - Self::MIN_THICKNESS / 2., mid + Self::MIN_THICKNESS / 2.)
+            } else {
+                (lo, hi)
+            }
+        };
+        let (x0, x1) = pad(min.x(), max.x());
+        let (y0, y1) = pad(min.y(), max.y());
+        let (z0, z1) = pad(min.z(), max.z());
+        min = Vec3::from(x0, y0, z0);
+        max = Vec3::from(x1, y1, z1);
+
+        Aabb {min, max}
+    }
+
+    /// Smallest box containing every point
+    pub fn around(points: &[Point3]) -> Self {
+        let (min, max) = points[1..].iter().fold((points[0], points[0]), |(min, max), p| {
+            (Vec3::from(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())),
+             Vec3::from(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())))
+        });
+        Aabb::from(min, max)
+    }
+
+    pub fn min(&self) -> Point3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Point3 {
+        self.max
+    }
+
+    /// Smallest box containing both boxes
+    pub fn merge(&self, other: &Aabb) -> Self {
+        Aabb::from(Vec3::from(self.min.x().min(other.min.x()),
+                              self.min.y().min(other.min.y()),
+                              self.min.z().min(other.min.z())),
+                   Vec3::from(self.max.x().max(other.max.x()),
+                              self.max.y().max(other.max.y()),
+                              self.max.z().max(other.max.z())))
+    }
+
+    /// Whether the ray passes through the box anywhere between t_min and t_max
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    /// Part of the range t_min to t_max which the ray spends inside the box, found with
+    /// the slab method
+    pub fn clip(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> Option<(f32, f32)> {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let axes = [(origin.x(), direction.x(), self.min.x(), self.max.x()),
+                    (origin.y(), direction.y(), self.min.y(), self.max.y()),
+                    (origin.z(), direction.z(), self.min.z(), self.max.z())];
+
+        for (o, d, lo, hi) in axes {
+            let inv = 1. / d;
+            let mut t0 = (lo - o) * inv;
+            let mut t1 = (hi - o) * inv;
+            if inv < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+
+        Some((t_min, t_max))
+    }
+}
@@ -0,0 +1,147 @@
+use std::ops::{Add, Mul, Sub};
+
+use crate::vec3::{Point3, Vec3};
+
+/// How a track moves between its keyframes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Smooth curve passing through every key, with the tangent at each key pointing
+    /// from the previous key to the next. Uneven key spacing is taken into account so
+    /// speed doesn't jump at the keys.
+    CatmullRom,
+}
+
+/// Keyframed values of a single property, ordered by frame
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    keys: Vec<(f32, T)>,
+    pub interpolation: Interpolation,
+}
+
+impl<T> Track<T>
+where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Track {keys: Vec::new(), interpolation}
+    }
+
+    /// Sets the value at `frame`, replacing any key already there
+    pub fn insert(&mut self, frame: f32, value: T) {
+        let i = self.keys.partition_point(|&(f, _)| f < frame);
+        if self.keys.get(i).is_some_and(|&(f, _)| f == frame) {
+            self.keys[i].1 = value;
+        } else {
+            self.keys.insert(i, (frame, value));
+        }
+    }
+
+    pub fn keys(&self) -> &[(f32, T)] {
+        &self.keys
+    }
+
+    /// Value keyed at exactly `frame`, if there is a key there
+    pub fn key(&self, frame: f32) -> Option<T> {
+        self.keys.iter().find(|&&(f, _)| f == frame).map(|&(_, value)| value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Value at `frame`, holding the first and last keys before and after the track,
+    /// or `None` if there are no keys at all
+    pub fn value(&self, frame: f32) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if frame <= first.0 {
+            return Some(first.1);
+        }
+        if frame >= last.0 {
+            return Some(last.1);
+        }
+
+        // Keys i and i + 1 surround the frame
+        let i = self.keys.partition_point(|&(f, _)| f <= frame) - 1;
+        let (f0, p0) = self.keys[i];
+        let (f1, p1) = self.keys[i + 1];
+        let h = f1 - f0;
+        let t = (frame - f0) / h;
+
+        match self.interpolation {
+            Interpolation::Linear => Some(p0 + (p1 - p0) * t),
+            Interpolation::CatmullRom => {
+                // Tangents in units per frame, one sided at either end of the track
+                let tangent = |j: usize| {
+                    let (fa, a) = self.keys[j.saturating_sub(1)];
+                    let (fb, b) = self.keys[(j + 1).min(self.keys.len() - 1)];
+                    (b - a) * (1. / (fb - fa))
+                };
+                let (m0, m1) = (tangent(i) * h, tangent(i + 1) * h);
+
+                // Cubic Hermite basis
+                let t2 = t * t;
+                let t3 = t2 * t;
+                Some(p0 * (2. * t3 - 3. * t2 + 1.)
+                     + m0 * (t3 - 2. * t2 + t)
+                     + p1 * (-2. * t3 + 3. * t2)
+                     + m1 * (t3 - t2))
+            },
+        }
+    }
+}
+
+/// Where a camera is and what it's looking at for a single frame
+#[derive(Debug, Clone, Copy)]
+pub struct CameraPose {
+    pub view_from: Point3,
+    pub view_at: Point3,
+    pub vfov: f32,
+    pub focus_dist: f32,
+}
+
+/// Keyframed camera properties. Properties without any keys keep the value of the
+/// pose the path is applied to.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    pub position: Track<Vec3>,
+    pub target: Track<Vec3>,
+    pub fov: Track<f32>,
+    pub focus: Track<f32>,
+}
+
+impl CameraPath {
+    pub fn new(interpolation: Interpolation) -> Self {
+        CameraPath {
+            position: Track::new(interpolation),
+            target: Track::new(interpolation),
+            fov: Track::new(interpolation),
+            focus: Track::new(interpolation),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position.is_empty() && self.target.is_empty() && self.fov.is_empty()
+            && self.focus.is_empty()
+    }
+
+    /// First and last frames with a key on any property
+    pub fn frame_range(&self) -> Option<(f32, f32)> {
+        let frames = self.position.keys().iter().map(|k| k.0)
+                         .chain(self.target.keys().iter().map(|k| k.0))
+                         .chain(self.fov.keys().iter().map(|k| k.0))
+                         .chain(self.focus.keys().iter().map(|k| k.0));
+        frames.fold(None, |range, f| match range {
+            Some((start, end)) => Some((f32::min(start, f), f32::max(end, f))),
+            None => Some((f, f)),
+        })
+    }
+
+    /// `base` with every keyed property replaced by its value at `frame`
+    pub fn pose(&self, frame: f32, base: &CameraPose) -> CameraPose {
+        CameraPose {
+            view_from: self.position.value(frame).unwrap_or(base.view_from),
+            view_at: self.target.value(frame).unwrap_or(base.view_at),
+            vfov: self.fov.value(frame).unwrap_or(base.vfov),
+            focus_dist: self.focus.value(frame).unwrap_or(base.focus_dist),
+        }
+    }
+}
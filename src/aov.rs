@@ -0,0 +1,159 @@
+use crate::vec3::{Color, Point3, Vec3, unit_vector};
+use crate::film::Film;
+use crate::output::ExrChannel;
+
+/// What a camera ray first hit in the scene
+pub struct FirstHit {
+    pub depth: f32,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Point3,
+    pub material_id: u32,
+    pub object_id: u32,
+}
+
+/// Everything recorded about a single camera sample, with its radiance split into
+/// light arriving directly from the background and light which bounced more than once
+pub struct AovSample {
+    pub first_hit: Option<FirstHit>,
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+impl AovSample {
+    // Sample which saw nothing at all
+    pub fn new() -> Self {
+        AovSample {
+            first_hit: None,
+            direct: Color::new(),
+            indirect: Color::new(),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        self.direct + self.indirect
+    }
+}
+
+impl Default for AovSample {
+    fn default() -> Self {
+        AovSample::new()
+    }
+}
+
+/// First hit data gathered for one pixel. Continuous values are averaged over the
+/// samples which hit something, while IDs are taken from the first such sample.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GeometryPixel {
+    pub hits: u32,
+    pub depth: f32,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Point3,
+    pub material_id: u32,
+    pub object_id: u32,
+}
+
+impl GeometryPixel {
+    pub fn add(&mut self, hit: &FirstHit) {
+        if self.hits == 0 {
+            self.material_id = hit.material_id;
+            self.object_id = hit.object_id;
+        }
+        self.hits += 1;
+        self.depth += hit.depth;
+        self.normal += hit.normal;
+        self.albedo += hit.albedo;
+        self.position += hit.position;
+    }
+
+    pub fn merge(&mut self, other: &GeometryPixel) {
+        if self.hits == 0 {
+            self.material_id = other.material_id;
+            self.object_id = other.object_id;
+        }
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.position += other.position;
+    }
+
+    // Distance to the first hit, infinite where nothing was hit
+    pub fn depth(&self) -> f32 {
+        if self.hits > 0 {self.depth / self.hits as f32} else {f32::INFINITY}
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        if self.normal.near_zero() {self.normal} else {unit_vector(self.normal)}
+    }
+
+    pub fn albedo(&self) -> Color {
+        if self.hits > 0 {self.albedo / self.hits as f32} else {Color::new()}
+    }
+
+    pub fn position(&self) -> Point3 {
+        if self.hits > 0 {self.position / self.hits as f32} else {Point3::new()}
+    }
+}
+
+/// Arbitrary output variables accumulated alongside the beauty film. Lighting
+/// passes share the beauty pass's reconstruction filter so that they sum to it.
+pub struct AovFilm {
+    width: u32,
+    pub direct: Film,
+    pub indirect: Film,
+    geometry: Vec<GeometryPixel>,
+}
+
+impl AovFilm {
+    pub fn new(width: u32, height: u32) -> Self {
+        AovFilm {
+            width,
+            direct: Film::new(width, height),
+            indirect: Film::new(width, height),
+            geometry: vec![GeometryPixel::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn geometry(&self, x: u32, y: u32) -> &GeometryPixel {
+        &self.geometry[(y * self.width + x) as usize]
+    }
+
+    pub fn geometry_mut(&mut self, x: u32, y: u32) -> &mut GeometryPixel {
+        &mut self.geometry[(y * self.width + x) as usize]
+    }
+
+    /// Named image channels for each output variable, in row order from the top
+    pub fn channels(&self) -> Vec<(String, ExrChannel)> {
+        let (width, height) = (self.direct.width(), self.direct.height());
+        let pixels = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+        let float = |f: &dyn Fn(u32, u32) -> f32| {
+            ExrChannel::Float(pixels().map(|(x, y)| f(x, y)).collect())
+        };
+        let uint = |f: &dyn Fn(&GeometryPixel) -> u32| {
+            ExrChannel::Uint(self.geometry.iter().map(f).collect())
+        };
+
+        vec![
+            ("depth.Z".to_string(), float(&|x, y| self.geometry(x, y).depth())),
+            ("normal.X".to_string(), float(&|x, y| self.geometry(x, y).normal().x())),
+            ("normal.Y".to_string(), float(&|x, y| self.geometry(x, y).normal().y())),
+            ("normal.Z".to_string(), float(&|x, y| self.geometry(x, y).normal().z())),
+            ("albedo.R".to_string(), float(&|x, y| self.geometry(x, y).albedo().x())),
+            ("albedo.G".to_string(), float(&|x, y| self.geometry(x, y).albedo().y())),
+            ("albedo.B".to_string(), float(&|x, y| self.geometry(x, y).albedo().z())),
+            ("position.X".to_string(), float(&|x, y| self.geometry(x, y).position().x())),
+            ("position.Y".to_string(), float(&|x, y| self.geometry(x, y).position().y())),
+            ("position.Z".to_string(), float(&|x, y| self.geometry(x, y).position().z())),
+            ("materialId.id".to_string(), uint(&|pixel| pixel.material_id)),
+            ("objectId.id".to_string(), uint(&|pixel| pixel.object_id)),
+            ("direct.R".to_string(), float(&|x, y| self.direct.color(x, y).x())),
+            ("direct.G".to_string(), float(&|x, y| self.direct.color(x, y).y())),
+            ("direct.B".to_string(), float(&|x, y| self.direct.color(x, y).z())),
+            ("indirect.R".to_string(), float(&|x, y| self.indirect.color(x, y).x())),
+            ("indirect.G".to_string(), float(&|x, y| self.indirect.color(x, y).y())),
+            ("indirect.B".to_string(), float(&|x, y| self.indirect.color(x, y).z())),
+        ]
+    }
+}
@@ -0,0 +1,98 @@
+use std::f32::consts::PI;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::vec3::Vec3;
+use crate::color::srgb_to_linear;
+use crate::image::Image;
+
+/// Shape of the opening light passes through on its way into the lens, which gives
+/// out of focus highlights (bokeh) their shape. Samples are points in the unit disk.
+#[derive(Clone)]
+pub enum Aperture {
+    Circular,
+    /// Regular polygon formed by `blades` straight blades, inscribed in the unit circle
+    /// and turned by `rotation` degrees
+    Polygon {blades: u32, rotation: f32},
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// Random point on the aperture, with z = 0
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circular => Vec3::random_in_unit_disk(),
+            Aperture::Polygon {blades, rotation} => {
+                let mut rng = rand::thread_rng();
+
+                // Every blade edge closes off an equal triangle with the center, so pick
+                // one at random and sample it uniformly
+                let blade = rng.gen_range(0..*blades) as f32;
+                let step = 2. * PI / *blades as f32;
+                let a0 = rotation.to_radians() + blade * step;
+                let a1 = a0 + step;
+
+                let (mut s, mut t): (f32, f32) = (rng.gen(), rng.gen());
+                if s + t > 1. {
+                    s = 1. - s;
+                    t = 1. - t;
+                }
+                Vec3::from(s * a0.cos() + t * a1.cos(), s * a0.sin() + t * a1.sin(), 0.)
+            },
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+/// Aperture whose transmission is read from a grayscale image, white letting light
+/// through and black blocking it. The image spans the lens diameter along its longer
+/// side, and points are chosen in proportion to transmission so the overall brightness
+/// of the image doesn't change.
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let image = Image::load(path)?;
+
+        let mut cdf = Vec::with_capacity((image.width() * image.height()) as usize);
+        let mut total = 0.;
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let pixel = image.pixel(x, y);
+                let mut value = (pixel.x() + pixel.y() + pixel.z()) / 3.;
+                if image.bit_depth() == 8 {
+                    value = srgb_to_linear(value);
+                }
+                total += value;
+                cdf.push(total);
+            }
+        }
+        if total <= 0. {
+            return Err(Error::new(ErrorKind::InvalidData, "aperture mask is entirely black"));
+        }
+
+        Ok(ApertureMask {width: image.width(), height: image.height(), cdf})
+    }
+
+    fn sample(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = rng.gen_range(0.0..total);
+        let index = self.cdf.partition_point(|&sum| sum <= target).min(self.cdf.len() - 1);
+
+        // Jitter within the chosen pixel, with image rows running from the top
+        let size = self.width.max(self.height) as f32;
+        let x = (index as u32 % self.width) as f32 + rng.gen::<f32>();
+        let y = (index as u32 / self.width) as f32 + rng.gen::<f32>();
+        Vec3::from((2. * x - self.width as f32) / size,
+                   (self.height as f32 - 2. * y) / size,
+                   0.)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::aabb::Aabb;
+
+// Most primitives left together in a leaf
+const LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over a set of primitives known by their index, for shapes
+/// made of many small parts. Built once from the parts' bounding boxes, it narrows each
+/// ray down to the few parts whose boxes it passes through.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // Primitive indices, arranged so each leaf covers a contiguous run
+    order: Vec<u32>,
+}
+
+struct Node {
+    bounds: Aabb,
+    // Leaves cover `count` primitives from `start` in the order. Interior nodes have a
+    // count of zero, their first child straight after them and the second at `start`.
+    start: u32,
+    count: u32,
+    axis: u8,
+}
+
+impl Bvh {
+    pub fn build(boxes: &[Aabb]) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * boxes.len() / LEAF_SIZE + 1),
+            order: (0..boxes.len() as u32).collect(),
+        };
+        let centers: Vec<Vec3> = boxes.iter().map(|b| (b.min() + b.max()) / 2.).collect();
+        if !boxes.is_empty() {
+            bvh.split(boxes, &centers, 0, boxes.len());
+        }
+        bvh
+    }
+
+    // Adds the node covering order[start..end], splitting it in half at the median
+    // center along the axis the centers are most spread out on
+    fn split(&mut self, boxes: &[Aabb], centers: &[Vec3], start: usize, end: usize) {
+        let items = &mut self.order[start..end];
+        let bounds = items[1..].iter().fold(boxes[items[0] as usize], |b, &i| {
+            b.merge(&boxes[i as usize])
+        });
+        let index = self.nodes.len();
+        let count = items.len() as u32;
+        self.nodes.push(Node {bounds, start: start as u32, count, axis: 0});
+        if items.len() <= LEAF_SIZE {
+            return;
+        }
+
+        let spread = Aabb::around(&items.iter().map(|&i| centers[i as usize])
+                                        .collect::<Vec<_>>());
+        let extent = spread.max() - spread.min();
+        let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        };
+        let key = |i: &u32| {
+            let c = centers[*i as usize];
+            [c.x(), c.y(), c.z()][axis]
+        };
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| key(a).total_cmp(&key(b)));
+
+        self.split(boxes, centers, start, start + mid);
+        let second = self.nodes.len() as u32;
+        self.split(boxes, centers, start + mid, end);
+        self.nodes[index] = Node {bounds, start: second, count: 0, axis: axis as u8};
+    }
+
+    /// Nearest hit among the primitives, calling `hit` with the index of each one the
+    /// ray may reach and the furthest distance still worth finding. A hit returns its
+    /// distance along the ray together with whatever it needs to report.
+    pub fn hit<R>(&self, ray: &Ray, t_min: f32, mut t_max: f32,
+                  mut hit: impl FnMut(usize, f32) -> Option<(f32, R)>) -> Option<R> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let direction = ray.direction();
+        let negative = [direction.x() < 0., direction.y() < 0., direction.z() < 0.];
+        let mut nearest = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(ray, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                let start = node.start as usize;
+                for &i in &self.order[start..start + node.count as usize] {
+                    if let Some((t, found)) = hit(i as usize, t_max) {
+                        t_max = t;
+                        nearest = Some(found);
+                    }
+                }
+            } else {
+                // Visit the child nearer the ray's origin first, so hits found there
+                // can rule out the other
+                let (first, second) = (index + 1, node.start as usize);
+                if negative[node.axis as usize] {
+                    stack.push(first);
+                    stack.push(second);
+                } else {
+                    stack.push(second);
+                    stack.push(first);
+                }
+            }
+        }
+
+        nearest
+    }
+}
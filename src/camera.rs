@@ -1,5 +1,83 @@
 use crate::vec3::*;
 use crate::ray::Ray;
+use crate::aperture::Aperture;
+
+/// Anything which can turn a position on the image, with u and v running from 0 to 1
+/// left to right and bottom to top, into a ray leaving the camera. Positions which
+/// don't correspond to any direction, like the corners of a fisheye image, give `None`.
+pub trait CameraModel: Sync {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray>;
+}
+
+/// How rays leave the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// Camera described in photographic terms. Focal length and sensor size are in
+/// millimetres and world units are taken to be metres.
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalCamera {
+    pub focal_length: f32,
+    pub sensor_width: f32,
+    pub sensor_height: f32,
+    pub f_number: f32,
+    pub shutter: f32,
+    pub iso: f32,
+}
+
+impl PhysicalCamera {
+    // Exposure of a camera following the "sunny 16" rule (f/16 with a shutter speed of
+    // 1/ISO), which is taken to be correct for scene radiance on the order of 1
+    const SUNNY_16: f32 = 1. / (16. * 16. * 100.);
+
+    /// Vertical field of view in degrees for an image of the given aspect ratio. The
+    /// image is fitted inside the sensor, like the sensor fit of most 3D packages.
+    pub fn vfov(&self, aspect_ratio: f32) -> f32 {
+        let height = if aspect_ratio >= self.sensor_width / self.sensor_height {
+            self.sensor_width / aspect_ratio
+        } else {
+            self.sensor_height
+        };
+        2. * (height / (2. * self.focal_length)).atan().to_degrees()
+    }
+
+    /// Diameter of the entrance pupil in world units
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_number / 1000.
+    }
+
+    /// Exposure in stops relative to the sunny 16 rule, growing by one for every
+    /// doubling of shutter time or ISO and shrinking by two for every doubling of the
+    /// f-number
+    pub fn exposure(&self) -> f32 {
+        let exposure = self.shutter * (self.iso / 100.) / (self.f_number * self.f_number);
+        (exposure / Self::SUNNY_16).log2()
+    }
+
+    /// Perspective camera with this lens, focused `focus_dist` units away
+    pub fn camera(&self, view_from: Point3, view_at: Point3, view_up: Vec3,
+                  aspect_ratio: f32, focus_dist: f32) -> Camera {
+        Camera::new(view_from, view_at, view_up, self.vfov(aspect_ratio), aspect_ratio,
+                    self.aperture(), focus_dist)
+    }
+}
+
+impl Default for PhysicalCamera {
+    // A 50mm lens on a full frame sensor, exposed for daylight
+    fn default() -> Self {
+        PhysicalCamera {
+            focal_length: 50.,
+            sensor_width: 36.,
+            sensor_height: 24.,
+            f_number: 16.,
+            shutter: 1. / 100.,
+            iso: 100.,
+        }
+    }
+}
 
 pub struct Camera {
     origin: Point3,
@@ -10,6 +88,9 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f32,
+    aperture: Aperture,
+    cats_eye: f32,
+    projection: Projection,
 }
 
 impl Camera {
@@ -19,9 +100,7 @@ impl Camera {
         let view_height = 2. * h;
         let view_width = aspect_ratio * view_height;
         
-        let w = unit_vector(view_from - view_at);
-        let u = unit_vector(cross(&view_up, &w));
-        let v = cross(&w, &u);
+        let (u, v, w) = Self::basis(view_from, view_at, view_up);
         let origin = view_from;
         let hori = focus_dist * view_width * u;
         let vert = focus_dist * view_height * v;
@@ -29,23 +108,107 @@ impl Camera {
         let lens_radius = aperture / 2.;
 
         Camera {
-            w,
             u,
             v,
+            w,
             origin,
             hori,
             vert,
             ll_corner,
             lens_radius,
+            aperture: Aperture::Circular,
+            cats_eye: 0.,
+            projection: Projection::Perspective,
+        }
+    }
+
+    /// Camera whose rays all travel parallel to the view direction, seeing a region
+    /// `view_width` world units across regardless of distance
+    pub fn orthographic(view_from: Point3, view_at: Point3, view_up: Vec3, view_width: f32,
+                        aspect_ratio: f32) -> Self {
+        let view_height = view_width / aspect_ratio;
+
+        let (u, v, w) = Self::basis(view_from, view_at, view_up);
+        let origin = view_from;
+        let hori = view_width * u;
+        let vert = view_height * v;
+        let ll_corner = origin - hori/2. - vert/2.;
+
+        Camera {
+            u,
+            v,
+            w,
+            origin,
+            hori,
+            vert,
+            ll_corner,
+            lens_radius: 0.,
+            aperture: Aperture::Circular,
+            cats_eye: 0.,
+            projection: Projection::Orthographic,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
-        let offset = self.u * rd.x() + self.v * rd.y();
+    /// Slides the image window sideways and up by fractions of its width and height,
+    /// like a shift lens, without turning the camera
+    pub fn shift(mut self, x: f32, y: f32) -> Self {
+        self.ll_corner += x * self.hori + y * self.vert;
+        self
+    }
 
-        Ray::from(self.origin + offset, 
-                  self.ll_corner + u * self.hori + v * self.vert - self.origin - offset
-        )
+    /// Replaces the default circular aperture, changing the shape of out of focus
+    /// highlights
+    pub fn aperture_shape(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    /// Clips the aperture against the lens barrel toward the edges of the image, giving
+    /// out of focus highlights there a cat's-eye shape. The barrel's opening slides
+    /// `strength` aperture radii off center at the corners of the image, and light it
+    /// blocks is lost, darkening the corners slightly as with a real lens.
+    pub fn cats_eye(mut self, strength: f32) -> Self {
+        self.cats_eye = strength;
+        self
+    }
+
+    /// Orthonormal look-at frame (u, v, w), with w pointing back toward the viewer
+    pub fn basis(view_from: Point3, view_at: Point3, view_up: Vec3) -> (Vec3, Vec3, Vec3) {
+        let w = unit_vector(view_from - view_at);
+        let u = unit_vector(cross(&view_up, &w));
+        let v = cross(&w, &u);
+
+        (u, v, w)
+    }
+}
+
+impl CameraModel for Camera {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        match self.projection {
+            Projection::Perspective => {
+                let rd = self.aperture.sample();
+                if self.cats_eye > 0. {
+                    // Position on the image relative to its center, reaching 1 at the
+                    // corners, which is also where the barrel is most off center
+                    let (width, height) = (self.hori.length(), self.vert.length());
+                    let half_diagonal = 0.5 * (width * width + height * height).sqrt();
+                    let barrel = Vec3::from((u - 0.5) * width, (v - 0.5) * height, 0.)
+                                 * (self.cats_eye / half_diagonal);
+                    if (rd - barrel).length_squared() > 1. {
+                        return None;
+                    }
+                }
+
+                let rd = self.lens_radius * rd;
+                let offset = self.u * rd.x() + self.v * rd.y();
+
+                Some(Ray::from(self.origin + offset, 
+                               self.ll_corner + u * self.hori + v * self.vert
+                               - self.origin - offset))
+            },
+            Projection::Orthographic => {
+                Some(Ray::from(self.ll_corner + u * self.hori + v * self.vert, -self.w))
+            },
+        }
     }
 }
@@ -0,0 +1,261 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Write};
+
+use crate::vec3::{Color, Vec3};
+use crate::render::{RenderState, TILE_SIZE};
+use crate::filter::Filter;
+use crate::film::Film;
+use crate::aov::{AovFilm, GeometryPixel};
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 4;
+
+/// Writes the accumulated film, any AOVs, reconstruction filter, scene seed, per tile
+/// sample counts and per tile RNG state to `path`. The data is first written to a
+/// temporary file and then renamed so that an interrupted write never clobbers the
+/// previous checkpoint.
+pub fn save(path: &str, state: &RenderState) -> Result<(), Error> {
+    let film = &state.film;
+    let mut data = Vec::new();
+
+    data.write_all(MAGIC)?;
+    for n in [VERSION, film.width(), film.height(), TILE_SIZE, state.tiles.len() as u32] {
+        data.write_all(&n.to_le_bytes())?;
+    }
+    data.write_all(&(state.filter.kind as u32).to_le_bytes())?;
+    data.write_all(&state.filter.radius.to_le_bytes())?;
+    data.write_all(&state.scene_seed.to_le_bytes())?;
+    for tile in &state.tiles {
+        data.write_all(&tile.samples.to_le_bytes())?;
+        data.write_all(&tile.rng_state.to_le_bytes())?;
+    }
+    write_film(&mut data, film)?;
+
+    match &state.aovs {
+        Some(aovs) => {
+            data.write_all(&1u32.to_le_bytes())?;
+            write_film(&mut data, &aovs.direct)?;
+            write_film(&mut data, &aovs.indirect)?;
+            for y in 0..film.height() {
+                for x in 0..film.width() {
+                    let pixel = aovs.geometry(x, y);
+                    data.write_all(&pixel.hits.to_le_bytes())?;
+                    data.write_all(&pixel.depth.to_le_bytes())?;
+                    for v in [pixel.normal, pixel.albedo, pixel.position] {
+                        for n in [v.x(), v.y(), v.z()] {
+                            data.write_all(&n.to_le_bytes())?;
+                        }
+                    }
+                    data.write_all(&pixel.material_id.to_le_bytes())?;
+                    data.write_all(&pixel.object_id.to_le_bytes())?;
+                }
+            }
+        },
+        None => data.write_all(&0u32.to_le_bytes())?,
+    }
+
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
+
+fn write_film(data: &mut Vec<u8>, film: &Film) -> Result<(), Error> {
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let sum = film.sum(x, y);
+            for n in [sum.x(), sum.y(), sum.z(), film.weight(x, y), film.abs_weight(x, y)] {
+                data.write_all(&n.to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(reader: &mut &[u8]) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64, Error> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f32(reader: &mut &[u8]) -> Result<f32, Error> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+fn read_vec3(reader: &mut &[u8]) -> Result<Vec3, Error> {
+    Ok(Vec3::from(read_f32(reader)?, read_f32(reader)?, read_f32(reader)?))
+}
+
+fn read_film(reader: &mut &[u8], film: &mut Film) -> Result<(), Error> {
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let sum: Color = read_vec3(reader)?;
+            let weight = read_f32(reader)?;
+            let abs_weight = read_f32(reader)?;
+            film.set(x, y, sum, weight, abs_weight);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores a render previously written by `save`, which must have been made with
+/// the same image dimensions and reconstruction filter, and which must include AOVs
+/// if `aovs` is set
+pub fn load(path: &str, width: u32, height: u32, filter: Filter,
+            aovs: bool) -> Result<RenderState, Error> {
+    let data = fs::read(path)?;
+    let mut reader = &data[..];
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+    if read_u32(&mut reader)? != VERSION {
+        return Err(invalid("unsupported checkpoint version"));
+    }
+    if read_u32(&mut reader)? != width || read_u32(&mut reader)? != height {
+        return Err(invalid("checkpoint was made with different image dimensions"));
+    }
+    if read_u32(&mut reader)? != TILE_SIZE {
+        return Err(invalid("checkpoint was made with a different tile size"));
+    }
+    let tile_count = read_u32(&mut reader)? as usize;
+    if read_u32(&mut reader)? != filter.kind as u32 || read_f32(&mut reader)? != filter.radius {
+        return Err(invalid("checkpoint was made with a different reconstruction filter"));
+    }
+    let scene_seed = read_u64(&mut reader)?;
+
+    let mut state = RenderState::new(width, height, filter, aovs);
+    state.scene_seed = scene_seed;
+    if tile_count != state.tiles.len() {
+        return Err(invalid("checkpoint tile count does not match image"));
+    }
+    for tile in state.tiles.iter_mut() {
+        tile.samples = read_u32(&mut reader)?;
+        tile.rng_state = read_u64(&mut reader)?;
+    }
+    read_film(&mut reader, &mut state.film)?;
+
+    let has_aovs = read_u32(&mut reader)? == 1;
+    if aovs && !has_aovs {
+        return Err(invalid("checkpoint was made without AOVs"));
+    }
+    if has_aovs {
+        let mut aov_film = AovFilm::new(width, height);
+        read_film(&mut reader, &mut aov_film.direct)?;
+        read_film(&mut reader, &mut aov_film.indirect)?;
+        for y in 0..height {
+            for x in 0..width {
+                *aov_film.geometry_mut(x, y) = GeometryPixel {
+                    hits: read_u32(&mut reader)?,
+                    depth: read_f32(&mut reader)?,
+                    normal: read_vec3(&mut reader)?,
+                    albedo: read_vec3(&mut reader)?,
+                    position: read_vec3(&mut reader)?,
+                    material_id: read_u32(&mut reader)?,
+                    object_id: read_u32(&mut reader)?,
+                };
+            }
+        }
+
+        // AOVs which weren't asked for this time are simply dropped
+        if aovs {
+            state.aovs = Some(aov_film);
+        }
+    }
+
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    fn temp_path(name: &str) -> String {
+        let file = format!("checkpoint-{}-{}.ckpt", name, std::process::id());
+        std::env::temp_dir().join(file).to_str().unwrap().to_string()
+    }
+
+    // Saves a small render and returns the bytes of the checkpoint
+    fn checkpoint_data(name: &str, filter: Filter) -> (String, Vec<u8>) {
+        let path = temp_path(name);
+        save(&path, &RenderState::new(40, 30, filter, false)).unwrap();
+        let data = fs::read(&path).unwrap();
+        (path, data)
+    }
+
+    #[test]
+    fn save_then_load_restores_state() {
+        let (width, height) = (70, 40);
+        let filter = Filter::new(FilterKind::Gaussian, 1.5);
+        let mut state = RenderState::new(width, height, filter, true);
+        for (i, tile) in state.tiles.iter_mut().enumerate() {
+            tile.samples = i as u32 + 1;
+        }
+        state.film.set(3, 5, Color::from(1., 2., 3.), 4., 5.);
+
+        let path = temp_path("restore");
+        let path = path.as_str();
+        save(path, &state).unwrap();
+        let (loaded, resized, refiltered) = (load(path, width, height, filter, true),
+                                             load(path, width + 1, height, filter, true),
+                                             load(path, width, height,
+                                                  Filter::new(FilterKind::Box, 0.5), true));
+        fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+        assert!(resized.is_err());
+        assert!(refiltered.is_err());
+
+        assert_eq!(loaded.filter, filter);
+        assert!(loaded.aovs.is_some());
+        assert_eq!(loaded.scene_seed, state.scene_seed);
+        assert_eq!(loaded.tiles.len(), state.tiles.len());
+        for (a, b) in loaded.tiles.iter().zip(&state.tiles) {
+            assert_eq!((a.samples, a.rng_state), (b.samples, b.rng_state));
+        }
+        let sum = loaded.film.sum(3, 5);
+        assert_eq!((sum.x(), sum.y(), sum.z()), (1., 2., 3.));
+        assert_eq!(loaded.film.weight(3, 5), 4.);
+        assert_eq!(loaded.film.abs_weight(3, 5), 5.);
+    }
+
+    #[test]
+    fn truncated_checkpoint_is_rejected() {
+        let filter = Filter::new(FilterKind::Box, 0.5);
+        let (path, data) = checkpoint_data("truncated", filter);
+        for len in [0, 3, 20, data.len() / 2, data.len() - 1] {
+            fs::write(&path, &data[..len]).unwrap();
+            assert!(load(&path, 40, 30, filter, false).is_err(), "length {}", len);
+        }
+        fs::write(&path, &data).unwrap();
+        let whole = load(&path, 40, 30, filter, false);
+        fs::remove_file(&path).unwrap();
+        assert!(whole.is_ok());
+    }
+
+    #[test]
+    fn other_version_is_rejected() {
+        let filter = Filter::new(FilterKind::Box, 0.5);
+        let (path, mut data) = checkpoint_data("version", filter);
+        data[4..8].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        let loaded = load(&path, 40, 30, filter, false);
+        fs::remove_file(&path).unwrap();
+        let error = loaded.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unsupported checkpoint version");
+    }
+}
@@ -1,36 +1,147 @@
-use std::fs::File;
-use std::io::{Write, Error};
-
 use crate::vec3::Color;
 
 #[derive(Debug)]
-struct RGB {
-    r: u32,
-    g: u32,
-    b: u32,
+struct Rgb {
+    r: u8,
+    g: u8,
+    b: u8,
 }
 
-pub fn format_color(color: &Color, samples: u32) -> String {
-    let converted = generate_color(color, samples);
-    format!("{} {} {}\n", converted.r, converted.g, converted.b)
+/// Operators for compressing linear radiance into the displayable range
+#[derive(Debug, Clone, Copy)]
+pub enum ToneMap {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard {white: f32},
+    Aces,
+    Hable,
+}
+
+impl ToneMap {
+    fn apply(&self, x: f32) -> f32 {
+        match self {
+            ToneMap::Clamp => x,
+            ToneMap::Reinhard => x / (1. + x),
+            ToneMap::ExtendedReinhard {white} => x * (1. + x / (white * white)) / (1. + x),
+            // Narkowicz's fit of the ACES filmic curve
+            ToneMap::Aces => {
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            },
+            // Hable's Uncharted 2 curve, normalized so the linear white point maps to 1
+            ToneMap::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.;
+                const WHITE: f32 = 11.2;
+                fn curve(x: f32) -> f32 {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+                }
+                curve(EXPOSURE_BIAS * x) / curve(WHITE)
+            },
+        }
+    }
+}
+
+/// Color spaces the final image can be encoded in. Rendering itself always happens
+/// with linear sRGB (Rec. 709) primaries.
+#[derive(Debug, Clone, Copy)]
+pub enum Gamut {
+    Srgb,
+    DisplayP3,
+    Rec2020,
 }
 
-pub fn write_color(file: &mut File, color: &Color, samples: u32) -> Result<(), Error> {
-    let converted = generate_color(color, samples);
-    write!(file, "{} {} {}", converted.r, converted.g, converted.b)?;
-    Ok(())
+impl Gamut {
+    // Converts linear sRGB into linear values relative to this gamut's primaries
+    fn convert(&self, c: &Color) -> Color {
+        let m = match self {
+            Gamut::Srgb => return *c,
+            Gamut::DisplayP3 => [[0.822_462, 0.177_538, 0.000_000],
+                                 [0.033_194, 0.966_806, 0.000_000],
+                                 [0.017_083, 0.072_397, 0.910_520]],
+            Gamut::Rec2020 => [[0.627_404, 0.329_283, 0.043_313],
+                               [0.069_097, 0.919_540, 0.011_362],
+                               [0.016_391, 0.088_013, 0.895_595]],
+        };
+        Color::from(m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+                    m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+                    m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z())
+    }
+
+    // Applies the transfer function of this color space to a linear value
+    fn encode(&self, x: f32) -> f32 {
+        match self {
+            Gamut::Srgb | Gamut::DisplayP3 => linear_to_srgb(x),
+            Gamut::Rec2020 => {
+                if x < 0.018_053_97 {
+                    4.5 * x
+                } else {
+                    1.099_296_8 * x.powf(0.45) - 0.099_296_8
+                }
+            },
+        }
+    }
+
+    /// Coding-independent code points (ITU-T H.273) identifying the primaries,
+    /// transfer function, matrix coefficients and range of this color space
+    pub fn cicp(&self) -> [u8; 4] {
+        match self {
+            Gamut::Srgb => [1, 13, 0, 1],
+            Gamut::DisplayP3 => [12, 13, 0, 1],
+            Gamut::Rec2020 => [9, 14, 0, 1],
+        }
+    }
 }
 
-fn generate_color(color: &Color, samples: u32) -> RGB {
-    let scale = 1. / samples as f32;
-    let r = (color.x() * scale).sqrt();
-    let g = (color.y() * scale).sqrt();
-    let b = (color.z() * scale).sqrt();
+/// Piecewise sRGB transfer function
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
 
-    RGB {
-        r: ((256. * r.clamp(0., 0.999)).floor()) as u32,
-        g: ((256. * g.clamp(0., 0.999)).floor()) as u32,
-        b: ((256. * b.clamp(0., 0.999)).floor()) as u32,
+/// Inverse of the sRGB transfer function, used to linearize 8-bit color data
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
     }
 }
 
+/// Settings controlling how linear film values are turned into display values
+#[derive(Debug, Clone, Copy)]
+pub struct Display {
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    pub gamut: Gamut,
+}
+
+pub fn format_color(color: &Color, display: &Display) -> String {
+    let converted = generate_color(color, display);
+    format!("{} {} {}\n", converted.r, converted.g, converted.b)
+}
+
+pub fn color_bytes(color: &Color, display: &Display) -> [u8; 3] {
+    let converted = generate_color(color, display);
+    [converted.r, converted.g, converted.b]
+}
+
+fn generate_color(color: &Color, display: &Display) -> Rgb {
+    // Exposure is given in stops
+    let scale = 2f32.powf(display.exposure);
+    let mapped = Color::from(display.tone_map.apply((color.x() * scale).max(0.)),
+                             display.tone_map.apply((color.y() * scale).max(0.)),
+                             display.tone_map.apply((color.z() * scale).max(0.)));
+    let converted = display.gamut.convert(&mapped);
+    let r = display.gamut.encode(converted.x().clamp(0., 1.));
+    let g = display.gamut.encode(converted.y().clamp(0., 1.));
+    let b = display.gamut.encode(converted.z().clamp(0., 1.));
+
+    Rgb {
+        r: ((256. * r.clamp(0., 0.999)).floor()) as u8,
+        g: ((256. * g.clamp(0., 0.999)).floor()) as u8,
+        b: ((256. * b.clamp(0., 0.999)).floor()) as u8,
+    }
+}
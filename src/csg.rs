@@ -0,0 +1,201 @@
+use crate::ray::Ray;
+use crate::hittable::{HitRecord, Hittable};
+use crate::aabb::Aabb;
+
+/// Stretch of a ray spent inside a solid, from where it enters the surface to where it
+/// leaves again
+pub struct Span<'a> {
+    pub enter: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
+}
+
+/// Closed shape with a well defined inside, which can take part in constructive solid
+/// geometry
+pub trait Solid: Hittable {
+    // Every span along the whole line of the ray, including behind its origin, in order
+    // and without overlaps
+    fn spans(&self, ray: &Ray) -> Vec<Span<'_>>;
+}
+
+/// How a `Csg` combines its operands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOp {
+    /// Inside either operand
+    Union,
+    /// Inside both operands
+    Intersection,
+    /// Inside the first operand but not the second
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Solid built by combining two others. Since the result is a solid too, these nest to
+/// build up more complex shapes.
+///
+/// Normals always point out of the combined solid, so surfaces carved out by a
+/// difference face into the hollow. Every surface keeps the material and UVs of the
+/// operand it came from, which means carved surfaces show the second operand's material.
+pub struct Csg<A: Solid, B: Solid> {
+    op: CsgOp,
+    a: A,
+    b: B,
+}
+
+impl<A: Solid, B: Solid> Csg<A, B> {
+    pub fn new(op: CsgOp, a: A, b: B) -> Self {
+        Csg {op, a, b}
+    }
+
+    pub fn union(a: A, b: B) -> Self {
+        Self::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: A, b: B) -> Self {
+        Self::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: A, b: B) -> Self {
+        Self::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl<A: Solid, B: Solid> Solid for Csg<A, B> {
+    fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+        // Every boundary along the ray, marked with whether it belongs to the first
+        // operand. Crossing one toggles whether the ray is inside that operand.
+        let mut boundaries = Vec::new();
+        for (from_a, spans) in [(true, self.a.spans(ray)), (false, self.b.spans(ray))] {
+            for span in spans {
+                boundaries.push((from_a, span.enter));
+                boundaries.push((from_a, span.exit));
+            }
+        }
+        boundaries.sort_by(|x, y| x.1.t.total_cmp(&y.1.t));
+
+        let (mut in_a, mut in_b, mut inside) = (false, false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for (from_a, mut record) in boundaries {
+            if from_a {
+                in_a = !in_a;
+            } else {
+                in_b = !in_b;
+            }
+            if self.op.contains(in_a, in_b) == inside {
+                continue;
+            }
+            inside = !inside;
+
+            // The normal already faces the ray, so only which side of the combined
+            // surface the ray is on can change
+            record.front_face = inside;
+            if inside {
+                enter = Some(record);
+            } else if let Some(enter) = enter.take() {
+                spans.push(Span {enter, exit: record});
+            }
+        }
+
+        spans
+    }
+}
+
+impl<A: Solid, B: Solid> Hittable for Csg<A, B> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if let Some(aabb) = self.bounding_box() {
+            if !aabb.hit(ray, t_min, t_max) {
+                return None;
+            }
+        }
+
+        self.spans(ray).into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|record| record.t >= t_min && record.t <= t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match self.op {
+            CsgOp::Union => Some(self.a.bounding_box()?.merge(&self.b.bounding_box()?)),
+            CsgOp::Intersection => self.a.bounding_box().or_else(|| self.b.bounding_box()),
+            CsgOp::Difference => self.a.bounding_box(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::vec3::{Color, Vec3};
+
+    fn sphere(x: f32, radius: f32) -> Sphere<Lambertian> {
+        Sphere::from(Vec3::from(x, 0., 0.), radius,
+                     Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))))
+    }
+
+    // Where each span along the x axis starts and ends, starting from x = -5
+    fn span_ends<S: Solid>(solid: &S) -> Vec<(f32, f32)> {
+        let ray = Ray::from(Vec3::from(-5., 0., 0.), Vec3::from(1., 0., 0.));
+        solid.spans(&ray).iter().map(|span| (span.enter.t, span.exit.t)).collect()
+    }
+
+    fn assert_spans(found: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (a, b) in found.iter().zip(expected) {
+            assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4, "{:?}", found);
+        }
+    }
+
+    #[test]
+    fn union_merges_overlapping_spans() {
+        // Overlapping spheres cover 4 to 6 and 5 to 7 along the ray
+        assert_spans(span_ends(&Csg::union(sphere(0., 1.), sphere(1., 1.))), &[(4., 7.)]);
+        assert_spans(span_ends(&Csg::union(sphere(3., 1.), sphere(0., 1.))),
+                     &[(4., 6.), (7., 9.)]);
+        // Nested unions join everything which touches
+        let chain = Csg::union(Csg::union(sphere(0., 1.), sphere(1.5, 1.)), sphere(3., 1.));
+        assert_spans(span_ends(&chain), &[(4., 9.)]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        assert_spans(span_ends(&Csg::intersection(sphere(0., 1.), sphere(1., 1.))),
+                     &[(5., 6.)]);
+        assert_spans(span_ends(&Csg::intersection(sphere(0., 1.), sphere(3., 1.))), &[]);
+        assert_spans(span_ends(&Csg::intersection(sphere(0., 2.), sphere(0., 1.))),
+                     &[(4., 6.)]);
+    }
+
+    #[test]
+    fn difference_carves_out_the_second() {
+        assert_spans(span_ends(&Csg::difference(sphere(0., 1.), sphere(1., 1.))),
+                     &[(4., 5.)]);
+        assert_spans(span_ends(&Csg::difference(sphere(1., 1.), sphere(0., 1.))),
+                     &[(6., 7.)]);
+
+        // A hollow shell, whose inner surface faces into the hollow
+        let shell = Csg::difference(sphere(0., 2.), sphere(0., 1.));
+        assert_spans(span_ends(&shell), &[(3., 4.), (6., 7.)]);
+        let ray = Ray::from(Vec3::from(-5., 0., 0.), Vec3::from(1., 0., 0.));
+        let spans = shell.spans(&ray);
+        let exit = &spans[0].exit;
+        assert!(spans[0].enter.front_face && !exit.front_face);
+        assert!(exit.normal.x() < -0.99, "normal should face the ray");
+        let inner = shell.hit(&ray, 3.5, f32::INFINITY).unwrap();
+        assert!((inner.t - 4.).abs() < 1e-4);
+        // Carving away everything leaves nothing
+        assert_spans(span_ends(&Csg::difference(sphere(0., 1.), sphere(0., 2.))), &[]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
+
+/// Axis-aligned box spanning two opposite corners. Each face maps the whole texture,
+/// with UVs running along the face's two axes in x, y, z order.
+pub struct Cuboid<T: Material> {
+    min: Point3,
+    max: Point3,
+    material: Arc<T>,
+}
+
+impl<T: Material> Cuboid<T> {
+    pub fn from(a: Point3, b: Point3, material: Arc<T>) -> Self {
+        Cuboid {
+            min: Vec3::from(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Vec3::from(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+            material,
+        }
+    }
+
+    // Where the ray's line enters and leaves the box, each as the distance along the
+    // ray and the outward normal of the face crossed there
+    fn slabs(&self, ray: &Ray) -> Option<((f32, Vec3), (f32, Vec3))> {
+        let (origin, direction) = (components(ray.origin()), components(ray.direction()));
+        let (min, max) = (components(&self.min), components(&self.max));
+        let (mut enter, mut exit) = ((f32::NEG_INFINITY, 0, 0.), (f32::INFINITY, 0, 0.));
+
+        for axis in 0..3 {
+            let (o, d) = (origin[axis], direction[axis]);
+            if d == 0. {
+                // Parallel to this pair of faces, so either always between them or never
+                if o < min[axis] || o > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let (t0, t1) = ((min[axis] - o) / d, (max[axis] - o) / d);
+            let (near, far) = if d > 0. {(t0, t1)} else {(t1, t0)};
+            if near > enter.0 {
+                enter = (near, axis, -d.signum());
+            }
+            if far < exit.0 {
+                exit = (far, axis, d.signum());
+            }
+        }
+        if enter.0 > exit.0 {
+            return None;
+        }
+
+        let face = |(t, axis, sign): (f32, usize, f32)| {
+            let mut normal = [0.; 3];
+            normal[axis] = sign;
+            (t, Vec3::from(normal[0], normal[1], normal[2]))
+        };
+        Some((face(enter), face(exit)))
+    }
+
+    fn record(&self, ray: &Ray, (t, normal): (f32, Vec3)) -> HitRecord<'_> {
+        let p = ray.at(t);
+        let fraction = |p: f32, min: f32, max: f32| (p - min) / (max - min);
+        let x = fraction(p.x(), self.min.x(), self.max.x());
+        let y = fraction(p.y(), self.min.y(), self.max.y());
+        let z = fraction(p.z(), self.min.z(), self.max.z());
+        let uv = if normal.x() != 0. {
+            (y, z)
+        } else if normal.y() != 0. {
+            (x, z)
+        } else {
+            (x, y)
+        };
+
+        HitRecord::from(t, ray, normal, uv, self.material.clone())
+    }
+}
+
+fn components(v: &Vec3) -> [f32; 3] {
+    [v.x(), v.y(), v.z()]
+}
+
+impl<T: Material + Sync + Send> Hittable for Cuboid<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (enter, exit) = self.slabs(ray)?;
+        let crossing = [enter, exit].into_iter().find(|(t, _)| *t >= t_min && *t <= t_max)?;
+
+        Some(self.record(ray, crossing))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from(self.min, self.max))
+    }
+}
+
+impl<T: Material + Sync + Send> Solid for Cuboid<T> {
+    fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+        match self.slabs(ray) {
+            Some((enter, exit)) => {
+                vec![Span {enter: self.record(ray, enter), exit: self.record(ray, exit)}]
+            },
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::f32::consts::SQRT_2;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, cross, dot, tangents, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+
+// Curves are split until their pieces are flat to within this fraction of their width
+const FLATNESS: f32 = 0.05;
+
+// Most times a curve is halved while looking for a hit
+const MAX_DEPTH: u32 = 10;
+
+/// How the width of a curve is filled in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveShape {
+    /// Flat strip which always faces the ray, cheap and suited to distant or very thin
+    /// strands such as fur and grass
+    Ribbon,
+    /// Round tube, shaded as if seen from the side, for hair and fibers seen close up
+    Cylinder,
+}
+
+/// Cubic Bézier curve through `points[0]` and `points[3]`, pulled towards the two
+/// between, which narrows linearly from `widths.0` at its start to `widths.1` at its
+/// end. U runs along the curve and v across it.
+pub struct Curve<T: Material> {
+    points: [Point3; 4],
+    widths: (f32, f32),
+    shape: CurveShape,
+    material: Arc<T>,
+}
+
+impl<T: Material> Curve<T> {
+    pub fn from(points: [Point3; 4], widths: (f32, f32), shape: CurveShape,
+                material: Arc<T>) -> Self {
+        Curve {points, widths, shape, material}
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Curve<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let found = hit_segment(ray, &self.points, self.widths, self.shape, t_min, t_max)?;
+        Some(found.record(ray, (0., 1.), self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(segment_bounds(&self.points, self.widths))
+    }
+}
+
+/// Many strands sharing one buffer of control points, each made of cubic Bézier
+/// segments joined end to end. A strand of n segments takes 3n + 1 points, every
+/// segment starting on the last point of the one before, and each point has a width
+/// which the segments blend between.
+///
+/// Segments are kept in a bounding volume hierarchy, so millions of strands stay fast
+/// to intersect. U runs from the root of each strand to its tip and v across it.
+pub struct Curves<T: Material> {
+    points: Vec<Point3>,
+    widths: Vec<f32>,
+    segments: Vec<Segment>,
+    bvh: Bvh,
+    bounds: Option<Aabb>,
+    shape: CurveShape,
+    material: Arc<T>,
+}
+
+// Segment of a strand starting at `first` in the points, and which of its strand's
+// `count` segments it is
+struct Segment {
+    first: u32,
+    index: u32,
+    count: u32,
+}
+
+impl<T: Material> Curves<T> {
+    /// Strands from `points` and their `widths`, with `strands` giving the number of
+    /// points in each strand in turn
+    pub fn from(points: Vec<Point3>, widths: Vec<f32>, strands: &[usize], shape: CurveShape,
+                material: Arc<T>) -> Result<Self, Error> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        if widths.len() != points.len() {
+            return Err(invalid(format!("{} widths given for {} points", widths.len(),
+                                       points.len())));
+        }
+        if strands.iter().sum::<usize>() != points.len() {
+            return Err(invalid(format!("strands take {} points but {} were given",
+                                       strands.iter().sum::<usize>(), points.len())));
+        }
+
+        let mut segments = Vec::new();
+        let mut first = 0;
+        for (n, &length) in strands.iter().enumerate() {
+            if length < 4 || (length - 1) % 3 != 0 {
+                return Err(invalid(format!("strand {} has {} points, which doesn't make \
+                                            whole cubic segments", n, length)));
+            }
+            let count = (length - 1) / 3;
+            for index in 0..count {
+                segments.push(Segment {
+                    first: (first + 3 * index) as u32,
+                    index: index as u32,
+                    count: count as u32,
+                });
+            }
+            first += length;
+        }
+
+        let boxes: Vec<Aabb> = segments.iter().map(|segment| {
+            let (points, widths) = Self::segment(&points, &widths, segment);
+            segment_bounds(&points, widths)
+        }).collect();
+        let bounds = boxes.iter().copied().reduce(|a, b| a.merge(&b));
+
+        Ok(Curves {points, widths, bvh: Bvh::build(&boxes), segments, bounds, shape, material})
+    }
+
+    fn segment(points: &[Point3], widths: &[f32],
+               segment: &Segment) -> ([Point3; 4], (f32, f32)) {
+        let first = segment.first as usize;
+        ([points[first], points[first + 1], points[first + 2], points[first + 3]],
+         (widths[first], widths[first + 3]))
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Curves<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (segment, found) = self.bvh.hit(ray, t_min, t_max, |i, t_max| {
+            let segment = &self.segments[i];
+            let (points, widths) = Self::segment(&self.points, &self.widths, segment);
+            let found = hit_segment(ray, &points, widths, self.shape, t_min, t_max)?;
+            Some((found.t, (segment, found)))
+        })?;
+
+        let (index, count) = (segment.index as f32, segment.count as f32);
+        Some(found.record(ray, (index / count, (index + 1.) / count), self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+// Control points stay within a curve's convex hull, so their box padded by the widest
+// point holds the whole curve
+fn segment_bounds(points: &[Point3; 4], widths: (f32, f32)) -> Aabb {
+    let aabb = Aabb::around(points);
+    let pad = Vec3::from(1., 1., 1.) * (widths.0.max(widths.1) / 2.);
+    Aabb::from(aabb.min() - pad, aabb.max() + pad)
+}
+
+struct CurveHit {
+    t: f32,
+    // Position along the curve and across it, from 0 to 1
+    u: f32,
+    v: f32,
+    normal: Vec3,
+}
+
+impl CurveHit {
+    // Record for a hit on a segment covering `span` of its strand's length
+    fn record<'a>(&self, ray: &Ray, span: (f32, f32),
+                  material: Arc<dyn Material + 'a>) -> HitRecord<'a> {
+        let u = span.0 + self.u * (span.1 - span.0);
+        HitRecord::from(self.t, ray, self.normal, (u, self.v), material)
+    }
+}
+
+// Nearest hit on a single segment, found by looking at it from along the ray, so the
+// ray becomes a point at the origin, and halving the curve until each piece is nearly
+// straight and can be tested as a thick line
+fn hit_segment(ray: &Ray, points: &[Point3; 4], widths: (f32, f32), shape: CurveShape,
+               t_min: f32, t_max: f32) -> Option<CurveHit> {
+    let length = ray.direction().length();
+    let z = *ray.direction() / length;
+    let (x, y) = tangents(&z);
+    let local = points.map(|p| {
+        let d = p - *ray.origin();
+        Vec3::from(dot(&d, &x), dot(&d, &y), dot(&d, &z))
+    });
+
+    // Halve until the pieces bend less than a fraction of the curve's width
+    let bend = (0..2).map(|i| {
+        let d = local[i] - 2. * local[i + 1] + local[i + 2];
+        d.x().abs().max(d.y().abs()).max(d.z().abs())
+    }).fold(0., f32::max);
+    let tolerance = FLATNESS * widths.0.max(widths.1);
+    let depth = if bend > 0. && tolerance > 0. {
+        let halvings = (SQRT_2 * 6. * bend / (8. * tolerance)).log2() / 2.;
+        halvings.round().clamp(0., MAX_DEPTH as f32) as u32
+    } else {
+        0
+    };
+
+    let search = Search {widths, z_min: t_min * length, z_max: t_max * length};
+    let (z_hit, u) = search.recurse(&local, (0., 1.), depth, search.z_max)?;
+    let mut t = z_hit / length;
+
+    // Shade in world space. The normal faces back along the ray, turned about the curve
+    // for tubes by how far off its center the ray passes.
+    let tangent = bezier_derivative(points, u);
+    let tangent = if tangent.near_zero() {points[3] - points[0]} else {tangent};
+    let tangent = unit_vector(tangent);
+    let facing = -z - dot(&-z, &tangent) * tangent;
+    if facing.near_zero() {
+        return None;
+    }
+    let facing = unit_vector(facing);
+    let side = cross(&tangent, &facing);
+    let half_width = (widths.0 + u * (widths.1 - widths.0)) / 2.;
+    let offset = (dot(&(ray.at(t) - bezier(points, u)), &side) / half_width).clamp(-1., 1.);
+    let normal = match shape {
+        CurveShape::Ribbon => facing,
+        CurveShape::Cylinder => {
+            // Move the hit from the middle of the tube out to its wall, so rays leaving
+            // it don't start inside. Rays starting inside the tube hit its far wall.
+            let depth = (1. - offset * offset).sqrt();
+            let near = t - depth * half_width / length;
+            let (t_wall, facing) = if near >= t_min {
+                (near, facing)
+            } else {
+                (t + depth * half_width / length, -facing)
+            };
+            if t_wall > t_max {
+                return None;
+            }
+            t = t_wall;
+            depth * facing + offset * side
+        },
+    };
+
+    Some(CurveHit {t, u, v: (offset + 1.) / 2., normal})
+}
+
+struct Search {
+    widths: (f32, f32),
+    z_min: f32,
+    z_max: f32,
+}
+
+impl Search {
+    // Depth and position along the whole curve of the nearest hit on the piece with
+    // control points `cp`, which covers `span` of the curve
+    fn recurse(&self, cp: &[Vec3; 4], span: (f32, f32), depth: u32,
+               z_max: f32) -> Option<(f32, f32)> {
+        let width = |u: f32| self.widths.0 + u * (self.widths.1 - self.widths.0);
+        let half = width(span.0).max(width(span.1)) / 2.;
+
+        // The ray, at the origin, has to fall within the piece's padded box
+        let (lo, hi) = cp[1..].iter().fold((cp[0], cp[0]), |(lo, hi), p| {
+            (Vec3::from(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z())),
+             Vec3::from(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z())))
+        });
+        if lo.x() - half > 0. || hi.x() + half < 0. || lo.y() - half > 0.
+            || hi.y() + half < 0. || lo.z() - half > z_max || hi.z() + half < self.z_min {
+            return None;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(cp);
+            let mid = (span.0 + span.1) / 2.;
+            let near = self.recurse(&first, (span.0, mid), depth - 1, z_max);
+            let far = self.recurse(&second, (mid, span.1), depth - 1,
+                                   near.map_or(z_max, |(z, _)| z));
+            return far.or(near);
+        }
+
+        // The origin has to lie between the lines across either end of the piece
+        let start = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        let end = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if start < 0. || end < 0. {
+            return None;
+        }
+
+        // Closest point to the origin along the piece's chord
+        let chord = cp[3] - cp[0];
+        let denominator = chord.x() * chord.x() + chord.y() * chord.y();
+        if denominator == 0. {
+            return None;
+        }
+        let w = ((-cp[0].x() * chord.x() - cp[0].y() * chord.y()) / denominator).clamp(0., 1.);
+        let u = span.0 + w * (span.1 - span.0);
+        let p = bezier(cp, w);
+        let radius = width(u) / 2.;
+        if p.x() * p.x() + p.y() * p.y() > radius * radius || p.z() < self.z_min
+            || p.z() > z_max {
+            return None;
+        }
+
+        Some((p.z(), u))
+    }
+}
+
+fn bezier(cp: &[Vec3; 4], u: f32) -> Vec3 {
+    let s = 1. - u;
+    s * s * s * cp[0] + 3. * s * s * u * cp[1] + 3. * s * u * u * cp[2] + u * u * u * cp[3]
+}
+
+fn bezier_derivative(cp: &[Vec3; 4], u: f32) -> Vec3 {
+    let s = 1. - u;
+    3. * (s * s * (cp[1] - cp[0]) + 2. * s * u * (cp[2] - cp[1]) + u * u * (cp[3] - cp[2]))
+}
+
+// Halves of a curve, by de Casteljau's algorithm
+fn split(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| (a + b) / 2.;
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let f = mid(d, e);
+    ([cp[0], a, d, f], [f, e, c, cp[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn material() -> Arc<Lambertian> {
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5)))
+    }
+
+    fn down_from(x: f32, y: f32) -> Ray {
+        Ray::from(Vec3::from(x, y, 5.), Vec3::from(0., 0., -1.))
+    }
+
+    #[test]
+    fn curve_is_hit_at_the_right_parameter() {
+        // An arch, whose middle at u = 0.5 lies at (1.5, 1.5)
+        let points = [Vec3::new(), Vec3::from(1., 2., 0.), Vec3::from(2., 2., 0.),
+                      Vec3::from(3., 0., 0.)];
+        let ribbon = Curve::from(points, (0.2, 0.2), CurveShape::Ribbon, material());
+        let hit = ribbon.hit(&down_from(1.5, 1.5), 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 5.).abs() < 1e-3, "{}", hit.t);
+        assert!((hit.u - 0.5).abs() < 1e-3, "{}", hit.u);
+        assert!((hit.v - 0.5).abs() < 1e-2, "{}", hit.v);
+        assert!((hit.normal.z() - 1.).abs() < 1e-3);
+        assert!(ribbon.hit(&down_from(1.5, 1.7), 0.001, f32::INFINITY).is_none());
+
+        // Tubes are hit on their wall, half their width nearer
+        let tube = Curve::from(points, (0.2, 0.2), CurveShape::Cylinder, material());
+        let hit = tube.hit(&down_from(1.5, 1.5), 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.9).abs() < 1e-3, "{}", hit.t);
+    }
+
+    #[test]
+    fn strands_are_hit_through_the_bvh() {
+        // A straight strand of two segments along x, then one of a single segment
+        // further along y, both evenly spaced so u follows x
+        let mut points: Vec<Point3> = (0..7).map(|i| Vec3::from(i as f32, 0., 0.)).collect();
+        points.extend((0..4).map(|i| Vec3::from(i as f32, 5., 0.)));
+        let widths = vec![0.2; points.len()];
+        let curves = Curves::from(points, widths, &[7, 4], CurveShape::Ribbon, material())
+                     .unwrap();
+
+        let hit = curves.hit(&down_from(4.5, 0.), 0.001, f32::INFINITY).unwrap();
+        assert!((hit.u - 0.75).abs() < 1e-3, "{}", hit.u);
+        let hit = curves.hit(&down_from(0.6, 5.), 0.001, f32::INFINITY).unwrap();
+        assert!((hit.u - 0.2).abs() < 1e-3, "{}", hit.u);
+        assert!(curves.hit(&down_from(4.5, 5.), 0.001, f32::INFINITY).is_none());
+
+        assert!(Curves::from(vec![Vec3::new(); 5], vec![0.1; 5], &[5], CurveShape::Ribbon,
+                             material()).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use rayon::prelude::*;
+
+use crate::vec3::{Color, Vec3, dot};
+use crate::film::Film;
+use crate::aov::AovFilm;
+
+const ITERATIONS: u32 = 5;
+const KERNEL: [f32; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+// Falloff of each edge stopping function, the color term is halved every iteration
+const SIGMA_COLOR: f32 = 0.25;
+const SIGMA_NORMAL: f32 = 0.3;
+const SIGMA_ALBEDO: f32 = 0.1;
+
+// Offset keeping dark albedos from blowing up demodulated color
+const EPSILON: f32 = 1e-3;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) guided by the first hit
+/// normal and albedo. Color is divided by albedo before filtering and multiplied back
+/// afterwards, so texture and material detail survive while lighting noise is smoothed.
+pub fn denoise(film: &Film, aovs: &AovFilm) -> Film {
+    let (width, height) = (film.width(), film.height());
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    // Background pixels have no albedo, so leave their color as is
+    let mut albedo = Vec::with_capacity((width * height) as usize);
+    let mut normal = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let geometry = aovs.geometry(x, y);
+            albedo.push(if geometry.hits > 0 {geometry.albedo()} else {Color::from(1., 1., 1.)});
+            normal.push(geometry.normal());
+        }
+    }
+
+    let mut color: Vec<Color> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+                                           .map(|(x, y)| demodulate(film.color(x, y),
+                                                                    albedo[index(x, y)]))
+                                           .collect();
+
+    let mut sigma_color = SIGMA_COLOR;
+    for i in 0..ITERATIONS {
+        let step = 1 << i;
+        color = (0..height).into_par_iter().flat_map_iter(|y| {
+            let (color, albedo, normal) = (&color, &albedo, &normal);
+            (0..width).map(move |x| {
+                let p = index(x, y);
+                let mut sum = Color::new();
+                let mut total = 0.;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = y as i64 + (j as i64 - 2) * step;
+                    if qy < 0 || qy >= height as i64 {
+                        continue;
+                    }
+                    for (k, kx) in KERNEL.iter().enumerate() {
+                        let qx = x as i64 + (k as i64 - 2) * step;
+                        if qx < 0 || qx >= width as i64 {
+                            continue;
+                        }
+
+                        let q = index(qx as u32, qy as u32);
+                        let weight = kx * ky
+                            * edge_weight(color[p] - color[q], sigma_color)
+                            * edge_weight(normal[p] - normal[q], SIGMA_NORMAL)
+                            * edge_weight(albedo[p] - albedo[q], SIGMA_ALBEDO);
+                        sum += weight * color[q];
+                        total += weight;
+                    }
+                }
+
+                sum / total
+            })
+        }).collect();
+        sigma_color /= 2.;
+    }
+
+    let mut denoised = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            denoised.set(x, y, remodulate(color[index(x, y)], albedo[index(x, y)]), 1., 1.);
+        }
+    }
+
+    denoised
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    Color::from(color.x() / (albedo.x() + EPSILON),
+                color.y() / (albedo.y() + EPSILON),
+                color.z() / (albedo.z() + EPSILON))
+}
+
+fn remodulate(color: Color, albedo: Color) -> Color {
+    color * (albedo + Color::from(EPSILON, EPSILON, EPSILON))
+}
+
+fn edge_weight(difference: Vec3, sigma: f32) -> f32 {
+    (-dot(&difference, &difference) / (sigma * sigma)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::FirstHit;
+    use crate::vec3::Point3;
+
+    const SIZE: u32 = 16;
+
+    // Film of the given colors, seeing a surface with the given normals and an albedo of
+    // one half everywhere
+    fn render(color: impl Fn(u32, u32) -> Color,
+              normal: impl Fn(u32, u32) -> Vec3) -> (Film, AovFilm) {
+        let mut film = Film::new(SIZE, SIZE);
+        let mut aovs = AovFilm::new(SIZE, SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                film.set(x, y, color(x, y), 1., 1.);
+                aovs.geometry_mut(x, y).add(&FirstHit {
+                    depth: 1.,
+                    normal: normal(x, y),
+                    albedo: Color::from(0.5, 0.5, 0.5),
+                    position: Point3::new(),
+                    material_id: 1,
+                    object_id: 1,
+                });
+            }
+        }
+        (film, aovs)
+    }
+
+    fn gray(c: Color) -> f32 {
+        (c.x() + c.y() + c.z()) / 3.
+    }
+
+    #[test]
+    fn constant_image_is_unchanged() {
+        let color = Color::from(0.2, 0.4, 0.1);
+        let (film, aovs) = render(|_, _| color, |_, _| Vec3::from(0., 0., 1.));
+        let denoised = denoise(&film, &aovs);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let c = denoised.color(x, y);
+                assert!((c - color).length() < 1e-5, "{} {}: {:?}", x, y, c);
+            }
+        }
+
+        // Pixels which saw only the background keep their color too
+        let denoised = denoise(&film, &AovFilm::new(SIZE, SIZE));
+        assert!((denoised.color(3, 7) - color).length() < 1e-5);
+    }
+
+    #[test]
+    fn noise_is_smoothed() {
+        let (film, aovs) = render(|x, y| {
+            let v = if (x + y) % 2 == 0 {0.26} else {0.24};
+            Color::from(v, v, v)
+        }, |_, _| Vec3::from(0., 0., 1.));
+        let denoised = denoise(&film, &aovs);
+        for (x, y) in [(5, 5), (5, 6), (0, 0), (15, 8)] {
+            let c = gray(denoised.color(x, y));
+            assert!((c - 0.25).abs() < 0.002, "{} {}: {}", x, y, c);
+        }
+    }
+
+    #[test]
+    fn edges_between_normals_are_kept() {
+        // Two faces of a box meeting in the middle, lit a little differently
+        let color = |x: u32, _| if x < SIZE / 2 {Color::from(0.26, 0.26, 0.26)}
+                                else {Color::from(0.24, 0.24, 0.24)};
+        let corner = |x: u32, _| if x < SIZE / 2 {Vec3::from(0., 0., 1.)}
+                                 else {Vec3::from(1., 0., 0.)};
+        let (film, aovs) = render(color, corner);
+        let denoised = denoise(&film, &aovs);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let found = gray(denoised.color(x, y));
+                assert!((found - gray(color(x, y))).abs() < 1e-4, "{} {}: {}", x, y, found);
+            }
+        }
+
+        // The same step on a flat surface is soft enough to be blurred across
+        let (film, aovs) = render(color, |_, _| Vec3::from(0., 0., 1.));
+        let denoised = denoise(&film, &aovs);
+        let step = gray(denoised.color(SIZE / 2 - 1, 4)) - gray(denoised.color(SIZE / 2, 4));
+        assert!(step < 0.01, "{}", step);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::vec3::Color;
+use crate::color::{format_color, Display};
+
+// Smallest share of a pixel's total absolute weight its signed weight is taken to be when
+// averaging. With filters which have negative lobes the signed weight of a pixel with
+// plenty of samples comes to around half of its absolute weight for Lanczos and most of it
+// for Mitchell, but with few samples it can fall close to zero or below it. Dividing by it
+// then would blow the pixel up or flip its sign, so the division is limited instead.
+const MIN_WEIGHT_SHARE: f32 = 0.25;
+
+/// Floating point image which accumulates weighted radiance samples
+pub struct Film {
+    width: u32,
+    height: u32,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+    abs_weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = (width * height) as usize;
+        Film {
+            width,
+            height,
+            sums: vec![Color::new(); len],
+            weights: vec![0.; len],
+            abs_weights: vec![0.; len],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Pixels are stored in row order starting from the top of the image
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    // Adds an already weighted radiance sum along with its total weight and the total
+    // of the absolute values of its weights
+    pub fn accumulate(&mut self, x: u32, y: u32, sum: Color, weight: f32, abs_weight: f32) {
+        let idx = self.index(x, y);
+        self.sums[idx] += sum;
+        self.weights[idx] += weight;
+        self.abs_weights[idx] += abs_weight;
+    }
+
+    pub fn sum(&self, x: u32, y: u32) -> Color {
+        self.sums[self.index(x, y)]
+    }
+
+    pub fn weight(&self, x: u32, y: u32) -> f32 {
+        self.weights[self.index(x, y)]
+    }
+
+    pub fn abs_weight(&self, x: u32, y: u32) -> f32 {
+        self.abs_weights[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, sum: Color, weight: f32, abs_weight: f32) {
+        let idx = self.index(x, y);
+        self.sums[idx] = sum;
+        self.weights[idx] = weight;
+        self.abs_weights[idx] = abs_weight;
+    }
+
+    // Average radiance of a pixel, black if it has yet to receive any samples. The weight
+    // divided by is at least `MIN_WEIGHT_SHARE` of the absolute weight, and any negative
+    // components left by negative filter lobes are clamped to zero.
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        let idx = self.index(x, y);
+        let weight = self.weights[idx].max(MIN_WEIGHT_SHARE * self.abs_weights[idx]);
+        if weight > 0. {
+            let c = self.sums[idx] / weight;
+            Color::from(c.x().max(0.), c.y().max(0.), c.z().max(0.))
+        } else {
+            Color::new()
+        }
+    }
+
+    pub fn to_ppm(&self, display: &Display) -> String {
+        let header = format!("P3\n{} {}\n255\n", // 255 denotes maximum color value
+                             self.width, self.height);
+        let data: String = (0..self.height).flat_map(|y| {
+            (0..self.width).map(move |x| format_color(&self.color(x, y), display))
+        }).collect();
+
+        format!("{}{}", header, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(c: Color) -> [f32; 3] {
+        [c.x(), c.y(), c.z()]
+    }
+
+    #[test]
+    fn color_is_the_weighted_average() {
+        let mut film = Film::new(2, 2);
+        assert_eq!(components(film.color(1, 1)), [0., 0., 0.]);
+
+        film.accumulate(1, 0, 0.5 * Color::from(1., 2., 4.), 0.5, 0.5);
+        film.accumulate(1, 0, 1.5 * Color::from(3., 2., 0.), 1.5, 1.5);
+        assert_eq!(components(film.color(1, 0)), [2.5, 2., 1.]);
+        assert_eq!((film.weight(1, 0), film.abs_weight(1, 0)), (2., 2.));
+        assert_eq!(components(film.color(0, 0)), [0., 0., 0.]);
+    }
+
+    #[test]
+    fn negative_lobes_are_limited() {
+        let mut film = Film::new(3, 1);
+        // A little signed weight left from a lot of absolute weight is divided by at least
+        // a quarter of the absolute weight
+        film.set(0, 0, Color::from(0.1, 0.2, 0.3), 0.05, 1.);
+        let c = components(film.color(0, 0));
+        assert!(c.iter().zip([0.4, 0.8, 1.2]).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", c);
+
+        // A negative signed weight doesn't flip the pixel's sign
+        film.set(1, 0, Color::from(0.5, 0.5, 0.5), -0.2, 1.);
+        assert_eq!(components(film.color(1, 0)), [2., 2., 2.]);
+
+        // Components pulled below zero by negative samples are clamped
+        film.set(2, 0, Color::from(-0.5, 1., 0.), 1., 1.5);
+        assert_eq!(components(film.color(2, 0)), [0., 1., 0.]);
+    }
+}
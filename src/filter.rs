@@ -0,0 +1,138 @@
+use std::f32::consts::PI;
+
+/// Shapes of pixel reconstruction filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    /// Radius in pixels used when none is given explicitly
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.,
+            FilterKind::Lanczos => 3.,
+        }
+    }
+}
+
+/// Separable filter used to weight each sample's contribution to the pixels around it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f32) -> Self {
+        Filter {kind, radius}
+    }
+
+    /// Weight of a sample offset by (dx, dy) pixels from a pixel center
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x > self.radius {
+            return 0.;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian => {
+                // Shifted so the filter falls to exactly zero at its radius
+                const ALPHA: f32 = 2.;
+                ((-ALPHA * x * x).exp() - (-ALPHA * self.radius * self.radius).exp()).max(0.)
+            },
+            FilterKind::Mitchell => {
+                // Mitchell-Netravali with B = C = 1/3, stretched over [-radius, radius]
+                const B: f32 = 1. / 3.;
+                const C: f32 = 1. / 3.;
+                let x = 2. * x / self.radius;
+                if x < 1. {
+                    ((12. - 9. * B - 6. * C) * x * x * x
+                     + (-18. + 12. * B + 6. * C) * x * x
+                     + (6. - 2. * B)) / 6.
+                } else {
+                    ((-B - 6. * C) * x * x * x
+                     + (6. * B + 30. * C) * x * x
+                     + (-12. * B - 48. * C) * x
+                     + (8. * B + 24. * C)) / 6.
+                }
+            },
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian,
+                                    FilterKind::Mitchell, FilterKind::Lanczos];
+
+    // Values of the filter along one axis at evenly spaced offsets covering its radius
+    fn profile(filter: &Filter, steps: usize) -> Vec<(f32, f32)> {
+        (0..=steps).map(|i| {
+            let x = filter.radius * (2. * i as f32 / steps as f32 - 1.);
+            (x, filter.evaluate(x, 0.))
+        }).collect()
+    }
+
+    #[test]
+    fn filters_peak_at_the_center_and_vanish_outside() {
+        for kind in KINDS {
+            let filter = Filter::new(kind, kind.default_radius());
+            let peak = filter.evaluate(0., 0.);
+            assert!(peak > 0., "{:?}", kind);
+            for (x, value) in profile(&filter, 200) {
+                assert!(value <= peak, "{:?} at {}", kind, x);
+                assert!((value - filter.evaluate(-x, 0.)).abs() < 1e-6, "{:?} at {}", kind, x);
+                let separable = filter.evaluate(x, 0.) * filter.evaluate(0., x) / peak;
+                assert!((filter.evaluate(x, x) - separable).abs() < 1e-6, "{:?} at {}", kind, x);
+            }
+            let r = filter.radius;
+            assert_eq!(filter.evaluate(r * 1.01, 0.), 0., "{:?}", kind);
+            assert_eq!(filter.evaluate(0., -r * 1.5), 0., "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn only_mitchell_and_lanczos_have_negative_lobes() {
+        for kind in KINDS {
+            // Lanczos only dips below zero past one pixel, where sinc first crosses zero
+            for radius in [kind.default_radius(), 1.5, 2.5] {
+                let filter = Filter::new(kind, radius);
+                let values = profile(&filter, 400);
+                let negative = values.iter().any(|&(_, value)| value < 0.);
+                assert_eq!(negative, matches!(kind, FilterKind::Mitchell | FilterKind::Lanczos),
+                           "{:?} with radius {}", kind, radius);
+
+                // However deep the lobes, the filter as a whole keeps a positive weight
+                let dx = 2. * radius / 400.;
+                let integral: f32 = values.iter().map(|&(_, value)| value * dx).sum();
+                let abs_integral: f32 = values.iter().map(|&(_, value)| value.abs() * dx).sum();
+                assert!(integral > 0.5 * abs_integral, "{:?} with radius {}", kind, radius);
+            }
+        }
+    }
+}
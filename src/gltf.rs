@@ -0,0 +1,866 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::vec3::{Color, Point3, Vec3, cross, dot, unit_vector};
+use crate::json::Json;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::aabb::Aabb;
+use crate::mesh::{PolygonMesh, TriangleMesh};
+use crate::material::Pbr;
+use crate::image::Image;
+use crate::texture::{ImageTexture, Texture};
+use crate::animation::CameraPose;
+
+// Column-major 4x4 matrix, laid out as glTF stores them
+type Matrix = [f32; 16];
+
+const IDENTITY: Matrix = [1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.];
+
+// Most values an accessor without a buffer view may hold. Its values are all zeros, as
+// only suits the base of a sparse accessor, so unlike accessors with data its count isn't
+// bounded by the size of any buffer.
+const MAX_ZERO_VALUES: usize = 1 << 20;
+
+/// Camera placed in a glTF scene. Poses have no roll, since the renderer always keeps
+/// the camera upright.
+#[derive(Debug, Clone, Copy)]
+pub struct GltfCamera {
+    pub pose: CameraPose,
+    /// Width of the view in world units for orthographic cameras, None for perspective
+    pub orthographic_width: Option<f32>,
+}
+
+/// Scene read from a glTF file, with every mesh placed in world space
+pub struct GltfScene {
+    /// One triangle mesh for each material, gathering every primitive which uses it
+    pub world: HittableList,
+    pub cameras: Vec<GltfCamera>,
+}
+
+/// Reads a glTF 2.0 scene from a `.gltf` JSON file or a binary `.glb`, along with the
+/// buffers and images it refers to. Buffers and images may be embedded in a GLB, written
+/// inline as base64 data URIs or kept in files next to the scene.
+///
+/// Nodes place their meshes and cameras through their transforms and those of their
+/// parents. Triangle primitives are read with their normals, first set of texture
+/// coordinates and vertex colors, and their metallic-roughness materials become `Pbr`
+/// materials. Textures must be PNG images. Emission, skins, morph targets, animation and
+/// extensions are ignored, and files which require an extension are rejected.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<GltfScene, Error> {
+    let path = path.as_ref();
+    parse(&fs::read(path)?, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Reads a glTF scene from memory, looking for any external files relative to `base`
+pub fn parse(data: &[u8], base: &Path) -> Result<GltfScene, Error> {
+    let (json, binary) = if data.starts_with(b"glTF") {
+        split_glb(data)?
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("JSON is not UTF-8"))?;
+        (text, None)
+    };
+    let json = Json::parse(json).map_err(|e| invalid(&e.to_string()))?;
+
+    let version = json.get("asset").and_then(|asset| asset.get("version"))
+                      .and_then(Json::as_str).ok_or_else(|| invalid("missing asset version"))?;
+    if !version.starts_with("2.") {
+        return Err(invalid(&format!("unsupported version {}", version)));
+    }
+    if let Some(extension) = array(&json, "extensionsRequired").first() {
+        return Err(invalid(&format!("requires unsupported extension {}",
+                                    extension.as_str().unwrap_or("?"))));
+    }
+
+    let buffers = array(&json, "buffers").iter().enumerate().map(|(i, buffer)| {
+        let bytes = match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) => read_uri(uri, base)?,
+            None if i == 0 => binary.map(<[u8]>::to_vec)
+                                    .ok_or_else(|| invalid("buffer 0 has no data"))?,
+            None => return Err(invalid(&format!("buffer {} has no uri", i))),
+        };
+        let length = count(buffer, "byteLength")?.unwrap_or(0);
+        if bytes.len() < length {
+            return Err(invalid(&format!("buffer {} is shorter than its byteLength", i)));
+        }
+        Ok(bytes)
+    }).collect::<Result<Vec<_>, Error>>()?;
+
+    let mut document = Document {
+        json: &json,
+        buffers,
+        base,
+        textures: HashMap::new(),
+        groups: Vec::new(),
+        cameras: Vec::new(),
+    };
+    document.walk()?;
+    document.finish()
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid glTF file: {}", msg))
+}
+
+// JSON and binary chunks of a GLB container
+fn split_glb(data: &[u8]) -> Result<(&str, Option<&[u8]>), Error> {
+    let word = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("truncated GLB"))
+    };
+    if word(4)? != 2 {
+        return Err(invalid("unsupported GLB version"));
+    }
+    let length = word(8)?.min(data.len());
+
+    let (mut json, mut binary) = (None, None);
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let (chunk_length, kind) = (word(offset)?, word(offset + 4)?);
+        let chunk = data.get(offset + 8..offset + 8 + chunk_length)
+                        .ok_or_else(|| invalid("truncated GLB chunk"))?;
+        match kind {
+            0x4e4f534a if json.is_none() => {
+                json = Some(std::str::from_utf8(chunk)
+                                .map_err(|_| invalid("JSON is not UTF-8"))?);
+            },
+            0x004e4942 if binary.is_none() => binary = Some(chunk),
+            _ => (),
+        }
+        // Chunks are padded to four bytes
+        offset += 8 + chunk_length.div_ceil(4) * 4;
+    }
+
+    Ok((json.ok_or_else(|| invalid("GLB has no JSON chunk"))?, binary))
+}
+
+// Contents of a data URI or of a file relative to the scene
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>, Error> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, payload) = data.split_once(',')
+                                    .ok_or_else(|| invalid("malformed data URI"))?;
+        if !header.ends_with(";base64") {
+            return Err(invalid("data URIs must be base64"));
+        }
+        return decode_base64(payload).ok_or_else(|| invalid("bad base64 in data URI"));
+    }
+
+    // Undo percent encoding, which URIs use for spaces and other special characters
+    let mut path = Vec::with_capacity(uri.len());
+    let mut bytes = uri.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+            let value = std::str::from_utf8(&hex).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| invalid("bad escape in uri"))?;
+            path.push(value);
+        } else {
+            path.push(b);
+        }
+    }
+    let path = String::from_utf8(path).map_err(|_| invalid("uri is not UTF-8"))?;
+    fs::read(base.join(&path)).map_err(|e| {
+        Error::new(e.kind(), format!("can't read \"{}\": {}", path, e))
+    })
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// Items of an array member, or none if it's missing
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).and_then(Json::as_array).unwrap_or_default()
+}
+
+// Member holding a count or index, which must be a whole number if it's there at all
+fn count(json: &Json, key: &str) -> Result<Option<usize>, Error> {
+    match json.get(key) {
+        None => Ok(None),
+        Some(value) => match value.as_f64() {
+            Some(n) if n >= 0. && n.fract() == 0. && n <= u32::MAX as f64 => {
+                Ok(Some(n as usize))
+            },
+            _ => Err(invalid(&format!("{} should be a whole number", key))),
+        },
+    }
+}
+
+fn number(json: &Json, key: &str, default: f32) -> Result<f32, Error> {
+    match json.get(key) {
+        None => Ok(default),
+        Some(value) => value.as_f64().map(|n| n as f32)
+                            .ok_or_else(|| invalid(&format!("{} should be a number", key))),
+    }
+}
+
+fn numbers<const N: usize>(json: &Json, key: &str) -> Result<Option<[f32; N]>, Error> {
+    let values = match json.get(key) {
+        None => return Ok(None),
+        Some(values) => values.as_array().unwrap_or_default(),
+    };
+    if values.len() != N || values.iter().any(|v| v.as_f64().is_none()) {
+        return Err(invalid(&format!("{} should be {} numbers", key, N)));
+    }
+    Ok(Some(std::array::from_fn(|i| values[i].as_f64().unwrap_or_default() as f32)))
+}
+
+// Element `index` of one of the top level arrays, such as "accessors"
+fn element<'a>(json: &'a Json, collection: &str, index: usize) -> Result<&'a Json, Error> {
+    array(json, collection).get(index).ok_or_else(|| {
+        invalid(&format!("{} index {} out of range", collection, index))
+    })
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|i| {
+        let (column, row) = (i / 4, i % 4);
+        (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum()
+    })
+}
+
+// Matrix which scales, then rotates by a unit quaternion, then translates
+fn compose(t: [f32; 3], [x, y, z, w]: [f32; 4], s: [f32; 3]) -> Matrix {
+    [
+        (1. - 2. * (y * y + z * z)) * s[0], 2. * (x * y + z * w) * s[0],
+        2. * (x * z - y * w) * s[0], 0.,
+        2. * (x * y - z * w) * s[1], (1. - 2. * (x * x + z * z)) * s[1],
+        2. * (y * z + x * w) * s[1], 0.,
+        2. * (x * z + y * w) * s[2], 2. * (y * z - x * w) * s[2],
+        (1. - 2. * (x * x + y * y)) * s[2], 0.,
+        t[0], t[1], t[2], 1.,
+    ]
+}
+
+fn transform_point(m: &Matrix, p: Point3) -> Point3 {
+    transform_vector(m, p) + Vec3::from(m[12], m[13], m[14])
+}
+
+fn transform_vector(m: &Matrix, v: Vec3) -> Vec3 {
+    Vec3::from(m[0] * v.x() + m[4] * v.y() + m[8] * v.z(),
+               m[1] * v.x() + m[5] * v.y() + m[9] * v.z(),
+               m[2] * v.x() + m[6] * v.y() + m[10] * v.z())
+}
+
+// Normals are carried by the inverse transpose of a matrix, which the cofactors give up
+// to scale. Columns of the cofactor matrix are cross products of the matrix's columns.
+fn transform_normal(m: &Matrix, n: Vec3) -> Vec3 {
+    let [a, b, c] = [0, 4, 8].map(|i| Vec3::from(m[i], m[i + 1], m[i + 2]));
+    let (bc, ca, ab) = (cross(&b, &c), cross(&c, &a), cross(&a, &b));
+    let sign = dot(&a, &bc).signum();
+    sign * (n.x() * bc + n.y() * ca + n.z() * ab)
+}
+
+fn determinant(m: &Matrix) -> f32 {
+    let [a, b, c] = [0, 4, 8].map(|i| Vec3::from(m[i], m[i + 1], m[i + 2]));
+    dot(&a, &cross(&b, &c))
+}
+
+// Every primitive using one material, merged into a single mesh
+#[derive(Default)]
+struct Group {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f32, f32)>,
+    colors: Vec<Color>,
+    triangles: Vec<[u32; 3]>,
+    // Whether any primitive gave texture coordinates or colors
+    textured: bool,
+    colored: bool,
+}
+
+struct Document<'a> {
+    json: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    base: &'a Path,
+    // Textures already loaded, by texture index and whether they hold color
+    textures: HashMap<(usize, bool), Arc<ImageTexture>>,
+    // Primitives gathered by material, with the last group for those without one
+    groups: Vec<Group>,
+    cameras: Vec<(Matrix, usize)>,
+}
+
+impl Document<'_> {
+    // Places every node of the scene, starting from the roots
+    fn walk(&mut self) -> Result<(), Error> {
+        let node_count = array(self.json, "nodes").len();
+        self.groups = (0..=array(self.json, "materials").len()).map(|_| Group::default())
+                                                                 .collect();
+
+        let roots: Vec<usize> = match array(self.json, "scenes") {
+            [] => {
+                // Without scenes, every node that isn't a child is drawn
+                let mut child = vec![false; node_count];
+                for node in array(self.json, "nodes") {
+                    for c in array(node, "children") {
+                        if let Some(i) = c.as_f64().filter(|&i| i >= 0. && i < node_count as f64) {
+                            child[i as usize] = true;
+                        }
+                    }
+                }
+                (0..node_count).filter(|&i| !child[i]).collect()
+            },
+            scenes => {
+                let scene = count(self.json, "scene")?.unwrap_or(0);
+                let scene = scenes.get(scene).ok_or_else(|| invalid("scene out of range"))?;
+                array(scene, "nodes").iter().map(|node| {
+                    node.as_f64().filter(|&i| i >= 0. && i.fract() == 0.)
+                        .map(|i| i as usize).ok_or_else(|| invalid("bad node index"))
+                }).collect::<Result<_, Error>>()?
+            },
+        };
+
+        // Nodes are placed depth first from a stack, so deep hierarchies can't overflow the
+        // call stack. Each node may have only one parent, so a node reached twice means a
+        // cycle, or children shared in a way that could take exponentially long to place.
+        let mut visited = vec![false; node_count];
+        let mut stack: Vec<(usize, Matrix)> = roots.into_iter().rev()
+                                                   .map(|root| (root, IDENTITY)).collect();
+        while let Some((index, parent)) = stack.pop() {
+            if visited.get(index) == Some(&true) {
+                return Err(invalid(&format!("node {} is reached more than once", index)));
+            }
+            let children = self.visit(index, &parent)?;
+            visited[index] = true;
+            stack.extend(children.into_iter().rev());
+        }
+        Ok(())
+    }
+
+    // Places a node's mesh and camera, returning its children along with the transform
+    // they inherit
+    fn visit(&mut self, index: usize, parent: &Matrix) -> Result<Vec<(usize, Matrix)>, Error> {
+        let json = self.json;
+        let node = element(json, "nodes", index)?;
+
+        let local = match numbers::<16>(node, "matrix")? {
+            Some(matrix) => matrix,
+            None => compose(numbers(node, "translation")?.unwrap_or([0.; 3]),
+                            numbers(node, "rotation")?.unwrap_or([0., 0., 0., 1.]),
+                            numbers(node, "scale")?.unwrap_or([1.; 3])),
+        };
+        let transform = multiply(parent, &local);
+
+        if let Some(mesh) = count(node, "mesh")? {
+            let mesh = element(json, "meshes", mesh)?;
+            for primitive in array(mesh, "primitives") {
+                self.add_primitive(primitive, &transform)?;
+            }
+        }
+        if let Some(camera) = count(node, "camera")? {
+            element(json, "cameras", camera)?;
+            self.cameras.push((transform, camera));
+        }
+        array(node, "children").iter().map(|child| {
+            child.as_f64().filter(|&i| i >= 0. && i.fract() == 0.)
+                 .map(|i| (i as usize, transform)).ok_or_else(|| invalid("bad child index"))
+        }).collect()
+    }
+
+    fn add_primitive(&mut self, primitive: &Json, transform: &Matrix) -> Result<(), Error> {
+        let attributes = primitive.get("attributes")
+                                  .ok_or_else(|| invalid("primitive has no attributes"))?;
+        let attribute = |name: &str| -> Result<Option<(Vec<f64>, usize)>, Error> {
+            count(attributes, name)?.map(|i| self.accessor(i)).transpose()
+        };
+
+        let (positions, components) = attribute("POSITION")?
+            .ok_or_else(|| invalid("primitive has no POSITION"))?;
+        if components != 3 {
+            return Err(invalid("POSITION should be VEC3"));
+        }
+        let vertex_count = positions.len() / 3;
+
+        let indices: Vec<u32> = match count(primitive, "indices")? {
+            Some(i) => {
+                let (indices, _) = self.accessor(i)?;
+                if let Some(&i) = indices.iter().find(|&&i| i >= vertex_count as f64) {
+                    return Err(invalid(&format!("vertex index {} out of range", i)));
+                }
+                indices.iter().map(|&i| i as u32).collect()
+            },
+            None => (0..vertex_count as u32).collect(),
+        };
+        let mut triangles: Vec<[u32; 3]> = match count(primitive, "mode")?.unwrap_or(4) {
+            4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // Strips alternate their winding, fans turn around their first vertex
+            5 => (2..indices.len()).map(|i| {
+                if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }
+            }).collect(),
+            6 => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]])
+                                   .collect(),
+            // Points and lines have no surface to draw
+            _ => return Ok(()),
+        };
+
+        let positions: Vec<Point3> = positions.chunks_exact(3).map(|p| {
+            transform_point(transform, Vec3::from(p[0] as f32, p[1] as f32, p[2] as f32))
+        }).collect();
+        if positions.iter().any(|p| !(p.x().is_finite() && p.y().is_finite()
+                                      && p.z().is_finite())) {
+            return Err(invalid("vertex positions are not finite"));
+        }
+        // Mirroring transforms turn triangles inside out, so they're wound back
+        if determinant(transform) < 0. {
+            for triangle in &mut triangles {
+                triangle.swap(1, 2);
+            }
+        }
+
+        let normals: Vec<Vec3> = match attribute("NORMAL")? {
+            Some((normals, 3)) if normals.len() == 3 * vertex_count => {
+                normals.chunks_exact(3).map(|n| {
+                    let n = Vec3::from(n[0] as f32, n[1] as f32, n[2] as f32);
+                    let n = transform_normal(transform, n);
+                    if n.near_zero() {n} else {unit_vector(n)}
+                }).collect()
+            },
+            Some(_) => return Err(invalid("NORMAL should be VEC3 with one per vertex")),
+            None => PolygonMesh {
+                positions: positions.clone(),
+                faces: triangles.iter().map(|t| t.to_vec()).collect(),
+                ..Default::default()
+            }.vertex_normals(),
+        };
+        // Images start from their top row, so v is flipped to match `ImageTexture`
+        let uvs: Option<Vec<(f32, f32)>> = match attribute("TEXCOORD_0")? {
+            Some((uvs, 2)) if uvs.len() == 2 * vertex_count => {
+                Some(uvs.chunks_exact(2).map(|uv| (uv[0] as f32, 1. - uv[1] as f32)).collect())
+            },
+            Some(_) => return Err(invalid("TEXCOORD_0 should be VEC2 with one per vertex")),
+            None => None,
+        };
+        let colors: Option<Vec<Color>> = match attribute("COLOR_0")? {
+            Some((colors, n @ (3 | 4))) if colors.len() == n * vertex_count => {
+                Some(colors.chunks_exact(n).map(|c| {
+                    Color::from(c[0] as f32, c[1] as f32, c[2] as f32)
+                }).collect())
+            },
+            Some(_) => return Err(invalid("COLOR_0 should be VEC3 or VEC4 with one per \
+                                           vertex")),
+            None => None,
+        };
+
+        let material = count(primitive, "material")?;
+        let group = match material {
+            Some(material) => {
+                element(self.json, "materials", material)?;
+                &mut self.groups[material]
+            },
+            None => self.groups.last_mut().ok_or_else(|| invalid("no groups"))?,
+        };
+        let offset = group.positions.len() as u32;
+        group.positions.extend(positions);
+        group.normals.extend(normals);
+        group.textured |= uvs.is_some();
+        group.uvs.extend(uvs.unwrap_or_else(|| vec![(0., 0.); vertex_count]));
+        group.colored |= colors.is_some();
+        group.colors.extend(colors.unwrap_or_else(|| vec![Color::from(1., 1., 1.);
+                                                          vertex_count]));
+        group.triangles.extend(triangles.iter().map(|t| t.map(|i| i + offset)));
+        Ok(())
+    }
+
+    // Values of an accessor, converted to floating point, along with the number of
+    // components in each element
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), Error> {
+        let accessor = element(self.json, "accessors", index)?;
+        let what = |msg: &str| invalid(&format!("accessor {}: {}", index, msg));
+        if accessor.get("sparse").is_some() {
+            return Err(what("sparse accessors aren't supported"));
+        }
+
+        let element_count = count(accessor, "count")?.ok_or_else(|| what("missing count"))?;
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(what("unknown type")),
+        };
+        let component_type = count(accessor, "componentType")?.unwrap_or(0);
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(what("unknown componentType")),
+        };
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+
+        let view = match count(accessor, "bufferView")? {
+            Some(view) => element(self.json, "bufferViews", view)?,
+            // Accessors without data are all zeros
+            None if element_count * components <= MAX_ZERO_VALUES => {
+                return Ok((vec![0.; element_count * components], components));
+            },
+            None => return Err(what("too many values without a buffer view")),
+        };
+        let buffer = count(view, "buffer")?.ok_or_else(|| what("view has no buffer"))?;
+        let buffer = self.buffers.get(buffer).ok_or_else(|| what("buffer out of range"))?;
+        let element_size = size * components;
+        let stride = count(view, "byteStride")?.unwrap_or(0).max(element_size);
+        let view_start = count(view, "byteOffset")?.unwrap_or(0);
+        let view_length = count(view, "byteLength")?.ok_or_else(|| what("view has no length"))?;
+        let view = buffer.get(view_start..view_start + view_length)
+                         .ok_or_else(|| what("view runs past the end of its buffer"))?;
+
+        let start = count(accessor, "byteOffset")?.unwrap_or(0);
+        let end = (element_count as u64).saturating_sub(1) * stride as u64
+                  + (start + element_size) as u64;
+        if element_count > 0 && end > view.len() as u64 {
+            return Err(what("runs past the end of its buffer view"));
+        }
+
+        let mut values = Vec::with_capacity(element_count * components);
+        for i in 0..element_count {
+            let item = &view[start + i * stride..];
+            for c in 0..components {
+                let b = &item[c * size..(c + 1) * size];
+                values.push(match component_type {
+                    5120 if normalized => (b[0] as i8 as f64 / 127.).max(-1.),
+                    5120 => b[0] as i8 as f64,
+                    5121 if normalized => b[0] as f64 / 255.,
+                    5121 => b[0] as f64,
+                    5122 if normalized => (i16::from_le_bytes([b[0], b[1]]) as f64 / 32767.)
+                                              .max(-1.),
+                    5122 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    5123 if normalized => u16::from_le_bytes([b[0], b[1]]) as f64 / 65535.,
+                    5123 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                });
+            }
+        }
+        Ok((values, components))
+    }
+
+    // Image of a texture, decoded from sRGB if it holds color
+    fn texture(&mut self, index: usize, color: bool) -> Result<Arc<ImageTexture>, Error> {
+        if let Some(texture) = self.textures.get(&(index, color)) {
+            return Ok(texture.clone());
+        }
+
+        let texture = element(self.json, "textures", index)?;
+        let source = count(texture, "source")?
+            .ok_or_else(|| invalid(&format!("texture {} has no source", index)))?;
+        let image = element(self.json, "images", source)?;
+        let bytes = match (image.get("uri").and_then(Json::as_str), count(image, "bufferView")?) {
+            (Some(uri), _) => read_uri(uri, self.base)?,
+            (None, Some(view)) => {
+                let view = element(self.json, "bufferViews", view)?;
+                let buffer = count(view, "buffer")?.and_then(|b| self.buffers.get(b))
+                                                   .ok_or_else(|| invalid("bad image buffer"))?;
+                let start = count(view, "byteOffset")?.unwrap_or(0);
+                let length = count(view, "byteLength")?.unwrap_or(0);
+                buffer.get(start..start + length)
+                      .ok_or_else(|| invalid("image runs past the end of its buffer"))?
+                      .to_vec()
+            },
+            (None, None) => return Err(invalid(&format!("image {} has no data", source))),
+        };
+        if !bytes.starts_with(b"\x89PNG") {
+            return Err(invalid(&format!("image {} isn't a PNG, the only format supported",
+                                        source)));
+        }
+
+        let image = Image::decode_png(&bytes)?;
+        let texture = Arc::new(if color {ImageTexture::from(image)}
+                               else {ImageTexture::data(image)});
+        self.textures.insert((index, color), texture.clone());
+        Ok(texture)
+    }
+
+    // Material of a group, or glTF's default material for primitives without one
+    fn material(&mut self, index: Option<usize>, colored: bool) -> Result<Pbr, Error> {
+        let json = self.json;
+        let empty = Json::Object(Vec::new());
+        let material = match index {
+            Some(index) => element(json, "materials", index)?,
+            None => &empty,
+        };
+        let pbr = material.get("pbrMetallicRoughness").unwrap_or(&empty);
+
+        let mut texture = |key: &str, color: bool| -> Result<Option<Arc<ImageTexture>>, Error> {
+            pbr.get(key).map(|info| {
+                let index = count(info, "index")?
+                    .ok_or_else(|| invalid(&format!("{} has no index", key)))?;
+                self.texture(index, color)
+            }).transpose()
+        };
+
+        let [r, g, b, _] = numbers::<4>(pbr, "baseColorFactor")?.unwrap_or([1.; 4]);
+        let base_color = Factor {
+            factor: Color::from(r, g, b),
+            image: texture("baseColorTexture", true)?,
+            vertex_colors: colored,
+        };
+        let metallic = number(pbr, "metallicFactor", 1.)?;
+        let roughness = number(pbr, "roughnessFactor", 1.)?;
+        let metallic_roughness = Factor {
+            factor: Color::from(1., roughness, metallic),
+            image: texture("metallicRoughnessTexture", false)?,
+            vertex_colors: false,
+        };
+
+        // Material IDs follow the file's materials, with the default material last
+        let id = index.unwrap_or(array(json, "materials").len()) as u32 + 1;
+        Ok(Pbr::textured(Arc::new(base_color), Arc::new(metallic_roughness)).with_id(id))
+    }
+
+    fn finish(mut self) -> Result<GltfScene, Error> {
+        let mut world = HittableList::new();
+        let mut bounds: Option<Aabb> = None;
+        let groups = std::mem::take(&mut self.groups);
+        let last = groups.len() - 1;
+        for (i, group) in groups.into_iter().enumerate() {
+            if group.triangles.is_empty() {
+                continue;
+            }
+            let index = if i == last {None} else {Some(i)};
+            let material = Arc::new(self.material(index, group.colored)?);
+
+            let mesh = PolygonMesh {
+                faces: group.triangles.iter().map(|t| t.to_vec()).collect(),
+                uvs: group.textured.then(|| group.triangles.iter().map(|t| {
+                    t.iter().map(|&v| group.uvs[v as usize]).collect()
+                }).collect()),
+                normals: Some(group.normals),
+                colors: group.colored.then_some(group.colors),
+                positions: group.positions,
+            };
+            let mesh = TriangleMesh::from(&mesh, material);
+            bounds = match (bounds, mesh.bounding_box()) {
+                (Some(a), Some(b)) => Some(a.merge(&b)),
+                (a, b) => a.or(b),
+            };
+            world.push(mesh);
+        }
+
+        // Cameras focus on the middle of the scene, or ten units ahead if it's behind them
+        let center = bounds.map(|b| (b.min() + b.max()) / 2.);
+        let cameras = self.cameras.iter().map(|(transform, index)| {
+            let camera = element(self.json, "cameras", *index)?;
+            let view_from = transform_point(transform, Vec3::new());
+            let forward = transform_vector(transform, Vec3::from(0., 0., -1.));
+            if forward.near_zero() {
+                return Err(invalid(&format!("camera {} has a degenerate transform", index)));
+            }
+            let forward = unit_vector(forward);
+            let focus_dist = center.map(|c| dot(&(c - view_from), &forward))
+                                   .filter(|&d| d > 0.).unwrap_or(10.);
+
+            let (vfov, orthographic_width) = match camera.get("type").and_then(Json::as_str) {
+                Some("perspective") => {
+                    let perspective = camera.get("perspective")
+                                            .ok_or_else(|| invalid("missing perspective"))?;
+                    (number(perspective, "yfov", 0.)?.to_degrees(), None)
+                },
+                Some("orthographic") => {
+                    let orthographic = camera.get("orthographic")
+                                             .ok_or_else(|| invalid("missing orthographic"))?;
+                    (20., Some(2. * number(orthographic, "xmag", 1.)?.abs()))
+                },
+                _ => return Err(invalid(&format!("camera {} has an unknown type", index))),
+            };
+            if !(vfov > 0. && vfov < 180.) {
+                return Err(invalid(&format!("camera {} has a bad field of view", index)));
+            }
+
+            Ok(GltfCamera {
+                pose: CameraPose {
+                    view_from,
+                    view_at: view_from + focus_dist * forward,
+                    vfov,
+                    focus_dist,
+                },
+                orthographic_width,
+            })
+        }).collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(GltfScene {world, cameras})
+    }
+}
+
+// Constant factor scaling an optional image and any vertex colors, which is how glTF
+// combines its material parameters
+struct Factor {
+    factor: Color,
+    image: Option<Arc<ImageTexture>>,
+    vertex_colors: bool,
+}
+
+impl Texture for Factor {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+        match &self.image {
+            Some(image) => self.factor * image.value(u, v, p),
+            None => self.factor,
+        }
+    }
+
+    fn value_at(&self, record: &HitRecord) -> Color {
+        let value = self.value(record.u, record.v, &record.p);
+        match (self.vertex_colors, record.color) {
+            (true, Some(color)) => value * color,
+            _ => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    // Parses a GLB whose buffer holds `positions` followed by `indices`, as accessors 0
+    // and 1, drawn by the given primitive through the given nodes. `extra` adds members
+    // to the top level of the JSON.
+    fn parse_glb(positions: &[[f32; 3]], indices: &[u16], primitive: &str, nodes: &str,
+                 extra: &str) -> Result<GltfScene, Error> {
+        let mut bin: Vec<u8> = positions.iter().flatten().flat_map(|n| n.to_le_bytes())
+                                        .collect();
+        let index_offset = bin.len();
+        bin.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        bin.resize(bin.len().div_ceil(4) * 4, 0);
+
+        let mut json = format!(r#"{{"asset": {{"version": "2.0"}}, {}
+            "buffers": [{{"byteLength": {}}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": {}}},
+                            {{"buffer": 0, "byteOffset": {}, "byteLength": {}}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": {},
+                            "type": "VEC3"}},
+                          {{"bufferView": 1, "componentType": 5123, "count": {},
+                            "type": "SCALAR"}}],
+            "meshes": [{{"primitives": [{}]}}],
+            "nodes": {}}}"#,
+            extra, bin.len(), index_offset, index_offset, 2 * indices.len(), positions.len(),
+            indices.len(), primitive, nodes);
+        while json.len() % 4 != 0 {
+            json.push(' ');
+        }
+
+        let mut data = b"glTF".to_vec();
+        data.extend(2u32.to_le_bytes());
+        data.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        data.extend((json.len() as u32).to_le_bytes());
+        data.extend(b"JSON");
+        data.extend(json.as_bytes());
+        data.extend((bin.len() as u32).to_le_bytes());
+        data.extend(b"BIN\0");
+        data.extend(bin);
+        parse(&data, Path::new(""))
+    }
+
+    // Whether a ray straight down through (x, y) hits the scene, and if so from the front
+    fn front_face_at(scene: &GltfScene, x: f32, y: f32) -> Option<bool> {
+        let ray = Ray::from(Vec3::from(x, y, 1.), Vec3::from(0., 0., -1.));
+        scene.world.hit(&ray, 0.001, f32::INFINITY).map(|record| record.front_face)
+    }
+
+    const SQUARE: [[f32; 3]; 4] = [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [1., 1., 0.]];
+    const ONE_NODE: &str = r#"[{"mesh": 0}]"#;
+
+    #[test]
+    fn triangle_is_read() {
+        let scene = parse_glb(&SQUARE[..3], &[0, 1, 2], r#"{"attributes": {"POSITION": 0},
+                              "indices": 1}"#, ONE_NODE, "").unwrap();
+        assert_eq!(front_face_at(&scene, 0.25, 0.25), Some(true));
+        assert_eq!(front_face_at(&scene, 0.75, 0.75), None);
+        let ray = Ray::from(Vec3::from(0.25, 0.25, 1.), Vec3::from(0., 0., -1.));
+        let hit = scene.world.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.).abs() < 1e-6);
+        assert!((hit.normal.z() - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn strips_and_fans_cover_the_square() {
+        // Listed as a strip, zig-zagging across the square, with the second triangle's
+        // winding reversed as strips require
+        let strip = parse_glb(&SQUARE, &[0, 1, 2, 3], r#"{"attributes": {"POSITION": 0},
+                              "indices": 1, "mode": 5}"#, ONE_NODE, "").unwrap();
+        // Listed as a fan, around the corner at the origin
+        let fan = parse_glb(&SQUARE, &[0, 1, 3, 2], r#"{"attributes": {"POSITION": 0},
+                            "indices": 1, "mode": 6}"#, ONE_NODE, "").unwrap();
+        // The same indices as separate triangles only cover half
+        let triangles = parse_glb(&SQUARE, &[0, 1, 2, 3], r#"{"attributes": {"POSITION": 0},
+                                  "indices": 1}"#, ONE_NODE, "").unwrap();
+        for (x, y) in [(0.25, 0.25), (0.75, 0.75), (0.9, 0.2), (0.2, 0.9)] {
+            assert_eq!(front_face_at(&strip, x, y), Some(true), "strip at {} {}", x, y);
+            assert_eq!(front_face_at(&fan, x, y), Some(true), "fan at {} {}", x, y);
+        }
+        assert_eq!(front_face_at(&triangles, 0.75, 0.75), None);
+    }
+
+    #[test]
+    fn mirrored_nodes_keep_their_outside() {
+        // Flipping z turns the triangle to face down, which only the rewinding preserves
+        // since the positions themselves stay in the plane z = 0
+        let primitive = r#"{"attributes": {"POSITION": 0}}"#;
+        let plain = parse_glb(&SQUARE[..3], &[], primitive, ONE_NODE, "").unwrap();
+        let mirrored = parse_glb(&SQUARE[..3], &[], primitive,
+                                 r#"[{"mesh": 0, "scale": [1, 1, -1]}]"#, "").unwrap();
+        assert_eq!(front_face_at(&plain, 0.25, 0.25), Some(true));
+        assert_eq!(front_face_at(&mirrored, 0.25, 0.25), Some(false));
+    }
+
+    #[test]
+    fn out_of_range_index_is_rejected() {
+        let error = parse_glb(&SQUARE[..3], &[0, 1, 3], r#"{"attributes": {"POSITION": 0},
+                              "indices": 1}"#, ONE_NODE, "").err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("vertex index 3 out of range"), "{}", error);
+    }
+
+    #[test]
+    fn required_extensions_are_rejected() {
+        let primitive = r#"{"attributes": {"POSITION": 0}}"#;
+        let error = parse_glb(&SQUARE[..3], &[], primitive, ONE_NODE,
+                              r#""extensionsRequired": ["KHR_draco_mesh_compression"],"#)
+                    .err().unwrap();
+        assert!(error.to_string().contains("KHR_draco_mesh_compression"), "{}", error);
+        let used = parse_glb(&SQUARE[..3], &[], primitive, ONE_NODE,
+                             r#""extensionsUsed": ["KHR_materials_emissive_strength"],"#);
+        assert!(used.is_ok());
+    }
+
+    #[test]
+    fn nodes_reached_twice_are_rejected() {
+        let primitive = r#"{"attributes": {"POSITION": 0}}"#;
+        let cycle = r#"[{"mesh": 0, "children": [1]}, {"children": [0]}]"#;
+        assert!(parse_glb(&SQUARE[..3], &[], primitive, cycle,
+                          r#""scenes": [{"nodes": [0]}],"#).is_err());
+        // Each level shares both children with the next, which would otherwise place the
+        // last node 2^n times
+        let shared = r#"[{"children": [1, 2]}, {"children": [3]}, {"children": [3]},
+                         {"mesh": 0}]"#;
+        let error = parse_glb(&SQUARE[..3], &[], primitive, shared, "").err().unwrap();
+        assert!(error.to_string().contains("node 3 is reached more than once"), "{}", error);
+    }
+}
@@ -0,0 +1,373 @@
+use std::io::{Error, ErrorKind};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::image::Image;
+
+// Cells along each side of the blocks whose height ranges let rays skip empty space
+const BLOCK: usize = 16;
+
+// Layers of noise summed for procedural terrain, each at twice the frequency and half
+// the amplitude of the one before
+const OCTAVES: u32 = 8;
+
+// Lattice cells across the field in the broadest layer of noise
+const BASE_FREQUENCY: f32 = 4.;
+
+/// Terrain made of a grid of heights over the xz plane, starting at `corner` and
+/// reaching `size.x()` along x and `size.z()` along z. Heights run from 0 to 1 and are
+/// scaled by `size.y()`, so the lowest possible point lies at `corner`.
+///
+/// Each cell is drawn as two triangles, with normals smoothly blended across them. UVs
+/// span the whole field, laid out so an image texture lines up with the image the
+/// heights came from.
+pub struct Heightfield<T: Material> {
+    corner: Point3,
+    size: Vec3,
+    // Samples along x and z
+    columns: usize,
+    rows: usize,
+    // Heights above the corner in world units, row by row along x
+    heights: Vec<f32>,
+    // Lowest and highest point of each block, row by row along x
+    blocks: Vec<(f32, f32)>,
+    block_columns: usize,
+    bounds: Aabb,
+    material: Arc<T>,
+}
+
+impl<T: Material> Heightfield<T> {
+    /// Heights from the brightness of a grayscale image, with its top row at the low z
+    /// side of the field. 16-bit images give smoother terrain than 8-bit ones.
+    pub fn load<P: AsRef<Path>>(path: P, corner: Point3, size: Vec3,
+                                material: Arc<T>) -> Result<Self, Error> {
+        let image = Image::load(path)?;
+        if image.width() < 2 || image.height() < 2 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  "heightfield image needs at least 2 x 2 pixels"));
+        }
+
+        let (columns, rows) = (image.width() as usize, image.height() as usize);
+        let heights = (0..rows * columns).map(|i| {
+            let pixel = image.pixel((i % columns) as u32, (i / columns) as u32);
+            (pixel.x() + pixel.y() + pixel.z()) / 3.
+        }).collect();
+
+        Ok(Self::new(columns, rows, heights, corner, size, material))
+    }
+
+    /// Rolling terrain from fractal value noise, `resolution` samples along each side.
+    /// The same seed always gives the same terrain.
+    pub fn noise(resolution: u32, seed: u64, corner: Point3, size: Vec3,
+                 material: Arc<T>) -> Self {
+        let n = resolution.max(2) as usize;
+        let mut heights: Vec<f32> = (0..n * n).into_par_iter().map(|i| {
+            let (x, z) = ((i % n) as f32 / (n - 1) as f32, (i / n) as f32 / (n - 1) as f32);
+            (0..OCTAVES).map(|octave| {
+                let frequency = BASE_FREQUENCY * (1 << octave) as f32;
+                value_noise(x * frequency, z * frequency, seed.wrapping_add(octave as u64))
+                    / (1 << octave) as f32
+            }).sum()
+        }).collect();
+
+        // Stretch to the full range of heights
+        let (low, high) = heights.iter().fold((f32::INFINITY, f32::NEG_INFINITY),
+                                              |(lo, hi), &h| (lo.min(h), hi.max(h)));
+        let range = (high - low).max(f32::EPSILON);
+        heights.par_iter_mut().for_each(|h| *h = (*h - low) / range);
+
+        Self::new(n, n, heights, corner, size, material)
+    }
+
+    fn new(columns: usize, rows: usize, mut heights: Vec<f32>, corner: Point3, size: Vec3,
+           material: Arc<T>) -> Self {
+        for h in heights.iter_mut() {
+            *h *= size.y();
+        }
+
+        // Blocks share their edge samples with their neighbours, since the triangles
+        // along a block's edge reach them
+        let (cells_x, cells_z) = (columns - 1, rows - 1);
+        let block_columns = cells_x.div_ceil(BLOCK);
+        let block_rows = cells_z.div_ceil(BLOCK);
+        let blocks = (0..block_columns * block_rows).map(|b| {
+            let (bx, bz) = (b % block_columns * BLOCK, b / block_columns * BLOCK);
+            let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+            for z in bz..=(bz + BLOCK).min(cells_z) {
+                let row = &heights[z * columns..(z + 1) * columns];
+                for &h in &row[bx..=(bx + BLOCK).min(cells_x)] {
+                    range = (range.0.min(h), range.1.max(h));
+                }
+            }
+            range
+        }).collect::<Vec<_>>();
+
+        let low = blocks.iter().map(|b| b.0).fold(f32::INFINITY, f32::min);
+        let high = blocks.iter().map(|b| b.1).fold(f32::NEG_INFINITY, f32::max);
+        let bounds = Aabb::from(corner + Vec3::from(0., low, 0.),
+                                corner + Vec3::from(size.x(), high, size.z()));
+
+        Heightfield {
+            corner,
+            size,
+            columns,
+            rows,
+            heights,
+            blocks,
+            block_columns,
+            bounds,
+            material,
+        }
+    }
+
+    // Sizes of a cell along x and z
+    fn spacing(&self) -> (f32, f32) {
+        (self.size.x() / (self.columns - 1) as f32, self.size.z() / (self.rows - 1) as f32)
+    }
+
+    // Normal at a sample from the slope across its neighbours, one sided at the edges
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.spacing();
+        let height = |i: usize, j: usize| self.heights[j * self.columns + i];
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f32 * dx);
+        let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f32 * dz);
+        unit_vector(Vec3::from(-slope_x, 1., -slope_z))
+    }
+
+    // Where the ray crosses the surface in a cell, which it spends from ta to tb. The
+    // cell is split along its diagonal into the two triangles drawn for it, over each
+    // of which the ray's height above the surface changes linearly.
+    //
+    // `above` carries the height above the surface where the ray came in, if known, and
+    // is left with the height where it leaves. Sharing these between neighbours keeps
+    // rounding from letting rays slip through the edges between them.
+    fn hit_cell(&self, ray: &Ray, (origin, direction): ((f32, f32), (f32, f32)), i: usize,
+                j: usize, (ta, tb): (f32, f32),
+                above: &mut Option<f32>) -> Option<HitRecord<'_>> {
+        let height = |i: usize, j: usize| self.heights[j * self.columns + i];
+        let (h00, h10) = (height(i, j), height(i + 1, j));
+        let (h01, h11) = (height(i, j + 1), height(i + 1, j + 1));
+
+        // Position within the cell, from 0 to 1 along x and z
+        let local = |t: f32| (origin.0 + t * direction.0 - i as f32,
+                              origin.1 + t * direction.1 - j as f32);
+        // Height of the plane of either triangle, the one beyond the diagonal being where
+        // x is ahead of z
+        let plane = |beyond: bool, (x, z): (f32, f32)| {
+            if beyond {
+                h00 + x * (h10 - h00) + z * (h11 - h10)
+            } else {
+                h00 + z * (h01 - h00) + x * (h11 - h01)
+            }
+        };
+        let (oy, dy) = (ray.origin().y() - self.corner.y(), ray.direction().y());
+        let rise = |t: f32, beyond: bool| oy + t * dy - plane(beyond, local(t));
+
+        // Split where the ray crosses the diagonal, leaving the second piece empty if it
+        // doesn't
+        let mut pieces = [(ta, tb), (tb, tb)];
+        let (start, slope) = (local(0.), direction.0 - direction.1);
+        if slope != 0. {
+            let diagonal = (start.1 - start.0) / slope;
+            if diagonal > ta && diagonal < tb {
+                pieces = [(ta, diagonal), (diagonal, tb)];
+            }
+        }
+
+        for (t0, t1) in pieces {
+            let mid = local((t0 + t1) / 2.);
+            let beyond = mid.0 >= mid.1;
+            let g0 = above.unwrap_or_else(|| rise(t0, beyond));
+            let g1 = rise(t1, beyond);
+            *above = Some(g1);
+            if g0 * g1 > 0. {
+                continue;
+            }
+
+            let t = if g0 == g1 {t0} else {t0 + (t1 - t0) * g0 / (g0 - g1)};
+            let (x, z) = local(t);
+            let (x, z) = (x.clamp(0., 1.), z.clamp(0., 1.));
+            let normal = if beyond {
+                (1. - x) * self.vertex_normal(i, j) + (x - z) * self.vertex_normal(i + 1, j)
+                    + z * self.vertex_normal(i + 1, j + 1)
+            } else {
+                (1. - z) * self.vertex_normal(i, j) + (z - x) * self.vertex_normal(i, j + 1)
+                    + x * self.vertex_normal(i + 1, j + 1)
+            };
+            let p = ray.at(t);
+            let u = (p.x() - self.corner.x()) / self.size.x();
+            let v = 1. - (p.z() - self.corner.z()) / self.size.z();
+
+            return Some(HitRecord::from(t, ray, unit_vector(normal), (u, v),
+                                        self.material.clone()));
+        }
+
+        None
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Heightfield<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let span = self.bounds.clip(ray, t_min, t_max)?;
+
+        // The ray in grid space, where cells are one unit across
+        let (dx, dz) = self.spacing();
+        let (o, d) = (*ray.origin() - self.corner, *ray.direction());
+        let origin = (o.x() / dx, o.z() / dz);
+        let direction = (d.x() / dx, d.z() / dz);
+        let (cells_x, cells_z) = (self.columns - 1, self.rows - 1);
+        let block_rows = self.blocks.len() / self.block_columns;
+
+        // Walk the blocks first, and only the cells of those whose heights the ray
+        // passes through
+        let mut above = None;
+        traverse(origin, direction, span, BLOCK as f32, 0..self.block_columns,
+                 0..block_rows, |bx, bz, t0, t1| {
+            let (low, high) = self.blocks[bz * self.block_columns + bx];
+            let (y0, y1) = (o.y() + t0 * d.y(), o.y() + t1 * d.y());
+            if y0.max(y1) < low || y0.min(y1) > high {
+                above = None;
+                return None;
+            }
+            traverse(origin, direction, (t0, t1), 1.,
+                     bx * BLOCK..((bx + 1) * BLOCK).min(cells_x),
+                     bz * BLOCK..((bz + 1) * BLOCK).min(cells_z),
+                     |i, j, ta, tb| {
+                self.hit_cell(ray, (origin, direction), i, j, (ta, tb), &mut above)
+            })
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+// Calls `visit` on each cell `size` units across which the ray passes through between
+// t0 and t1, in order along the ray, with the part of the ray inside it. Cells are
+// limited to the given ranges, and walking stops at the first cell `visit` returns
+// something for.
+fn traverse<R>(origin: (f32, f32), direction: (f32, f32), (t0, t1): (f32, f32), size: f32,
+               range_x: Range<usize>, range_z: Range<usize>,
+               mut visit: impl FnMut(usize, usize, f32, f32) -> Option<R>) -> Option<R> {
+    let start = |o: f32, d: f32, range: &Range<usize>| {
+        (((o + t0 * d) / size).floor() as i64).clamp(range.start as i64, range.end as i64 - 1)
+    };
+    // Distance along the ray to where it leaves a cell along one axis
+    let boundary = |o: f32, d: f32, cell: i64| {
+        if d > 0. {
+            ((cell + 1) as f32 * size - o) / d
+        } else if d < 0. {
+            (cell as f32 * size - o) / d
+        } else {
+            f32::INFINITY
+        }
+    };
+
+    let (mut i, mut j) = (start(origin.0, direction.0, &range_x),
+                          start(origin.1, direction.1, &range_z));
+    let mut t = t0;
+    loop {
+        let (next_x, next_z) = (boundary(origin.0, direction.0, i),
+                                boundary(origin.1, direction.1, j));
+        let exit = next_x.min(next_z).min(t1);
+        if let Some(found) = visit(i as usize, j as usize, t, exit) {
+            return Some(found);
+        }
+        if exit >= t1 {
+            return None;
+        }
+
+        if next_x < next_z {
+            i += direction.0.signum() as i64;
+        } else {
+            j += direction.1.signum() as i64;
+        }
+        if i < range_x.start as i64 || i >= range_x.end as i64
+            || j < range_z.start as i64 || j >= range_z.end as i64 {
+            return None;
+        }
+        t = exit;
+    }
+}
+
+// Smoothly interpolated random values on an integer lattice, from 0 to 1
+fn value_noise(x: f32, z: f32, seed: u64) -> f32 {
+    let lattice = |i: i64, j: i64| {
+        // SplitMix64 finalizer over the lattice point and seed
+        let mut h = seed ^ (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                         ^ (j as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        h ^= h >> 31;
+        (h >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let smooth = |t: f32| t * t * (3. - 2. * t);
+
+    let (i, j) = (x.floor() as i64, z.floor() as i64);
+    let (fx, fz) = (smooth(x - x.floor()), smooth(z - z.floor()));
+    let top = lattice(i, j) + fx * (lattice(i + 1, j) - lattice(i, j));
+    let bottom = lattice(i, j + 1) + fx * (lattice(i + 1, j + 1) - lattice(i, j + 1));
+    top + fz * (bottom - top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    // Flat field 39 units square, one unit per cell, with a ridge along z at the given
+    // column reaching a height of one
+    fn ridge(column: usize) -> Heightfield<Lambertian> {
+        let n = 40;
+        let heights = (0..n * n).map(|i| if i % n == column {1.} else {0.}).collect();
+        Heightfield::new(n, n, heights, Vec3::new(), Vec3::from(39., 1., 39.),
+                         Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))))
+    }
+
+    fn hit_t(field: &Heightfield<Lambertian>, from: Point3, direction: Vec3) -> Option<f32> {
+        field.hit(&Ray::from(from, direction), 0.001, f32::INFINITY).map(|hit| hit.t)
+    }
+
+    #[test]
+    fn ray_skips_empty_blocks_to_the_ridge() {
+        // Blocks are 16 cells across, so the ray crosses out of the first block before
+        // meeting the ridge's slope halfway up, half a cell before its top
+        let along_x = Vec3::from(1., 0., 0.);
+        let t = hit_t(&ridge(20), Vec3::from(-1., 0.5, 0.5), along_x).unwrap();
+        assert!((t - 20.5).abs() < 1e-4, "{}", t);
+
+        // Diagonally, crossing block boundaries along both x and z
+        let t = hit_t(&ridge(20), Vec3::from(-1., 0.5, 0.5), Vec3::from(1., 0., 1.)).unwrap();
+        assert!((t - 20.5).abs() < 1e-4, "{}", t);
+
+        // A ridge on the boundary itself is found from the first block
+        let t = hit_t(&ridge(16), Vec3::from(-1., 0.5, 7.3), along_x).unwrap();
+        assert!((t - 16.5).abs() < 1e-4, "{}", t);
+        // And from the second, coming the other way
+        let t = hit_t(&ridge(16), Vec3::from(40., 0.5, 7.3), -along_x).unwrap();
+        assert!((t - 23.5).abs() < 1e-4, "{}", t);
+    }
+
+    #[test]
+    fn ray_over_the_ridge_misses() {
+        let field = ridge(20);
+        assert!(hit_t(&field, Vec3::from(-1., 1.01, 0.5), Vec3::from(1., 0., 0.)).is_none());
+        // Straight down onto the flat ground
+        let t = hit_t(&field, Vec3::from(30.2, 5., 10.7), Vec3::from(0., -1., 0.)).unwrap();
+        assert!((t - 5.).abs() < 1e-4);
+        // Outside the field entirely
+        assert!(hit_t(&field, Vec3::from(40., 5., 10.), Vec3::from(0., -1., 0.)).is_none());
+    }
+}
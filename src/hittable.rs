@@ -1,23 +1,38 @@
 use std::sync::Arc;
 
-use crate::{Ray, Point3, Vec3, dot, Material};
+use crate::ray::Ray;
+use crate::vec3::{Color, Point3, Vec3, dot};
+use crate::material::Material;
+use crate::aabb::Aabb;
 
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f32,
+    pub u: f32,
+    pub v: f32,
     pub front_face: bool,
     pub material: Arc<dyn Material + 'a>,
+    pub object_id: u32,
+    /// Color blended from the vertices around the hit, for meshes which carry one
+    pub color: Option<Color>,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn from(t: f32, ray: &Ray, normal: Vec3, material: Arc<dyn Material + 'a>) -> Self {
+    /// Record of a hit at `t` along the ray, with surface coordinates `uv` and an outward
+    /// facing normal which is flipped to face the ray if needed
+    pub fn from(t: f32, ray: &Ray, normal: Vec3, (u, v): (f32, f32),
+                material: Arc<dyn Material + 'a>) -> Self {
         let mut record = HitRecord {
             t,
+            u,
+            v,
             normal,
             material,
             p: ray.at(t),
             front_face: false,
+            object_id: 0,
+            color: None,
         };
         record.set_face_normal(ray);
 
@@ -31,7 +46,10 @@ impl<'a> HitRecord<'a> {
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // Box enclosing the whole object, or None if it goes on forever
+    fn bounding_box(&self) -> Option<Aabb>;
 }
 
 pub struct HittableList {
@@ -48,19 +66,32 @@ impl HittableList {
     }
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
+}
+
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut hit_rec = None;
         let mut closest = t_max;
 
-        for object in &self.objects {
-            if let Some(rec) = object.hit(ray, t_min, closest) {
+        for (i, object) in self.objects.iter().enumerate() {
+            if let Some(mut rec) = object.hit(ray, t_min, closest) {
                 closest = rec.t;
+                rec.object_id = i as u32 + 1; // Zero is left for the background
                 hit_rec = Some(rec);
             }
         }
 
         hit_rec
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |aabb, other| Some(aabb.merge(&other?)))
+    }
 }
 
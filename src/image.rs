@@ -0,0 +1,256 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind, Read};
+use std::path::Path;
+
+use crate::vec3::Color;
+
+/// Image loaded from disk with channels normalized to [0, 1] but otherwise left
+/// exactly as stored, so 8-bit color data is still sRGB encoded
+pub struct Image {
+    width: u32,
+    height: u32,
+    bit_depth: u32,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Loads a PNG or PPM (P3 or P6) image, chosen by file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => Self::load_png(path),
+            Some("ppm") => Self::load_ppm(path),
+            _ => Err(Error::new(ErrorKind::InvalidInput,
+                                format!("unsupported image format \"{}\"", path.display()))),
+        }
+    }
+
+    /// Decodes a PNG image held in memory, such as one embedded in a scene file
+    pub fn decode_png(data: &[u8]) -> Result<Self, Error> {
+        Self::read_png(data)
+    }
+
+    fn load_png(path: &Path) -> Result<Self, Error> {
+        Self::read_png(BufReader::new(File::open(path)?))
+    }
+
+    fn read_png<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        // Textures look up pixels by clamping coordinates to the last row and column
+        let (width, height) = reader.info().size();
+        if width == 0 || height == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid PNG file: empty image"));
+        }
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let data = &buf[..info.buffer_size()];
+
+        let (bit_depth, max) = match info.bit_depth {
+            png::BitDepth::Sixteen => (16, u16::MAX as f32),
+            _ => (8, u8::MAX as f32),
+        };
+        let values: Vec<f32> = if bit_depth == 16 {
+            data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / max)
+                                .collect()
+        } else {
+            data.iter().map(|&b| b as f32 / max).collect()
+        };
+
+        let pixels = values.chunks_exact(info.color_type.samples()).map(|c| {
+            match info.color_type {
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                    Color::from(c[0], c[0], c[0])
+                },
+                _ => Color::from(c[0], c[1], c[2]),
+            }
+        }).collect();
+
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            bit_depth,
+            pixels,
+        })
+    }
+
+    fn load_ppm(path: &Path) -> Result<Self, Error> {
+        fn invalid(msg: &str) -> Error {
+            Error::new(ErrorKind::InvalidData, format!("invalid PPM file: {}", msg))
+        }
+
+        let data = fs::read(path)?;
+
+        // Header is whitespace separated with '#' comments, ending after the maximum
+        // value and a single whitespace character
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+                if data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated header"));
+            }
+            fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+        }
+        pos += 1;
+
+        let parse = |field: &str| field.parse::<u32>().map_err(|_| invalid("bad header value"));
+        let width = parse(&fields[1])?;
+        let height = parse(&fields[2])?;
+        let max = parse(&fields[3])?;
+        if max == 0 || max > u16::MAX as u32 {
+            return Err(invalid("bad maximum value"));
+        }
+        if fields[0] != "P3" && fields[0] != "P6" {
+            return Err(invalid("unsupported magic number"));
+        }
+        if width == 0 || height == 0 {
+            return Err(invalid("empty image"));
+        }
+        let len = (width as usize).checked_mul(height as usize)
+                                  .and_then(|n| n.checked_mul(3))
+                                  .ok_or_else(|| invalid("image too large"))?;
+
+        // Every value takes at least one byte, or two for 16-bit binary data, so a header
+        // claiming more than the file holds is caught before anything is allocated
+        let body = data.len().saturating_sub(pos);
+        let value_size = if fields[0] == "P6" && max >= 256 {2} else {1};
+        if len.checked_mul(value_size).is_none_or(|size| size > body) {
+            return Err(invalid("truncated data"));
+        }
+
+        let values: Vec<u32> = match fields[0].as_str() {
+            "P3" => {
+                String::from_utf8_lossy(data.get(pos..).unwrap_or_default())
+                    .split_ascii_whitespace()
+                    .take(len)
+                    .map(parse)
+                    .collect::<Result<_, _>>()?
+            },
+            "P6" if max < 256 => {
+                data.get(pos..pos + len).ok_or_else(|| invalid("truncated data"))?
+                    .iter().map(|&b| b as u32).collect()
+            },
+            "P6" => {
+                data.get(pos..pos + 2 * len).ok_or_else(|| invalid("truncated data"))?
+                    .chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32).collect()
+            },
+            _ => return Err(invalid("unsupported magic number")),
+        };
+        if values.len() != len {
+            return Err(invalid("truncated data"));
+        }
+
+        let pixels = values.chunks_exact(3).map(|c| {
+            Color::from(c[0] as f32, c[1] as f32, c[2] as f32) / max as f32
+        }).collect();
+
+        Ok(Image {
+            width,
+            height,
+            bit_depth: if max < 256 {8} else {16},
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u32 {
+        self.bit_depth
+    }
+
+    // Pixels are stored in row order starting from the top of the image
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `data` to a temporary PPM file and loads it back
+    fn load_ppm(name: &str, data: &[u8]) -> Result<Image, Error> {
+        let file = format!("image-{}-{}.ppm", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        fs::write(&path, data)?;
+        let image = Image::load(&path);
+        fs::remove_file(&path)?;
+        image
+    }
+
+    fn assert_pixel(image: &Image, x: u32, y: u32, expected: [f32; 3]) {
+        let c = image.pixel(x, y);
+        for (value, expected) in [c.x(), c.y(), c.z()].into_iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6, "{:?} at {} {}", [c.x(), c.y(), c.z()],
+                    x, y);
+        }
+    }
+
+    #[test]
+    fn ascii_ppm_is_read() {
+        let image = load_ppm("ascii", b"P3\n# comment\n2 1 # another\n4\n0 1 2\n4 4 4\n")
+                    .unwrap();
+        assert_eq!((image.width(), image.height(), image.bit_depth()), (2, 1, 8));
+        assert_pixel(&image, 0, 0, [0., 0.25, 0.5]);
+        assert_pixel(&image, 1, 0, [1., 1., 1.]);
+    }
+
+    #[test]
+    fn binary_ppm_is_read() {
+        let mut data = b"P6 1 2 255\n".to_vec();
+        data.extend([255, 0, 51, 0, 102, 255]);
+        let image = load_ppm("binary", &data).unwrap();
+        assert_eq!((image.width(), image.height(), image.bit_depth()), (1, 2, 8));
+        assert_pixel(&image, 0, 0, [1., 0., 0.2]);
+        assert_pixel(&image, 0, 1, [0., 0.4, 1.]);
+
+        // Values above 255 take two bytes each, most significant first
+        let mut data = b"P6 1 1 65535\n".to_vec();
+        data.extend([0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+        let image = load_ppm("wide", &data).unwrap();
+        assert_eq!(image.bit_depth(), 16);
+        assert_pixel(&image, 0, 0, [1., 32768. / 65535., 0.]);
+    }
+
+    #[test]
+    fn bad_ppm_is_rejected() {
+        let rejected = |name, data: &[u8], message: &str| {
+            let error = load_ppm(name, data).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().ends_with(message), "{}", error);
+        };
+        rejected("magic", b"P5 1 1 255\n\0", "unsupported magic number");
+        rejected("header", b"P6 1 1", "truncated header");
+        rejected("maximum", b"P3 1 1 0\n0 0 0", "bad maximum value");
+        rejected("empty", b"P3 0 4 255\n", "empty image");
+        rejected("short", b"P6 2 2 255\n\0\0\0", "truncated data");
+        rejected("short16", b"P6 1 1 1000\n\0\0\0", "truncated data");
+        rejected("ascii", b"P3 1 1 255\n1 2", "truncated data");
+        rejected("large", b"P6 60000 60000 255\n", "truncated data");
+        rejected("huge", b"P6 4000000000 4000000000 255\n", "image too large");
+    }
+}
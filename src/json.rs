@@ -0,0 +1,274 @@
+use std::io::{Error, ErrorKind};
+
+// Deepest nesting of arrays and objects accepted, so hostile files can't overflow the
+// stack
+const MAX_DEPTH: usize = 256;
+
+/// JSON value, as read by `Json::parse`. Object members keep the order they appear in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut parser = Parser {text: text.as_bytes(), pos: 0};
+        let value = parser.value(0)?;
+        parser.whitespace();
+        if parser.pos < parser.text.len() {
+            return Err(parser.invalid("unexpected data after the end"));
+        }
+        Ok(value)
+    }
+
+    /// Member of an object, or None if it's missing or this isn't an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn invalid(&self, msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidData,
+                   format!("invalid JSON: byte {}: {}", self.pos, msg))
+    }
+
+    fn whitespace(&mut self) {
+        while self.pos < self.text.len() && matches!(self.text[self.pos], b' ' | b'\t' |
+                                                                           b'\n' | b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), Error> {
+        if self.text[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.invalid(&format!("expected \"{}\"", literal)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.invalid("nested too deeply"));
+        }
+
+        self.whitespace();
+        match self.text.get(self.pos) {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        },
+                        _ => return Err(self.invalid("expected ',' or ']'")),
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    if self.text.get(self.pos) != Some(&b'"') {
+                        return Err(self.invalid("expected a member name"));
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    members.push((key, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        },
+                        _ => return Err(self.invalid("expected ',' or '}'")),
+                    }
+                }
+            },
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.invalid("unexpected character")),
+            None => Err(self.invalid("unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, Error> {
+        let start = self.pos;
+        while self.pos < self.text.len()
+              && matches!(self.text[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        // Only ASCII was consumed, so this slice is valid UTF-8
+        let literal = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        literal.parse().map(Json::Number)
+               .map_err(|_| self.invalid(&format!("bad number \"{}\"", literal)))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.pos += 1; // Opening quote
+        let mut bytes = Vec::new();
+        loop {
+            let byte = *self.text.get(self.pos).ok_or_else(|| self.invalid("unended string"))?;
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = *self.text.get(self.pos)
+                                           .ok_or_else(|| self.invalid("unended string"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.hex4()?;
+                            // Characters beyond the basic plane come as surrogate pairs.
+                            // Unpaired surrogates become U+FFFD, leaving whatever escape
+                            // follows to be read on its own.
+                            let code = match self.low_surrogate() {
+                                Some(low) if (0xd800..0xdc00).contains(&high) => {
+                                    self.pos += 6;
+                                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                                },
+                                _ => high,
+                            };
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        },
+                        _ => return Err(self.invalid("bad escape")),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                },
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.invalid("string is not UTF-8"))
+    }
+
+    // Low half of a surrogate pair escaped next in the text, without consuming it
+    fn low_surrogate(&self) -> Option<u32> {
+        let escape = self.text.get(self.pos..self.pos + 6)?.strip_prefix(b"\\u")?;
+        let low = u32::from_str_radix(std::str::from_utf8(escape).ok()?, 16).ok()?;
+        (0xdc00..0xe000).contains(&low).then_some(low)
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+                              .and_then(|digits| std::str::from_utf8(digits).ok())
+                              .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                              .ok_or_else(|| self.invalid("bad unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_are_decoded() {
+        let json = Json::parse(r#""a\"b\\c\/d\b\f\n\r\t\u00e9€""#).unwrap();
+        assert_eq!(json.as_str(), Some("a\"b\\c/d\u{8}\u{c}\n\r\té€"));
+        assert!(Json::parse(r#""\x""#).is_err());
+        assert!(Json::parse(r#""\u12""#).is_err());
+        assert!(Json::parse(r#""unended"#).is_err());
+    }
+
+    #[test]
+    fn surrogate_pairs_are_joined() {
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#).unwrap().as_str(), Some("😀"));
+        // Unpaired halves are replaced, without swallowing the escape which follows
+        assert_eq!(Json::parse(r#""\ud83dx""#).unwrap().as_str(), Some("\u{fffd}x"));
+        assert_eq!(Json::parse(r#""\ude00\ud83d""#).unwrap().as_str(),
+                   Some("\u{fffd}\u{fffd}"));
+        assert_eq!(Json::parse(r#""\ud83dA""#).unwrap().as_str(), Some("\u{fffd}A"));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_ok());
+        assert!(Json::parse(&nested(MAX_DEPTH + 2)).is_err());
+        let objects = "{\"a\":".repeat(MAX_DEPTH + 2) + "0" + &"}".repeat(MAX_DEPTH + 2);
+        assert!(Json::parse(&objects).is_err());
+    }
+
+    #[test]
+    fn trailing_data_is_rejected() {
+        assert_eq!(Json::parse(" {\"a\": [1, true, null]} \n").unwrap().get("a"),
+                   Some(&Json::Array(vec![Json::Number(1.), Json::Bool(true), Json::Null])));
+        assert!(Json::parse("{} {}").is_err());
+        assert!(Json::parse("[1] x").is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+}
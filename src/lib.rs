@@ -0,0 +1,39 @@
+pub mod vec3;
+pub mod color;
+pub mod ray;
+pub mod hittable;
+pub mod aabb;
+pub mod bvh;
+pub mod sphere;
+pub mod planar;
+pub mod quadric;
+pub mod torus;
+pub mod polynomial;
+pub mod cuboid;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod curve;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod gltf;
+pub mod subdivision;
+pub mod camera;
+pub mod aperture;
+pub mod panorama;
+pub mod stereo;
+pub mod animation;
+pub mod scene;
+pub mod material;
+pub mod film;
+pub mod filter;
+pub mod aov;
+pub mod denoise;
+pub mod render;
+pub mod checkpoint;
+pub mod image;
+pub mod json;
+pub mod texture;
+pub mod output;
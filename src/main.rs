@@ -1,44 +1,118 @@
 use std::env;
 use std::process;
-use std::fs;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::slice::Iter;
+use std::time::{Duration, Instant};
+use std::io::ErrorKind;
 
 use rand::prelude::*;
-use rayon::prelude::*;
-
-mod vec3;
-mod color;
-mod ray; 
-mod hittable;
-mod sphere;
-mod camera;
-mod material;
-
-use vec3::*;
-use color::*;
-use ray::*;
-use hittable::*;
-use sphere::*;
-use camera::*;
-use material::*;
+
+use ray_trace::vec3::*;
+use ray_trace::color::*;
+use ray_trace::hittable::*;
+use ray_trace::camera::*;
+use ray_trace::aperture::*;
+use ray_trace::panorama::*;
+use ray_trace::stereo::*;
+use ray_trace::animation::CameraPose;
+use ray_trace::scene::{MaterialDescription, SceneDescription, Shape};
+use ray_trace::gltf::{self, GltfScene};
+use ray_trace::render::*;
+use ray_trace::filter::*;
+use ray_trace::output::*;
+use ray_trace::film::Film;
+use ray_trace::denoise::denoise;
+use ray_trace::checkpoint;
 
 const ASPECT_RATIO: f32 = 3./2.;
+const PASS_SAMPLES: u32 = 4;
+const DEFAULT_CHECKPOINT_SECS: u64 = 60;
+const DEFAULT_PREVIEW_SECS: u64 = 10;
+const DEFAULT_WHITE_POINT: f32 = 4.;
+const DEFAULT_FISHEYE_FOV: f32 = 180.;
+const DEFAULT_IPD: f32 = 0.065;
+const DEFAULT_CONVERGENCE: f32 = 10.;
+
+// How long a render is allowed to keep taking samples
+enum Budget {
+    Samples(u32),
+    Time {limit: Duration, max_samples: u32},
+}
+
+// Which camera model to render the scene through
+enum CameraKind {
+    Perspective,
+    Orthographic {width: f32},
+    Equirectangular,
+    Fisheye {fov: f32},
+    Cubemap,
+}
+
+impl CameraKind {
+    // Aspect ratio each camera model fills best
+    fn aspect_ratio(&self) -> f32 {
+        match self {
+            CameraKind::Perspective | CameraKind::Orthographic {..} => ASPECT_RATIO,
+            CameraKind::Equirectangular => 2.,
+            CameraKind::Fisheye {..} => 1.,
+            CameraKind::Cubemap => 3. / 2.,
+        }
+    }
+}
+
+impl Budget {
+    fn max_samples(&self) -> u32 {
+        match self {
+            Budget::Samples(n) => *n,
+            Budget::Time {max_samples, ..} => *max_samples,
+        }
+    }
+}
 
 struct ImageArgs {
     width: u32,
     height: u32,
-    samples: u32,
+    budget: Budget,
     max_depth: u32,
     filename: String,
+    resume: bool,
+    checkpoint_interval: Option<Duration>,
+    progressive: bool,
+    preview_filename: String,
+    preview_passes: Option<u32>,
+    preview_interval: Option<Duration>,
+    display: Display,
+    format: ImageFormat,
+    filter: Filter,
+    aovs: bool,
+    denoise: bool,
+    camera: CameraKind,
+    stereo: Option<(StereoRig, StereoLayout)>,
+    aperture: Aperture,
+    cats_eye: f32,
+    physical: Option<PhysicalCamera>,
+    scene: Option<SceneDescription>,
+    frames: Option<(u32, u32)>,
+    gltf: Option<GltfScene>,
+    save_scene: Option<String>,
 }
 
-fn random_scene() -> HittableList {
-    let mut world = HittableList::new();
+// Seeded so that a resumed render recreates exactly the same scene
+fn random_scene(seed: u64) -> SceneDescription {
+    let mut scene = SceneDescription::new();
+    // Every name below is a distinct single word, which is all a material name needs
+    fn add_material(scene: &mut SceneDescription, name: &str,
+                    material: MaterialDescription) -> usize {
+        scene.add_material(name, material).expect("material names are unique words")
+    }
 
-    let mat_ground = Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5)));
-    world.push(Sphere::from(Point3::from(0., -1000., 0.), 1000., Arc::clone(&mat_ground)));
+    let mat_ground = add_material(&mut scene, "ground", MaterialDescription::Lambertian {
+        albedo: Color::from(0.5, 0.5, 0.5),
+    });
+    scene.add_object(Shape::Plane {point: Point3::new(), normal: Vec3::from(0., 1., 0.)},
+                     mat_ground);
 
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(seed);
     for i in -11..11 {
         for j in -11..11 {
             let mat_type: f32 = rng.gen();
@@ -47,65 +121,145 @@ fn random_scene() -> HittableList {
                                       j as f32 + 0.9 * rng.gen::<f32>());
             
             if (center - Point3::from(4., 0.2, 0.)).length() > 0.9 {
-                if mat_type < 0.8 { // Make Diffuse sphere
-                    let mat_sphere = Arc::new(Lambertian::from(
-                                                Color::from(rng.gen::<f32>(), 
-                                                            rng.gen::<f32>(), 
-                                                            rng.gen::<f32>())));
-                    world.push(Sphere::from(center, 0.2, Arc::clone(&mat_sphere)));
+                let name = format!("sphere{}", scene.objects.len());
+                let material = if mat_type < 0.8 { // Make Diffuse sphere
+                    MaterialDescription::Lambertian {
+                        albedo: Color::from(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()),
+                    }
                 } else if mat_type < 0.95 { // Make Metal sphere
-                    let mat_sphere = Arc::new(Metal::from(
-                                                Color::from(rng.gen_range(0.5..1.),
-                                                            rng.gen_range(0.5..1.),
-                                                            rng.gen_range(0.5..1.)),
-                                                rng.gen_range(0.0..0.5)));
-                    world.push(Sphere::from(center, 0.2, Arc::clone(&mat_sphere)));
+                    MaterialDescription::Metal {
+                        albedo: Color::from(rng.gen_range(0.5..1.),
+                                            rng.gen_range(0.5..1.),
+                                            rng.gen_range(0.5..1.)),
+                        fuzz: rng.gen_range(0.0..0.5),
+                    }
                 } else { // Make Glass sphere
-                    let mat_sphere = Arc::new(Dielectric::from(1.5));
-                    world.push(Sphere::from(center, 0.2, Arc::clone(&mat_sphere)));
-                }
+                    MaterialDescription::Dielectric {refraction: 1.5}
+                };
+                let mat_sphere = add_material(&mut scene, &name, material);
+                scene.add_object(Shape::Sphere {center, radius: 0.2}, mat_sphere);
             }
         }
     }
 
-    let mat1 = Arc::new(Dielectric::from(1.5));
-    let mat2 = Arc::new(Lambertian::from(Color::from(0.4, 0.2, 0.1)));
-    let mat3 = Arc::new(Metal::from(Color::from(0.7, 0.6, 0.5), 0.));
-    world.push(Sphere::from(Point3::from(0., 1., 0.), 1., Arc::clone(&mat1)));
-    world.push(Sphere::from(Point3::from(-4., 1., 0.), 1., Arc::clone(&mat2)));
-    world.push(Sphere::from(Point3::from(4., 1., 0.), 1., Arc::clone(&mat3)));
+    let mat1 = add_material(&mut scene, "glass",
+                            MaterialDescription::Dielectric {refraction: 1.5});
+    let mat2 = add_material(&mut scene, "brown", MaterialDescription::Lambertian {
+        albedo: Color::from(0.4, 0.2, 0.1),
+    });
+    let mat3 = add_material(&mut scene, "bronze", MaterialDescription::Metal {
+        albedo: Color::from(0.7, 0.6, 0.5),
+        fuzz: 0.,
+    });
+    scene.add_object(Shape::Sphere {center: Point3::from(0., 1., 0.), radius: 1.}, mat1);
+    scene.add_object(Shape::Sphere {center: Point3::from(-4., 1., 0.), radius: 1.}, mat2);
+    scene.add_object(Shape::Sphere {center: Point3::from(4., 1., 0.), radius: 1.}, mat3);
 
-    world
+    scene
 }
 
-fn ray_color<T: Hittable>(ray: &Ray, world: &T, depth: u32) -> Color {
-    // Check if we've exceeded the 'bounce limit'
-    if depth == 0 {
-        return Color::new();
+// Logic for pasring command line image generation arguments
+fn parse_args(args: &[String]) -> ImageArgs {
+    // Basic help message
+    fn help() {
+        eprintln!("expected usage: rust_ray_trace <image width> <samples> <maximum depth> \
+                    <filename> [options]");
+        eprintln!("options:");
+        eprintln!("    --time-limit <seconds>    keep adding samples until the time runs \
+                    out, taking at most <samples>");
+        eprintln!("    --resume                  continue rendering from <filename>.ckpt");
+        eprintln!("    --checkpoint <seconds>    write <filename>.ckpt at most this often \
+                    (default {} with --resume, otherwise never)", DEFAULT_CHECKPOINT_SECS);
+        eprintln!("    --progressive             render one sample per pixel per pass and \
+                    write previews along the way");
+        eprintln!("    --preview <filename>      write previews here instead of <filename>");
+        eprintln!("    --preview-passes <n>      write a preview every n passes");
+        eprintln!("    --preview-interval <seconds>  write a preview every so many seconds \
+                    (default {})", DEFAULT_PREVIEW_SECS);
+        eprintln!("    --exposure <stops>        scale the image by 2^stops before tone \
+                    mapping (default 0)");
+        eprintln!("    --tonemap <operator>      one of clamp, reinhard, reinhard-extended, \
+                    aces or hable (default clamp)");
+        eprintln!("    --white <value>           white point for reinhard-extended \
+                    (default {})", DEFAULT_WHITE_POINT);
+        eprintln!("    --gamut <color space>     one of srgb, p3 or rec2020 (default srgb)");
+        eprintln!("    --format <format>         one of ppm or png (default ppm)");
+        eprintln!("    --filter <filter>         one of box, tent, gaussian, mitchell or \
+                    lanczos (default box)");
+        eprintln!("    --filter-radius <pixels>  radius of the reconstruction filter");
+        eprintln!("    --aovs                    also write depth, normal, albedo, ID, \
+                    position and lighting passes to <filename>.exr");
+        eprintln!("    --ortho <width>           use an orthographic camera seeing <width> \
+                    world units across");
+        eprintln!("    --camera <model>          one of perspective, equirect, fisheye or \
+                    cubemap (default perspective)");
+        eprintln!("    --fov <degrees>           field of view of the fisheye camera \
+                    (default {})", DEFAULT_FISHEYE_FOV);
+        eprintln!("    --stereo <layout>         render a left and right eye, either as \
+                    separate images or packed side-by-side or top-bottom");
+        eprintln!("    --ipd <distance>          distance between the eyes (default {})",
+                    DEFAULT_IPD);
+        eprintln!("    --convergence <distance>  distance at which the eyes converge \
+                    (default {})", DEFAULT_CONVERGENCE);
+        eprintln!("    --scene <file>            read camera settings and keyframes from a \
+                    scene description");
+        eprintln!("    --frames <start>-<end>    render this range of frames of an animated \
+                    scene as a numbered sequence");
+        eprintln!("    --save-scene <file>       write the scene as rendered, camera and \
+                    objects included, to a scene description");
+        eprintln!("    --gltf <file>             render the scene in a glTF or GLB file, seen \
+                    through its first camera, in place of the random spheres");
+        eprintln!("    --focal-length <mm>       describe the camera photographically, with \
+                    this lens focal length");
+        eprintln!("    --sensor <width>x<height> sensor size in mm (default 36x24)");
+        eprintln!("    --f-stop <n>              f-number of the lens (default 16)");
+        eprintln!("    --shutter <seconds>       shutter speed, such as 1/125 (default 1/100)");
+        eprintln!("    --iso <n>                 sensor sensitivity (default 100)");
+        eprintln!("    --blades <n>              use a polygonal aperture with n blades");
+        eprintln!("    --blade-rotation <degrees>  rotate the aperture blades (default 0)");
+        eprintln!("    --aperture-mask <image>   take the aperture shape from a grayscale \
+                    PNG or PPM image");
+        eprintln!("    --cats-eye <strength>     clip the aperture toward the edges of the \
+                    image, from 0 (off) up to 2");
+        eprintln!("    --toe-in                  converge by rotating the eyes inward rather \
+                    than shifting their image windows");
+        eprintln!("    --denoise                 filter out noise, guided by normals and \
+                    albedo, before tone mapping");
     }
 
-    if let Some(record) = world.hit(ray, 0.001, f32::INFINITY) {
-        let mut scattered = Ray::new();
-        let mut attenuation = Color::new();
-        
-        if record.material.scatter(ray, &record, &mut attenuation, &mut scattered) {
-            return attenuation * ray_color(&scattered, world, depth-1);
-        } else {
-            return Color::new();
+    // Parse a single argument, error and exit if not properly formatted
+    fn parse_value<T: FromStr>(arg: &str) -> T {
+        match arg.parse() {
+            Ok(n) => {
+                n
+            },
+            Err(_) => {
+                eprintln!("Error parsing arguments: Expected a number, \
+                            received \"{}\"", arg);
+                help();
+                process::exit(1);
+            },
         }
     }
 
-    let unit_direction = unit_vector(*ray.direction());
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * Color::from(1.0, 1.0, 1.0) + t * Color::from(0.5, 0.7, 1.0)
-}
+    // Parse a shutter speed given either in seconds or as a fraction like 1/125
+    fn parse_shutter(arg: &str) -> f32 {
+        match arg.split_once('/') {
+            Some((n, d)) => parse_value::<f32>(n) / parse_value::<f32>(d),
+            None => parse_value(arg),
+        }
+    }
 
-// Logic for pasring command line image generation arguments
-fn parse_args(args: &Vec<String>) -> ImageArgs {
-    // Basic help message
-    fn help() {
-        eprintln!("expected usage: rust_ray_trace <image width> <samples> <maximum depth> \
-                    <filename>");
+    // Fetch the value following an option, error and exit if there isn't one
+    fn next_value<'a>(option: &str, options: &mut Iter<'a, String>) -> &'a str {
+        match options.next() {
+            Some(value) => value,
+            None => {
+                eprintln!("Error parsing arguments: Expected a value after \"{}\"", option);
+                help();
+                process::exit(1);
+            },
+        }
     }
     
     // Error and exit if not enough args
@@ -122,115 +276,780 @@ fn parse_args(args: &Vec<String>) -> ImageArgs {
     }
 
     // Parse args, error and exit if not properly formatted
-    let width: u32 = match args[1].parse() {
-        Ok(n) => {
-            n
+    let width: u32 = parse_value(&args[1]);
+    let samples: u32 = parse_value(&args[2]);
+    let max_depth: u32 = parse_value(&args[3]);
+    let filename = args[4].clone();
+
+    // Parse any trailing options
+    let mut resume = false;
+    let mut checkpoint_secs: Option<u64> = None;
+    let mut progressive = false;
+    let mut time_limit: Option<f32> = None;
+    let mut preview = None;
+    let mut preview_passes: Option<u32> = None;
+    let mut preview_secs: Option<u64> = None;
+    let mut exposure = 0.;
+    let mut tone_map = "clamp";
+    let mut white = DEFAULT_WHITE_POINT;
+    let mut gamut = "srgb";
+    let mut format = "ppm";
+    let mut filter = "box";
+    let mut filter_radius: Option<f32> = None;
+    let mut aovs = false;
+    let mut denoise = false;
+    let mut ortho_width: Option<f32> = None;
+    let mut camera = "perspective";
+    let mut fov = DEFAULT_FISHEYE_FOV;
+    let mut stereo = None;
+    let mut ipd = DEFAULT_IPD;
+    let mut convergence = DEFAULT_CONVERGENCE;
+    let mut toe_in = false;
+    let mut blades: Option<u32> = None;
+    let mut blade_rotation = 0.;
+    let mut aperture_mask = None;
+    let mut cats_eye = 0.;
+    let mut physical: Option<PhysicalCamera> = None;
+    let mut scene = None;
+    let mut frames = None;
+    let mut gltf = None;
+    let mut save_scene = None;
+    let mut options = args[5..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--resume" => resume = true,
+            "--checkpoint" => {
+                checkpoint_secs = Some(parse_value(next_value(option, &mut options)))
+            },
+            "--progressive" => progressive = true,
+            "--time-limit" => time_limit = Some(parse_value(next_value(option, &mut options))),
+            "--preview" => preview = Some(next_value(option, &mut options).to_string()),
+            "--preview-passes" => {
+                preview_passes = Some(parse_value(next_value(option, &mut options)))
+            },
+            "--preview-interval" => {
+                preview_secs = Some(parse_value(next_value(option, &mut options)))
+            },
+            "--exposure" => exposure = parse_value(next_value(option, &mut options)),
+            "--tonemap" => tone_map = next_value(option, &mut options),
+            "--white" => white = parse_value(next_value(option, &mut options)),
+            "--gamut" => gamut = next_value(option, &mut options),
+            "--format" => format = next_value(option, &mut options),
+            "--filter" => filter = next_value(option, &mut options),
+            "--aovs" => aovs = true,
+            "--denoise" => denoise = true,
+            "--ortho" => ortho_width = Some(parse_value(next_value(option, &mut options))),
+            "--camera" => camera = next_value(option, &mut options),
+            "--fov" => fov = parse_value(next_value(option, &mut options)),
+            "--stereo" => stereo = Some(next_value(option, &mut options)),
+            "--ipd" => ipd = parse_value(next_value(option, &mut options)),
+            "--convergence" => convergence = parse_value(next_value(option, &mut options)),
+            "--toe-in" => toe_in = true,
+            "--blades" => blades = Some(parse_value(next_value(option, &mut options))),
+            "--blade-rotation" => {
+                blade_rotation = parse_value(next_value(option, &mut options))
+            },
+            "--aperture-mask" => aperture_mask = Some(next_value(option, &mut options)),
+            "--scene" => scene = Some(next_value(option, &mut options)),
+            "--frames" => frames = Some(next_value(option, &mut options)),
+            "--gltf" => gltf = Some(next_value(option, &mut options)),
+            "--save-scene" => save_scene = Some(next_value(option, &mut options).to_string()),
+            "--focal-length" => {
+                physical.get_or_insert_with(Default::default).focal_length =
+                    parse_value(next_value(option, &mut options))
+            },
+            "--sensor" => {
+                let value = next_value(option, &mut options);
+                let (w, h) = match value.split_once('x') {
+                    Some(size) => size,
+                    None => {
+                        eprintln!("Error parsing arguments: Expected a sensor size like \
+                                    36x24, received \"{}\"", value);
+                        help();
+                        process::exit(1);
+                    },
+                };
+                let lens = physical.get_or_insert_with(Default::default);
+                lens.sensor_width = parse_value(w);
+                lens.sensor_height = parse_value(h);
+            },
+            "--f-stop" => {
+                physical.get_or_insert_with(Default::default).f_number =
+                    parse_value(next_value(option, &mut options))
+            },
+            "--shutter" => {
+                physical.get_or_insert_with(Default::default).shutter =
+                    parse_shutter(next_value(option, &mut options))
+            },
+            "--iso" => {
+                physical.get_or_insert_with(Default::default).iso =
+                    parse_value(next_value(option, &mut options))
+            },
+            "--cats-eye" => cats_eye = parse_value(next_value(option, &mut options)),
+            "--filter-radius" => {
+                filter_radius = Some(parse_value(next_value(option, &mut options)))
+            },
+            _ => {
+                eprintln!("Error parsing arguments: Unknown option \"{}\"", option);
+                help();
+                process::exit(1);
+            },
+        }
+    }
+
+    // Error and exit if args are not "sane" size"
+    if width < 100 {
+        eprintln!("Error parsing arguments: image width should be at least 100 pixels");
+        help();
+        process::exit(1);
+    }
+    if samples < 1 {
+        eprintln!("Error parsing arguments: samples should be at least 1");
+        help();
+        process::exit(1);
+    }
+    if max_depth < 1 {
+        eprintln!("Error parsing arguments: maximum depth should be at least 1");
+        help();
+        process::exit(1);
+    }
+    if time_limit.is_some_and(|t| t <= 0.) {
+        eprintln!("Error parsing arguments: time limit should be positive");
+        help();
+        process::exit(1);
+    }
+    if preview_passes == Some(0) {
+        eprintln!("Error parsing arguments: preview passes should be at least 1");
+        help();
+        process::exit(1);
+    }
+
+    let scene = scene.map(|path| match SceneDescription::load(path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("Error reading scene \"{}\": {}", path, e);
+            process::exit(1);
+        },
+    });
+
+    if gltf.is_some() && save_scene.is_some() {
+        eprintln!("Error parsing arguments: glTF scenes can't be saved as scene descriptions");
+        help();
+        process::exit(1);
+    }
+    let gltf = gltf.map(|path| match gltf::load(path) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("Error reading glTF scene \"{}\": {}", path, e);
+            process::exit(1);
+        },
+    });
+    // The projection of the scene description, or else of the glTF camera, is kept unless
+    // another model is asked for
+    // A lens given on the command line replaces the scene description's as a whole
+    let physical = physical.or_else(|| scene.as_ref().and_then(|scene| scene.lens));
+    let ortho_width = ortho_width.or_else(|| match camera {
+        "perspective" => match scene.as_ref().filter(|scene| scene.projection.is_some()) {
+            Some(scene) => scene.view_width,
+            None => gltf.as_ref().and_then(|scene| scene.cameras.first())
+                        .and_then(|camera| camera.orthographic_width),
         },
-        Err(_) => {
-            eprintln!("Error parsing arguments: Expected a positive integer, \
-                        received \"{}\"", args[1]);
+        _ => None,
+    });
+
+    if ortho_width.is_some_and(|w| w <= 0.) {
+        eprintln!("Error parsing arguments: orthographic view width should be positive");
+        help();
+        process::exit(1);
+    }
+    if fov <= 0. || fov > 360. {
+        eprintln!("Error parsing arguments: fisheye field of view should be between 0 and \
+                    360 degrees");
+        help();
+        process::exit(1);
+    }
+    let camera = match (camera, ortho_width) {
+        ("perspective", None) => CameraKind::Perspective,
+        ("perspective", Some(width)) => CameraKind::Orthographic {width},
+        ("equirect", None) => CameraKind::Equirectangular,
+        ("fisheye", None) => CameraKind::Fisheye {fov},
+        ("cubemap", None) => CameraKind::Cubemap,
+        ("equirect" | "fisheye" | "cubemap", Some(_)) => {
+            eprintln!("Error parsing arguments: --ortho cannot be combined with the {} \
+                        camera", camera);
             help();
             process::exit(1);
         },
-    };
-    let height = (width as f32 / ASPECT_RATIO) as u32;
-    let samples: u32 = match args[2].parse() {
-        Ok(n) => {
-            n
-        },
-        Err(_) => {
-            eprintln!("Error parsing arguments: Expected a positive integer, \
-                        received \"{}\"", args[2]);
+        _ => {
+            eprintln!("Error parsing arguments: Unknown camera \"{}\"", camera);
             help();
             process::exit(1);
         },
     };
-    let max_depth: u32 = match args[3].parse() {
-        Ok(n) => {
-            n
+    let height = (width as f32 / camera.aspect_ratio()) as u32;
+
+    if let Some(lens) = &physical {
+        if !matches!(camera, CameraKind::Perspective) {
+            eprintln!("Error parsing arguments: photographic settings only apply to the \
+                        perspective camera");
+            help();
+            process::exit(1);
+        }
+        let values = [lens.focal_length, lens.sensor_width, lens.sensor_height,
+                      lens.f_number, lens.shutter, lens.iso];
+        if values.iter().any(|&v| v <= 0. || !v.is_finite()) {
+            eprintln!("Error parsing arguments: photographic settings should be positive");
+            help();
+            process::exit(1);
+        }
+    }
+
+    if ipd < 0. {
+        eprintln!("Error parsing arguments: interocular distance should not be negative");
+        help();
+        process::exit(1);
+    }
+    if convergence <= 0. {
+        eprintln!("Error parsing arguments: convergence distance should be positive");
+        help();
+        process::exit(1);
+    }
+    if !(0. ..2.).contains(&cats_eye) {
+        eprintln!("Error parsing arguments: cat's-eye strength should be from 0 up to 2");
+        help();
+        process::exit(1);
+    }
+    let aperture = match (blades, aperture_mask) {
+        (None, None) => Aperture::Circular,
+        (Some(blades), None) if blades >= 3 => {
+            Aperture::Polygon {blades, rotation: blade_rotation}
         },
-        Err(_) => {
-            eprintln!("Error parsing arguments: Expected a positive integer, \
-                        received \"{}\"", args[3]);
+        (Some(_), None) => {
+            eprintln!("Error parsing arguments: aperture should have at least 3 blades");
+            help();
+            process::exit(1);
+        },
+        (None, Some(path)) => match ApertureMask::load(path) {
+            Ok(mask) => Aperture::Mask(mask),
+            Err(e) => {
+                eprintln!("Error reading aperture mask \"{}\": {}", path, e);
+                process::exit(1);
+            },
+        },
+        (Some(_), Some(_)) => {
+            eprintln!("Error parsing arguments: --blades cannot be combined with \
+                        --aperture-mask");
             help();
             process::exit(1);
         },
     };
-    let filename = args[4].clone();
 
-    // Error and exit if args are not "sane" size"
-    if width < 100 {
-        eprintln!("Error parsing arguments: image width should be at least 100 pixels");
+    let frames = match frames {
+        Some(range) => {
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (parse_value(start), parse_value(end)),
+                None => {
+                    let frame = parse_value(range);
+                    (frame, frame)
+                },
+            };
+            if end < start {
+                eprintln!("Error parsing arguments: last frame comes before the first");
+                help();
+                process::exit(1);
+            }
+            if scene.is_none() {
+                eprintln!("Error parsing arguments: --frames needs a --scene to animate");
+                help();
+                process::exit(1);
+            }
+            Some((start, end))
+        },
+        None => scene.as_ref().and_then(|scene| scene.frame_range()),
+    };
+
+    let stereo = stereo.map(|layout| {
+        let layout = match layout {
+            "separate" => StereoLayout::Separate,
+            "side-by-side" => StereoLayout::SideBySide,
+            "top-bottom" => StereoLayout::TopBottom,
+            _ => {
+                eprintln!("Error parsing arguments: Unknown stereo layout \"{}\"", layout);
+                help();
+                process::exit(1);
+            },
+        };
+        if !matches!(camera, CameraKind::Perspective | CameraKind::Equirectangular) {
+            eprintln!("Error parsing arguments: stereo is only supported by the \
+                        perspective and equirect cameras");
+            help();
+            process::exit(1);
+        }
+        let method = if toe_in {Convergence::ToeIn} else {Convergence::OffAxis};
+        (StereoRig::new(ipd, convergence, method), layout)
+    });
+
+    if white <= 0. {
+        eprintln!("Error parsing arguments: white point should be positive");
         help();
         process::exit(1);
     }
-    if samples < 1 {
-        eprintln!("Error parsing arguments: samples should be at least 1");
+    let tone_map = match tone_map {
+        "clamp" => ToneMap::Clamp,
+        "reinhard" => ToneMap::Reinhard,
+        "reinhard-extended" => ToneMap::ExtendedReinhard {white},
+        "aces" => ToneMap::Aces,
+        "hable" => ToneMap::Hable,
+        _ => {
+            eprintln!("Error parsing arguments: Unknown tone mapping operator \"{}\"",
+                        tone_map);
+            help();
+            process::exit(1);
+        },
+    };
+
+    let gamut = match gamut {
+        "srgb" => Gamut::Srgb,
+        "p3" => Gamut::DisplayP3,
+        "rec2020" => Gamut::Rec2020,
+        _ => {
+            eprintln!("Error parsing arguments: Unknown color space \"{}\"", gamut);
+            help();
+            process::exit(1);
+        },
+    };
+    let format = match format {
+        "ppm" => ImageFormat::Ppm,
+        "png" => ImageFormat::Png,
+        _ => {
+            eprintln!("Error parsing arguments: Unknown image format \"{}\"", format);
+            help();
+            process::exit(1);
+        },
+    };
+
+    let filter = match filter {
+        "box" => FilterKind::Box,
+        "tent" => FilterKind::Tent,
+        "gaussian" => FilterKind::Gaussian,
+        "mitchell" => FilterKind::Mitchell,
+        "lanczos" => FilterKind::Lanczos,
+        _ => {
+            eprintln!("Error parsing arguments: Unknown filter \"{}\"", filter);
+            help();
+            process::exit(1);
+        },
+    };
+    let filter_radius = filter_radius.unwrap_or(filter.default_radius());
+    if filter_radius <= 0. {
+        eprintln!("Error parsing arguments: filter radius should be positive");
         help();
         process::exit(1);
     }
-    if max_depth < 1 {
-        eprintln!("Error parsing arguments: maximum depth should be at least 1");
-        help();
-        process::exit(1);
+
+    // Fall back on timed previews unless only a pass count was given
+    if preview_passes.is_none() && preview_secs.is_none() {
+        preview_secs = Some(DEFAULT_PREVIEW_SECS);
     }
 
     ImageArgs {
         width,
         height,
-        samples,
+        budget: match time_limit {
+            Some(secs) => Budget::Time {
+                limit: Duration::from_secs_f32(secs),
+                max_samples: samples,
+            },
+            None => Budget::Samples(samples),
+        },
         max_depth,
+        resume,
+        // Checkpoints are only worth writing for renders that may be continued later
+        checkpoint_interval: checkpoint_secs.or(resume.then_some(DEFAULT_CHECKPOINT_SECS))
+                                            .map(Duration::from_secs),
+        progressive,
+        preview_filename: preview.unwrap_or_else(|| filename.clone()),
         filename,
+        preview_passes,
+        preview_interval: preview_secs.map(Duration::from_secs),
+        // Photographic exposure adds to any adjustment given in stops
+        display: Display {
+            exposure: exposure + physical.map_or(0., |lens| lens.exposure()),
+            tone_map,
+            gamut,
+        },
+        format,
+        filter: Filter::new(filter, filter_radius),
+        aovs,
+        denoise,
+        camera,
+        stereo,
+        aperture,
+        cats_eye,
+        physical,
+        scene,
+        frames,
+        gltf,
+        save_scene,
+    }
+}
+
+// Denoises a copy of the film if asked to, leaving the accumulated samples untouched
+fn denoised_film(state: &RenderState, image_args: &ImageArgs) -> Option<Film> {
+    match (&state.aovs, image_args.denoise) {
+        (Some(aovs), true) => Some(denoise(&state.film, aovs)),
+        _ => None,
+    }
+}
+
+// A single image rendered through one camera, like one eye of a stereo pair
+struct View {
+    camera: Box<dyn CameraModel>,
+    filename: String,
+    preview_filename: String,
+    checkpoint_path: String,
+}
+
+impl View {
+    fn new(camera: Box<dyn CameraModel>, filename: &str, preview_filename: &str) -> Self {
+        View {
+            camera,
+            filename: filename.to_string(),
+            preview_filename: preview_filename.to_string(),
+            checkpoint_path: format!("{}.ckpt", filename),
+        }
+    }
+
+    // Restore a previous render if requested, otherwise start from scratch. Also says
+    // whether the state came from a checkpoint.
+    fn load_state(&self, image_args: &ImageArgs) -> (RenderState, bool) {
+        let new_state = || RenderState::new(image_args.width, image_args.height,
+                                            image_args.filter,
+                                            image_args.aovs || image_args.denoise);
+        if !image_args.resume {
+            return (new_state(), false);
+        }
+
+        match checkpoint::load(&self.checkpoint_path, image_args.width, image_args.height,
+                               image_args.filter, image_args.aovs || image_args.denoise) {
+            Ok(state) => {
+                eprintln!("Resuming from \"{}\" with {} samples completed...",
+                            self.checkpoint_path, state.min_samples());
+                (state, true)
+            },
+            // Frames of a sequence which were never started have no checkpoint
+            Err(e) if e.kind() == ErrorKind::NotFound && image_args.frames.is_some() => {
+                (new_state(), false)
+            },
+            Err(e) => {
+                eprintln!("Error reading checkpoint \"{}\": {}", self.checkpoint_path, e);
+                process::exit(1);
+            },
+        }
+    }
+
+    // Actually generate image data, checkpointing between passes. Progressive renders
+    // spread single samples over the whole image each pass and write previews as they go.
+    fn render<T: Hittable>(&self, state: &mut RenderState, world: &T, image_args: &ImageArgs,
+                           budget: &Budget) {
+        let pass_samples = if image_args.progressive {1} else {PASS_SAMPLES};
+        let max_samples = budget.max_samples();
+        let mut passes = 0;
+        let start = Instant::now();
+        let mut sample_secs: Option<f32> = None;
+        let mut last_checkpoint = Instant::now();
+        let mut last_preview = Instant::now();
+        while state.min_samples() < max_samples {
+            // Under a time limit, only take as many samples as the measured cost of the
+            // previous pass suggests will fit in the remaining time. Until that cost is
+            // known a single sample is taken to measure it, which always happens so that
+            // the image isn't left empty, and so is all a very short limit overshoots by.
+            let mut samples = pass_samples;
+            if let Budget::Time {limit, ..} = budget {
+                let remaining = limit.saturating_sub(start.elapsed()).as_secs_f32();
+                let affordable = match sample_secs {
+                    Some(secs) => (remaining / secs) as u32,
+                    None if passes == 0 => 1,
+                    None => 0,
+                };
+                if affordable == 0 {
+                    break;
+                }
+                samples = samples.min(affordable);
+            }
+
+            let pass_start = Instant::now();
+            let taken = state.min_samples();
+            state.render_pass(world, &*self.camera, samples, max_samples,
+                              image_args.max_depth);
+            let taken = state.min_samples() - taken;
+            if taken > 0 {
+                sample_secs = Some(pass_start.elapsed().as_secs_f32() / taken as f32);
+            }
+            passes += 1;
+
+            if image_args.progressive {
+                let pass_due = image_args.preview_passes.is_some_and(|n| passes % n == 0);
+                let time_due = image_args.preview_interval
+                                         .is_some_and(|t| last_preview.elapsed() >= t);
+                if pass_due || time_due {
+                    let denoised = denoised_film(state, image_args);
+                    match write_image(&self.preview_filename,
+                                      denoised.as_ref().unwrap_or(&state.film),
+                                      &image_args.display, image_args.format) {
+                        Ok(_) => eprintln!("Preview with {} samples written to \"{}.{}\"",
+                                            state.min_samples(), self.preview_filename,
+                                            image_args.format.extension()),
+                        Err(e) => eprintln!("Error writing preview: {}", e),
+                    }
+                    last_preview = Instant::now();
+                }
+            }
+
+            if image_args.checkpoint_interval.is_some_and(|t| last_checkpoint.elapsed() >= t) {
+                if let Err(e) = checkpoint::save(&self.checkpoint_path, state) {
+                    eprintln!("Error writing checkpoint: {}", e);
+                }
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        if let Budget::Time {..} = budget {
+            eprintln!("Took {} samples in {:.1}s", state.min_samples(),
+                        start.elapsed().as_secs_f32());
+        }
+
+        // Leave a final checkpoint so more samples can be added later
+        if image_args.checkpoint_interval.is_some() {
+            if let Err(e) = checkpoint::save(&self.checkpoint_path, state) {
+                eprintln!("Error writing checkpoint: {}", e);
+            }
+        }
+    }
+}
+
+// Cameras for every view of a single frame, with one view per eye for stereo renders
+fn make_views(image_args: &ImageArgs, pose: &CameraPose, aperture: f32, filename: &str,
+              preview_filename: &str) -> Vec<View> {
+    let CameraPose {view_from, view_at, vfov, focus_dist} = *pose;
+    let view_up = Vec3::from(0., 1., 0.);
+    let aspect_ratio = image_args.camera.aspect_ratio();
+    let lens = |camera: Camera| {
+        camera.aperture_shape(image_args.aperture.clone()).cats_eye(image_args.cats_eye)
+    };
+
+    match &image_args.stereo {
+        None => {
+            let camera: Box<dyn CameraModel> = match image_args.camera {
+                CameraKind::Perspective => {
+                    Box::new(lens(Camera::new(view_from, view_at, view_up, vfov,
+                                              aspect_ratio, aperture, focus_dist)))
+                },
+                CameraKind::Orthographic {width} => {
+                    Box::new(Camera::orthographic(view_from, view_at, view_up, width,
+                                                  aspect_ratio))
+                },
+                CameraKind::Equirectangular => {
+                    Box::new(Equirectangular::new(view_from, view_at, view_up))
+                },
+                CameraKind::Fisheye {fov} => {
+                    Box::new(Fisheye::new(view_from, view_at, view_up, fov, aspect_ratio))
+                },
+                CameraKind::Cubemap => Box::new(Cubemap::new(view_from)),
+            };
+            vec![View::new(camera, filename, preview_filename)]
+        },
+        Some((rig, _)) => [Eye::Left, Eye::Right].into_iter().map(|eye| {
+            let camera: Box<dyn CameraModel> = match image_args.camera {
+                CameraKind::Equirectangular => {
+                    Box::new(rig.omnidirectional(eye, view_from, view_at, view_up))
+                },
+                _ => Box::new(lens(rig.perspective(eye, view_from, view_at, view_up, vfov,
+                                                   aspect_ratio, aperture, focus_dist))),
+            };
+            View::new(camera, &format!("{}_{}", filename, eye.name()),
+                      &format!("{}_{}", preview_filename, eye.name()))
+        }).collect(),
+    }
+}
+
+// Write final generated image, packing stereo pairs into one image if asked to
+fn write_outputs(views: &[View], states: &[RenderState], image_args: &ImageArgs,
+                 filename: &str) {
+    let denoised: Vec<Option<Film>> = states.iter()
+                                            .map(|state| denoised_film(state, image_args))
+                                            .collect();
+    let films: Vec<&Film> = states.iter().zip(denoised.iter())
+                                  .map(|(state, film)| film.as_ref().unwrap_or(&state.film))
+                                  .collect();
+    let packed = match &image_args.stereo {
+        Some((_, layout)) => layout.pack(films[0], films[1]),
+        None => None,
+    };
+    match packed {
+        Some(film) => write_final_image(filename, &film, image_args),
+        None => {
+            for (view, film) in views.iter().zip(films) {
+                write_final_image(&view.filename, film, image_args);
+            }
+        },
+    }
+
+    for (view, state) in views.iter().zip(states.iter()) {
+        if let (Some(aovs), true) = (&state.aovs, image_args.aovs) {
+            match write_layers(&view.filename, &state.film, aovs) {
+                Ok(_) => eprintln!("AOVs written to \"{}.exr\"", view.filename),
+                Err(e) => eprintln!("Error writing AOVs: {}", e),
+            }
+        }
+    }
+}
+
+fn write_final_image(filename: &str, film: &Film, image_args: &ImageArgs) {
+    match write_image(filename, film, &image_args.display, image_args.format) {
+        Ok(_) => eprintln!(
+                    "Image generated successfully! Output written to \"{}.{}\"",
+                        filename, image_args.format.extension()),
+        Err(_) => eprintln!("Error writing image data"),
     }
 }
 
 fn main() {
     // Parse command line arguments
     let args: Vec<String> = env::args().collect();
-    let image_args = parse_args(&args);
-
-    // Create randomized scene
-    eprintln!("Beginning {} x {} image with {} samples and a maximum depth of {}...",
-                image_args.width, image_args.height, image_args.samples,
-                image_args.max_depth);
-    let world = random_scene();
-
-    // Setup Camera
-    let view_from = Point3::from(13., 2., 3.);
-    let view_at = Point3::from(0., 0., 0.);
-    let camera = Camera::new(view_from, view_at, Vec3::from(0., 1., 0.), 20.,
-                             ASPECT_RATIO, 0.1, 10.);
-
-    // Actually generate image data
-    let ppm_header = format!("P3\n{} {}\n255\n", // 255 denotes maximum color value
-                                image_args.width, image_args.height);
-
-    eprintln!("Generating...");
-    let image_data =
-        (0..image_args.height).into_par_iter().rev().map(|i| {
-            (0..image_args.width).into_par_iter().map(|j| {
-                let mut rng = rand::thread_rng();
-                let mut pixel_color = Color::new();
-                for _ in 0..image_args.samples {
-                    let r1: f32 = rng.gen();
-                    let r2: f32 = rng.gen();
-                    let u = (j as f32 + r1) / (image_args.width - 1) as f32;
-                    let v = (i as f32 + r2) / (image_args.height - 1) as f32;
-
-                    let ray = camera.get_ray(u, v);
-                    pixel_color += ray_color(&ray, &world, image_args.max_depth);
+    let mut image_args = parse_args(&args);
+    let gltf = image_args.gltf.take();
+
+    match image_args.budget {
+        Budget::Samples(samples) => eprintln!(
+            "Beginning {} x {} image with {} samples and a maximum depth of {}...",
+            image_args.width, image_args.height, samples, image_args.max_depth),
+        Budget::Time {limit, max_samples} => eprintln!(
+            "Beginning {} x {} image with a {:.1}s time limit, at most {} samples and a \
+            maximum depth of {}...", image_args.width, image_args.height,
+            limit.as_secs_f32(), max_samples, image_args.max_depth),
+    }
+
+    // Setup Camera, which the scene description may move from frame to frame
+    let aspect_ratio = image_args.camera.aspect_ratio();
+    let (vfov, aperture) = match &image_args.physical {
+        Some(physical) => {
+            eprintln!("Lens has a {:.1}° vertical field of view, a {:.1}mm aperture and \
+                        exposure of {:+.1} stops", physical.vfov(aspect_ratio),
+                        physical.aperture() * 1000., physical.exposure());
+            (physical.vfov(aspect_ratio), physical.aperture())
+        },
+        None => (20., 0.1),
+    };
+    let base_pose = match gltf.as_ref().and_then(|scene| scene.cameras.first()) {
+        Some(camera) => CameraPose {
+            vfov: if image_args.physical.is_some() {vfov} else {camera.pose.vfov},
+            ..camera.pose
+        },
+        None => CameraPose {
+            view_from: Point3::from(13., 2., 3.),
+            view_at: Point3::from(0., 0., 0.),
+            vfov,
+            focus_dist: 10.,
+        },
+    };
+
+    let frames: Vec<Option<u32>> = match image_args.frames {
+        Some((start, end)) => {
+            eprintln!("Rendering frames {} to {}...", start, end);
+            (start..=end).map(Some).collect()
+        },
+        None => vec![None],
+    };
+
+    // Any time limit is split evenly between every frame and view
+    let view_count = if image_args.stereo.is_some() {2} else {1};
+    let budget = match image_args.budget {
+        Budget::Time {limit, max_samples} => Budget::Time {
+            limit: limit / (frames.len() * view_count) as u32,
+            max_samples,
+        },
+        Budget::Samples(samples) => Budget::Samples(samples),
+    };
+
+    let mut scene_seed: Option<u64> = image_args.scene.as_ref().and_then(|scene| scene.seed);
+    let mut world: Option<HittableList> = gltf.map(|scene| scene.world);
+    for (i, &frame) in frames.iter().enumerate() {
+        let pose = match &image_args.scene {
+            Some(scene) => scene.camera(frame.unwrap_or(0) as f32, &base_pose),
+            None => base_pose,
+        };
+        let (filename, preview_filename) = match frame {
+            Some(frame) => {
+                eprintln!("Frame {} ({} of {})...", frame, i + 1, frames.len());
+                (format!("{}_{:04}", image_args.filename, frame),
+                 format!("{}_{:04}", image_args.preview_filename, frame))
+            },
+            None => (image_args.filename.clone(), image_args.preview_filename.clone()),
+        };
+        let views = make_views(&image_args, &pose, aperture, &filename, &preview_filename);
+
+        // Every view of every frame must see the same scene, so they all share one seed,
+        // taken from the scene description or else the first checkpoint or view
+        let (mut states, restored): (Vec<RenderState>, Vec<bool>) =
+            views.iter().map(|view| view.load_state(&image_args)).unzip();
+        let first = restored.iter().position(|&r| r).unwrap_or(0);
+        let seed = *scene_seed.get_or_insert(states[first].scene_seed);
+        if states.iter().zip(&restored).any(|(state, &r)| r && state.scene_seed != seed) {
+            eprintln!("Error reading checkpoints: views were rendered from different scenes");
+            process::exit(1);
+        }
+        for state in states.iter_mut() {
+            state.scene_seed = seed;
+        }
+
+        // Build the scene description's objects, or else the randomized scene, unless a
+        // glTF scene was loaded
+        let world = world.get_or_insert_with(|| {
+            let mut scene = image_args.scene.clone().unwrap_or_default();
+            if scene.objects.is_empty() {
+                let random = random_scene(seed);
+                scene.materials = random.materials;
+                scene.objects = random.objects;
+            }
+            if let Some(path) = &image_args.save_scene {
+                // Saved with the camera filled in, so rendering it again needs no defaults
+                scene.seed = Some(seed);
+                scene.position.get_or_insert(base_pose.view_from);
+                scene.target.get_or_insert(base_pose.view_at);
+                scene.fov.get_or_insert(base_pose.vfov);
+                scene.focus.get_or_insert(base_pose.focus_dist);
+                scene.lens = image_args.physical;
+                if let CameraKind::Orthographic {width} = image_args.camera {
+                    scene.projection = Some(Projection::Orthographic);
+                    scene.view_width = Some(width);
                 }
-                format_color(&pixel_color, image_args.samples) 
-            }).collect::<Vec<String>>().join("")
-        }).collect::<Vec<String>>().join("");
+                match scene.save(path) {
+                    Ok(_) => eprintln!("Scene written to \"{}\"", path),
+                    Err(e) => eprintln!("Error writing scene: {}", e),
+                }
+            }
+            scene.build()
+        });
 
-    // Write final generated image
-    let final_pic = format!("{}{}", ppm_header, image_data);
-    match fs::write(format!("{}.ppm", image_args.filename), final_pic) {
-        Ok(_) => eprintln!(
-                    "Image generated successfully! Output written to \"{}.ppm\"",
-                        image_args.filename),
-        Err(_) => eprintln!("Error writing image data"),
+        eprintln!("Generating...");
+        for (view, state) in views.iter().zip(states.iter_mut()) {
+            view.render(state, world, &image_args, &budget);
+        }
+
+        write_outputs(&views, &states, &image_args, &filename);
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_scene_survives_saving() {
+        let scene = random_scene(42);
+        let reloaded = SceneDescription::parse(&scene.to_string()).unwrap();
+        assert_eq!(format!("{:?}", reloaded.materials), format!("{:?}", scene.materials));
+        assert_eq!(format!("{:?}", reloaded.objects), format!("{:?}", scene.objects));
+    }
+}
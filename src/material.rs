@@ -1,8 +1,11 @@
+use std::sync::Arc;
+
 use rand::random;
 
 use crate::ray::Ray;
 use crate::vec3::*;
 use crate::hittable::HitRecord;
+use crate::texture::{SolidColor, Texture};
 
 pub trait Material {
     fn scatter(&self,
@@ -10,22 +13,41 @@ pub trait Material {
                record: &HitRecord, 
                attenuation: &mut Color, 
                scattered: &mut Ray) -> bool;
+
+    // Identifier written to material ID passes, given out by whoever builds the scene.
+    // Zero is left for the background and for materials which were never given one.
+    fn id(&self) -> u32;
+
+    // Base color of the surface, independent of lighting
+    fn albedo(&self, record: &HitRecord) -> Color;
 }
 
 /// Lambertian material
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
+    id: u32,
 }
 
 impl Lambertian {
     pub fn from(albedo: Color) -> Self {
-        Lambertian {albedo}
+        Lambertian::textured(Arc::new(SolidColor::from(albedo)))
+    }
+
+    /// Lambertian material whose color varies over the surface
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Lambertian {albedo, id: 0}
+    }
+
+    /// Sets the ID written to material ID passes
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
     }
 }
 
 impl Material for Lambertian {
     fn scatter(&self, 
-               incident: &Ray, 
+               _incident: &Ray, 
                record: &HitRecord, 
                attenuation: &mut Color, 
                scattered: &mut Ray) -> bool {
@@ -37,16 +59,25 @@ impl Material for Lambertian {
         }
 
         *scattered = Ray::from(record.p, scatter_dir);
-        *attenuation = self.albedo;
+        *attenuation = self.albedo.value_at(record);
 
         true
     }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Color {
+        self.albedo.value_at(record)
+    }
 }
 
 /// Metal material
 pub struct Metal {
     albedo: Color,
     fuzz: f32,
+    id: u32,
 }
 
 impl Metal {
@@ -54,8 +85,15 @@ impl Metal {
         Metal {
             albedo,
             fuzz: if fuzz < 1. {fuzz} else {1.},
+            id: 0,
         }
     }
+
+    /// Sets the ID written to material ID passes
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
 }
 
 impl Material for Metal {
@@ -70,16 +108,31 @@ impl Material for Metal {
         
         dot(scattered.direction(), &record.normal) > 0.
     }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 /// Dielectric material
 pub struct Dielectric {
     refraction: f32,
+    id: u32,
 }
 
 impl Dielectric {
     pub fn from(refraction: f32) -> Self {
-        Dielectric {refraction}
+        Dielectric {refraction, id: 0}
+    }
+
+    /// Sets the ID written to material ID passes
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
     }
 
     // Schlick approximation for reflectance
@@ -115,5 +168,90 @@ impl Material for Dielectric {
 
         true
     }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Color {
+        Color::from(1., 1., 1.)
+    }
+}
+
+/// Physically based material following the metallic-roughness model of glTF. Metals
+/// reflect tinted by the base color, while other surfaces are diffuse in the base color
+/// under a thin clear coat which reflects more at grazing angles. Roughness blurs both
+/// reflections, from a mirror at zero to a broad glossy blur at one.
+pub struct Pbr {
+    base_color: Arc<dyn Texture>,
+    // Roughness in the green channel and metalness in the blue, as glTF packs them
+    metallic_roughness: Arc<dyn Texture>,
+    id: u32,
 }
 
+impl Pbr {
+    pub fn from(base_color: Color, metallic: f32, roughness: f32) -> Self {
+        Pbr::textured(Arc::new(SolidColor::from(base_color)),
+                      Arc::new(SolidColor::from(Color::from(0., roughness, metallic))))
+    }
+
+    pub fn textured(base_color: Arc<dyn Texture>,
+                    metallic_roughness: Arc<dyn Texture>) -> Self {
+        Pbr {base_color, metallic_roughness, id: 0}
+    }
+
+    /// Sets the ID written to material ID passes
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+}
+
+impl Material for Pbr {
+    fn scatter(&self,
+               incident: &Ray,
+               record: &HitRecord,
+               attenuation: &mut Color,
+               scattered: &mut Ray) -> bool {
+        let base_color = self.base_color.value_at(record);
+        let parameters = self.metallic_roughness.value_at(record);
+        let (roughness, metallic) = (parameters.y().clamp(0., 1.), parameters.z().clamp(0., 1.));
+        let unit_direction = unit_vector(*incident.direction());
+        let cos_theta = dot(&-unit_direction, &record.normal).clamp(0., 1.);
+
+        // Each scatter picks one of the lobes, in proportion to how much light it carries
+        let specular = if random::<f32>() < metallic {
+            *attenuation = base_color;
+            true
+        } else if random::<f32>() < Dielectric::reflectance(cos_theta, 1.5) {
+            *attenuation = Color::from(1., 1., 1.);
+            true
+        } else {
+            false
+        };
+
+        if specular {
+            // Perceptual roughness is squared, as glTF does, to spread it more evenly
+            let reflected = reflect(&unit_direction, &record.normal);
+            let fuzz = roughness * roughness;
+            *scattered = Ray::from(record.p, reflected + fuzz * Vec3::random_in_unit_sphere());
+            dot(scattered.direction(), &record.normal) > 0.
+        } else {
+            let mut scatter_dir = record.normal + Vec3::random_unit_vector();
+            if scatter_dir.near_zero() {
+                scatter_dir = record.normal;
+            }
+            *scattered = Ray::from(record.p, scatter_dir);
+            *attenuation = base_color;
+            true
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn albedo(&self, record: &HitRecord) -> Color {
+        self.base_color.value_at(record)
+    }
+}
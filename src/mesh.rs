@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Color, Point3, Vec3, cross, dot, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+
+/// Mesh of polygons sharing vertices, as loaded from a file and before it's turned into
+/// triangles for rendering. Texture coordinates belong to the corners of faces rather
+/// than to vertices, so seams where a vertex has different UVs on either side survive.
+#[derive(Debug, Clone, Default)]
+pub struct PolygonMesh {
+    pub positions: Vec<Point3>,
+    /// Indices into `positions` going around each face
+    pub faces: Vec<Vec<u32>>,
+    /// UVs for each corner of each face, laid out like `faces`
+    pub uvs: Option<Vec<Vec<(f32, f32)>>>,
+    /// Normals given by the file for each vertex, used in place of ones worked out from
+    /// the faces. Reshaping the mesh drops them.
+    pub normals: Option<Vec<Vec3>>,
+    /// Linear color of each vertex
+    pub colors: Option<Vec<Color>>,
+}
+
+impl PolygonMesh {
+    /// Area weighted normal at each vertex, from the faces around it
+    pub fn vertex_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::new(); self.positions.len()];
+        for face in &self.faces {
+            let normal = self.face_normal(face);
+            for &i in face {
+                normals[i as usize] += normal;
+            }
+        }
+        normals.into_iter()
+               .map(|n| if n.near_zero() {n} else {unit_vector(n)})
+               .collect()
+    }
+
+    /// Checks every face is a proper polygon, with at least three corners, each a
+    /// different vertex within `positions`. Loaders reject meshes which fail this, since
+    /// `subdivide` relies on it.
+    pub fn validate(&self) -> Result<(), String> {
+        for (f, face) in self.faces.iter().enumerate() {
+            if face.len() < 3 {
+                return Err(format!("face {} has {} corners", f, face.len()));
+            }
+            if let Some(i) = face.iter().find(|&&i| i as usize >= self.positions.len()) {
+                return Err(format!("face {}: vertex index {} out of range", f, i));
+            }
+            if let Some((k, i)) = face.iter().enumerate().find(|(k, i)| face[..*k].contains(i)) {
+                return Err(format!("face {}: vertex {} repeats at corner {}", f, i, k));
+            }
+        }
+        Ok(())
+    }
+
+    // Normal of a face, scaled by its area, summed over a fan of triangles so it also
+    // suits polygons which aren't quite flat
+    fn face_normal(&self, face: &[u32]) -> Vec3 {
+        let p0 = self.positions[face[0] as usize];
+        face[1..].windows(2).fold(Vec3::new(), |sum, edge| {
+            let (p1, p2) = (self.positions[edge[0] as usize], self.positions[edge[1] as usize]);
+            sum + 0.5 * cross(&(p1 - p0), &(p2 - p0))
+        })
+    }
+}
+
+/// Triangles sharing vertices, with normals blended smoothly between them. Triangles are
+/// kept in a bounding volume hierarchy, so large meshes stay fast to intersect.
+///
+/// UVs come from the mesh where it has them, and are otherwise the barycentric
+/// coordinates within each triangle. Vertex colors are blended the same way and passed on
+/// with each hit, for the `VertexColor` texture.
+pub struct TriangleMesh<T: Material> {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    triangles: Vec<[u32; 3]>,
+    uvs: Option<Vec<[(f32, f32); 3]>>,
+    colors: Option<Vec<Color>>,
+    bvh: Bvh,
+    bounds: Option<Aabb>,
+    material: Arc<T>,
+}
+
+impl<T: Material> TriangleMesh<T> {
+    /// Splits every polygon into a fan of triangles around its first corner, which suits
+    /// convex polygons
+    pub fn from(mesh: &PolygonMesh, material: Arc<T>) -> Self {
+        let mut triangles = Vec::new();
+        let mut uvs = mesh.uvs.as_ref().map(|_| Vec::new());
+        for (f, face) in mesh.faces.iter().enumerate() {
+            for k in 1..face.len().saturating_sub(1) {
+                triangles.push([face[0], face[k], face[k + 1]]);
+                if let (Some(uvs), Some(corners)) = (uvs.as_mut(), mesh.uvs.as_ref()) {
+                    let corners = &corners[f];
+                    uvs.push([corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+        }
+
+        let positions = mesh.positions.clone();
+        let boxes: Vec<Aabb> = triangles.iter().map(|triangle| {
+            Aabb::around(&triangle.map(|i| positions[i as usize]))
+        }).collect();
+        let bounds = boxes.iter().copied().reduce(|a, b| a.merge(&b));
+
+        let normals = match &mesh.normals {
+            Some(normals) => normals.iter()
+                                    .map(|&n| if n.near_zero() {n} else {unit_vector(n)})
+                                    .collect(),
+            None => mesh.vertex_normals(),
+        };
+
+        TriangleMesh {
+            positions,
+            normals,
+            colors: mesh.colors.clone(),
+            bvh: Bvh::build(&boxes),
+            triangles,
+            uvs,
+            bounds,
+            material,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    // Distance along the ray and barycentric coordinates of the second and third corners
+    // where the ray crosses a triangle, using the Möller–Trumbore method
+    fn hit_triangle(&self, ray: &Ray, triangle: &[u32; 3]) -> Option<(f32, f32, f32)> {
+        let [a, b, c] = triangle.map(|i| self.positions[i as usize]);
+        let (edge1, edge2) = (b - a, c - a);
+        let pvec = cross(ray.direction(), &edge2);
+        let det = dot(&edge1, &pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+
+        let tvec = *ray.origin() - a;
+        let beta = dot(&tvec, &pvec) * inv_det;
+        if !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+        let qvec = cross(&tvec, &edge1);
+        let gamma = dot(ray.direction(), &qvec) * inv_det;
+        if gamma < 0. || beta + gamma > 1. {
+            return None;
+        }
+
+        Some((dot(&edge2, &qvec) * inv_det, beta, gamma))
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for TriangleMesh<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, i, beta, gamma) = self.bvh.hit(ray, t_min, t_max, |i, t_max| {
+            let (t, beta, gamma) = self.hit_triangle(ray, &self.triangles[i])?;
+            if t < t_min || t > t_max {
+                return None;
+            }
+            Some((t, (t, i, beta, gamma)))
+        })?;
+
+        let triangle = self.triangles[i];
+        let alpha = 1. - beta - gamma;
+        let [na, nb, nc] = triangle.map(|v| self.normals[v as usize]);
+        let mut normal = alpha * na + beta * nb + gamma * nc;
+        if normal.near_zero() {
+            let [a, b, c] = triangle.map(|v| self.positions[v as usize]);
+            normal = cross(&(b - a), &(c - a));
+        }
+        let uv = match &self.uvs {
+            Some(uvs) => {
+                let [a, b, c] = uvs[i];
+                (alpha * a.0 + beta * b.0 + gamma * c.0, alpha * a.1 + beta * b.1 + gamma * c.1)
+            },
+            None => (beta, gamma),
+        };
+
+        let mut record = HitRecord::from(t, ray, unit_vector(normal), uv, self.material.clone());
+        record.color = self.colors.as_ref().map(|colors| {
+            let [a, b, c] = triangle.map(|v| colors[v as usize]);
+            alpha * a + beta * b + gamma * c
+        });
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::vec3::Vec3;
+use crate::mesh::PolygonMesh;
+
+/// Reads the geometry of a Wavefront OBJ file: vertex positions (`v`), texture
+/// coordinates (`vt`) and polygonal faces (`f`). Normals, groups and materials are
+/// skipped, since normals are worked out again once the mesh is refined.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<PolygonMesh, Error> {
+    parse(&fs::read_to_string(path)?)
+}
+
+pub fn parse(text: &str) -> Result<PolygonMesh, Error> {
+    let mut mesh = PolygonMesh::default();
+    let mut uvs: Vec<(f32, f32)> = Vec::new();
+    let mut corner_uvs: Vec<Vec<(f32, f32)>> = Vec::new();
+    // Whether every face so far has given texture coordinates for all its corners
+    let mut textured = true;
+
+    for (n, line) in text.lines().enumerate() {
+        let invalid = |msg: &str| {
+            Error::new(ErrorKind::InvalidData,
+                       format!("invalid OBJ file: line {}: {}", n + 1, msg))
+        };
+        let number = |value: &str| {
+            value.parse::<f32>().map_err(|_| invalid(&format!("bad value \"{}\"", value)))
+        };
+
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next();
+        let values: Vec<&str> = fields.collect();
+        match keyword {
+            Some("v") => {
+                if values.len() < 3 {
+                    return Err(invalid("vertex needs x, y and z"));
+                }
+                mesh.positions.push(Vec3::from(number(values[0])?, number(values[1])?,
+                                               number(values[2])?));
+            },
+            Some("vt") => {
+                if values.is_empty() {
+                    return Err(invalid("texture coordinate needs u"));
+                }
+                let v = match values.get(1) {
+                    Some(v) => number(v)?,
+                    None => 0.,
+                };
+                uvs.push((number(values[0])?, v));
+            },
+            Some("f") => {
+                if values.len() < 3 {
+                    return Err(invalid("face needs at least 3 corners"));
+                }
+
+                // Corners are v, v/vt, v//vn or v/vt/vn, counting from 1 or, when
+                // negative, back from the latest element
+                let resolve = |index: &str, count: usize, what: &str| {
+                    let i: i64 = index.parse().map_err(|_| {
+                        invalid(&format!("bad index \"{}\"", index))
+                    })?;
+                    let resolved = if i < 0 {count as i64 + i} else {i - 1};
+                    if resolved < 0 || resolved >= count as i64 {
+                        return Err(invalid(&format!("{} index {} out of range", what, i)));
+                    }
+                    Ok(resolved as usize)
+                };
+                let mut face = Vec::with_capacity(values.len());
+                let mut face_uvs = Vec::with_capacity(values.len());
+                for corner in values {
+                    let mut parts = corner.split('/');
+                    let vertex = parts.next().unwrap_or_default();
+                    face.push(resolve(vertex, mesh.positions.len(), "vertex")? as u32);
+                    match parts.next() {
+                        Some(uv) if !uv.is_empty() => {
+                            face_uvs.push(uvs[resolve(uv, uvs.len(), "texture coordinate")?]);
+                        },
+                        _ => textured = false,
+                    }
+                }
+                if (1..face.len()).any(|k| face[..k].contains(&face[k])) {
+                    return Err(invalid("face repeats a vertex"));
+                }
+                mesh.faces.push(face);
+                corner_uvs.push(face_uvs);
+            },
+            _ => (),
+        }
+    }
+
+    if mesh.faces.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid OBJ file: no faces"));
+    }
+    if textured {
+        mesh.uvs = Some(corner_uvs);
+    }
+
+    Ok(mesh)
+}
@@ -0,0 +1,266 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error};
+
+use png::chunk::ChunkType;
+
+use crate::vec3::Color;
+use crate::color::{Display, Gamut, color_bytes};
+use crate::film::Film;
+use crate::aov::AovFilm;
+
+/// File formats the final image can be written in
+#[derive(Debug, Clone, Copy)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// Writes the film to `<filename>.<extension>`. PNG output is tagged with the color
+/// space it was encoded in, PPM has no way to carry that information.
+pub fn write_image(filename: &str, film: &Film, display: &Display,
+                   format: ImageFormat) -> Result<(), Error> {
+    let path = format!("{}.{}", filename, format.extension());
+    match format {
+        ImageFormat::Ppm => fs::write(path, film.to_ppm(display)),
+        ImageFormat::Png => write_png(&path, film, display),
+    }
+}
+
+fn write_png(path: &str, film: &Film, display: &Display) -> Result<(), Error> {
+    let data: Vec<u8> = (0..film.height()).flat_map(|y| {
+        (0..film.width()).flat_map(move |x| color_bytes(&film.color(x, y), display))
+    }).collect();
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?),
+                                        film.width(), film.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Gamut::Srgb = display.gamut {
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_chunk(ChunkType(*b"cICP"), &display.gamut.cicp())?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Writes the linear beauty pass along with every AOV as layers of
+/// `<filename>.exr`
+pub fn write_layers(filename: &str, film: &Film, aovs: &AovFilm) -> Result<(), Error> {
+    let (width, height) = (film.width(), film.height());
+    let beauty = |f: fn(&Color) -> f32| {
+        ExrChannel::Float((0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+                                     .map(|(x, y)| f(&film.color(x, y)))
+                                     .collect())
+    };
+
+    let mut channels = vec![
+        ("R".to_string(), beauty(|c| c.x())),
+        ("G".to_string(), beauty(|c| c.y())),
+        ("B".to_string(), beauty(|c| c.z())),
+    ];
+    channels.extend(aovs.channels());
+
+    write_exr(&format!("{}.exr", filename), width, height, channels)
+}
+
+/// Data for a single channel of an EXR image
+pub enum ExrChannel {
+    Uint(Vec<u32>),
+    Float(Vec<f32>),
+}
+
+impl ExrChannel {
+    fn pixel_type(&self) -> i32 {
+        match self {
+            ExrChannel::Uint(_) => 0,
+            ExrChannel::Float(_) => 2,
+        }
+    }
+
+    fn write_row(&self, data: &mut Vec<u8>, start: usize, len: usize) {
+        match self {
+            ExrChannel::Uint(values) => {
+                for v in &values[start..start + len] {
+                    data.extend_from_slice(&v.to_le_bytes());
+                }
+            },
+            ExrChannel::Float(values) => {
+                for v in &values[start..start + len] {
+                    data.extend_from_slice(&v.to_le_bytes());
+                }
+            },
+        }
+    }
+}
+
+/// Writes an uncompressed scanline OpenEXR image. Channel names may contain a layer
+/// prefix such as `normal.X`, and every channel must hold width * height values.
+pub fn write_exr(path: &str, width: u32, height: u32,
+                 mut channels: Vec<(String, ExrChannel)>) -> Result<(), Error> {
+    fn attribute(data: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(kind.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        data.extend_from_slice(value);
+    }
+
+    // Channels have to be stored in alphabetical order
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]); // Magic number
+    data.extend_from_slice(&2u32.to_le_bytes()); // Version 2, single part scanline
+
+    let mut list = Vec::new();
+    for (name, channel) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&channel.pixel_type().to_le_bytes());
+        list.extend_from_slice(&[0; 4]); // Perceptually linear flag and padding
+        list.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        list.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    list.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter().flat_map(|n| n.to_le_bytes()).collect();
+    attribute(&mut data, "channels", "chlist", &list);
+    attribute(&mut data, "compression", "compression", &[0]);
+    attribute(&mut data, "dataWindow", "box2i", &window);
+    attribute(&mut data, "displayWindow", "box2i", &window);
+    attribute(&mut data, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut data, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut data, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut data, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    data.push(0);
+
+    // Every scanline is its own chunk, preceded by a table of chunk offsets
+    let row_size = 4 * width as usize * channels.len();
+    let table_start = data.len();
+    for y in 0..height as usize {
+        let offset = table_start + 8 * height as usize + y * (8 + row_size);
+        data.extend_from_slice(&(offset as u64).to_le_bytes());
+    }
+    for y in 0..height as usize {
+        data.extend_from_slice(&(y as i32).to_le_bytes());
+        data.extend_from_slice(&(row_size as i32).to_le_bytes());
+        for (_, channel) in &channels {
+            channel.write_row(&mut data, y * width as usize, width as usize);
+        }
+    }
+
+    fs::write(path, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ToneMap;
+    use crate::image::Image;
+
+    fn temp_path(name: &str) -> String {
+        let file = format!("output-{}-{}", name, std::process::id());
+        std::env::temp_dir().join(file).to_str().unwrap().to_string()
+    }
+
+    // Type and contents of every chunk in a PNG file
+    fn png_chunks(data: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut chunks = Vec::new();
+        let mut pos = 8;
+        while pos + 8 <= data.len() {
+            let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = data[pos + 4..pos + 8].try_into().unwrap();
+            chunks.push((kind, data[pos + 8..pos + 8 + len].to_vec()));
+            pos += 12 + len; // Length, type, data and CRC
+        }
+        chunks
+    }
+
+    #[test]
+    fn png_is_tagged_with_its_color_space() {
+        let mut film = Film::new(3, 2);
+        film.set(0, 0, Color::from(1., 0.5, 0.25), 1., 1.);
+        film.set(2, 1, Color::from(0.2, 0.4, 0.8), 2., 2.);
+
+        for gamut in [Gamut::Srgb, Gamut::DisplayP3, Gamut::Rec2020] {
+            let display = Display {exposure: 0., tone_map: ToneMap::Clamp, gamut};
+            let filename = temp_path("png");
+            write_image(&filename, &film, &display, ImageFormat::Png).unwrap();
+            let path = format!("{}.png", filename);
+            let data = fs::read(&path).unwrap();
+            let image = Image::load(&path);
+            fs::remove_file(&path).unwrap();
+
+            // cICP has to come before the image data to be used
+            let chunks = png_chunks(&data);
+            let position = |kind: &[u8; 4]| chunks.iter().position(|(k, _)| k == kind);
+            let cicp = position(b"cICP").unwrap();
+            assert_eq!(chunks[cicp].1, gamut.cicp());
+            assert!(cicp < position(b"IDAT").unwrap());
+            assert_eq!(position(b"sRGB").is_some(), matches!(gamut, Gamut::Srgb));
+
+            let image = image.unwrap();
+            assert_eq!((image.width(), image.height()), (3, 2));
+            for (x, y) in [(0, 0), (2, 1), (1, 1)] {
+                let expected = color_bytes(&film.color(x, y), &display);
+                let c = image.pixel(x, y);
+                let found = [c.x(), c.y(), c.z()].map(|v| (v * 255.).round() as u8);
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn exr_holds_sorted_channels_in_scanlines() {
+        let (width, height) = (3, 2);
+        let path = temp_path("exr");
+        let floats: Vec<f32> = (0..6).map(|i| i as f32 * 0.5).collect();
+        let ids: Vec<u32> = (10..16).collect();
+        write_exr(&path, width, height, vec![
+            ("id".to_string(), ExrChannel::Uint(ids.clone())),
+            ("B".to_string(), ExrChannel::Float(floats.clone())),
+        ]).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(data[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(data[4..8], 2u32.to_le_bytes());
+        // Uppercase sorts before lowercase, so B comes first
+        let find = |text: &[u8]| data.windows(text.len()).position(|w| w == text).unwrap();
+        assert!(find(b"B\0") < find(b"id\0"));
+
+        // The header ends with an empty attribute name, followed by the offsets table
+        let end = find(b"screenWindowWidth\0float\0") + 24 + 4 + 4;
+        assert_eq!(data[end], 0);
+        let table = end + 1;
+        let row_size = 4 * width as usize * 2;
+        assert_eq!(data.len(), table + 8 * height as usize + height as usize * (8 + row_size));
+        for y in 0..height as usize {
+            let offset = u64::from_le_bytes(data[table + 8 * y..table + 8 * y + 8]
+                                            .try_into().unwrap()) as usize;
+            let word = |i: usize| data[offset + 4 * i..offset + 4 * i + 4].try_into().unwrap();
+            assert_eq!(i32::from_le_bytes(word(0)), y as i32);
+            assert_eq!(i32::from_le_bytes(word(1)), row_size as i32);
+            for x in 0..width as usize {
+                let i = y * width as usize + x;
+                assert_eq!(f32::from_le_bytes(word(2 + x)), floats[i]);
+                assert_eq!(u32::from_le_bytes(word(2 + width as usize + x)), ids[i]);
+            }
+        }
+    }
+}
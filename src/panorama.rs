@@ -0,0 +1,119 @@
+use std::f32::consts::PI;
+
+use crate::vec3::*;
+use crate::ray::Ray;
+use crate::camera::{Camera, CameraModel};
+
+/// Latitude-longitude panorama covering every direction around `view_from`, with
+/// `view_at` in the middle of the image. Best rendered at a 2:1 aspect ratio.
+pub struct Equirectangular {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Equirectangular {
+    pub fn new(view_from: Point3, view_at: Point3, view_up: Vec3) -> Self {
+        let (u, v, w) = Camera::basis(view_from, view_at, view_up);
+        Equirectangular {origin: view_from, u, v, w}
+    }
+
+    /// Longitude and latitude in radians at a position on the image
+    pub fn angles(u: f32, v: f32) -> (f32, f32) {
+        ((u - 0.5) * 2. * PI, (v - 0.5) * PI)
+    }
+
+    // Direction for a given longitude and latitude, both in radians
+    pub fn direction(&self, longitude: f32, latitude: f32) -> Vec3 {
+        latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        let (longitude, latitude) = Self::angles(u, v);
+        Some(Ray::from(self.origin, self.direction(longitude, latitude)))
+    }
+}
+
+/// Equal-angle (equidistant) fisheye, where distance from the center of the image is
+/// proportional to the angle away from the view direction. The image circle fills the
+/// height of the image, so a 1:1 aspect ratio wastes the least space.
+pub struct Fisheye {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    half_fov: f32,
+    aspect_ratio: f32,
+}
+
+impl Fisheye {
+    pub fn new(view_from: Point3, view_at: Point3, view_up: Vec3, fov: f32,
+               aspect_ratio: f32) -> Self {
+        let (u, v, w) = Camera::basis(view_from, view_at, view_up);
+        Fisheye {
+            origin: view_from,
+            u,
+            v,
+            w,
+            half_fov: fov.to_radians() / 2.,
+            aspect_ratio,
+        }
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        let x = (2. * u - 1.) * self.aspect_ratio;
+        let y = 2. * v - 1.;
+        let r = (x * x + y * y).sqrt();
+        if r > 1. {
+            return None;
+        }
+
+        let theta = r * self.half_fov;
+        let phi = y.atan2(x);
+        let direction = theta.sin() * (phi.cos() * self.u + phi.sin() * self.v)
+                        - theta.cos() * self.w;
+
+        Some(Ray::from(self.origin, direction))
+    }
+}
+
+/// Six 90° views along the world axes, laid out in a 3:2 grid with +X, -X and +Y
+/// along the top row and -Y, +Z and -Z along the bottom. Faces follow the usual
+/// OpenGL cube map orientation.
+pub struct Cubemap {
+    origin: Point3,
+}
+
+impl Cubemap {
+    pub fn new(view_from: Point3) -> Self {
+        Cubemap {origin: view_from}
+    }
+}
+
+impl CameraModel for Cubemap {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        let col = ((u * 3.) as i32).clamp(0, 2);
+        let row = ((v * 2.) as i32).clamp(0, 1);
+
+        // Face coordinates from -1 to 1, with t running down the face
+        let s = 2. * (u * 3. - col as f32) - 1.;
+        let t = 1. - 2. * (v * 2. - row as f32);
+
+        let direction = match (row, col) {
+            (1, 0) => Vec3::from(1., -t, -s),
+            (1, 1) => Vec3::from(-1., -t, s),
+            (1, _) => Vec3::from(s, 1., t),
+            (_, 0) => Vec3::from(s, -1., -t),
+            (_, 1) => Vec3::from(s, -t, 1.),
+            (_, _) => Vec3::from(-s, -t, -1.),
+        };
+
+        Some(Ray::from(self.origin, direction))
+    }
+}
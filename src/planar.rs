@@ -0,0 +1,146 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, cross, dot, tangents, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+
+// Distance along the ray to the plane through `point` with the given normal, if it
+// falls between t_min and t_max
+fn plane_hit(ray: &Ray, point: &Point3, normal: &Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+    let denom = dot(normal, ray.direction());
+    if denom.abs() < 1e-8 {
+        return None; // Parallel to the plane
+    }
+
+    let t = dot(normal, &(*point - *ray.origin())) / denom;
+    if t < t_min || t > t_max {
+        None
+    } else {
+        Some(t)
+    }
+}
+
+/// Infinite plane through `point`. UVs are distances in world units from `point` along
+/// two directions in the plane, so image textures repeat once per unit.
+pub struct Plane<T: Material> {
+    point: Point3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<T>,
+}
+
+impl<T: Material> Plane<T> {
+    pub fn from(point: Point3, normal: Vec3, material: Arc<T>) -> Self {
+        let normal = unit_vector(normal);
+        let (tangent, bitangent) = tangents(&normal);
+        Plane {point, normal, tangent, bitangent, material}
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Plane<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = plane_hit(ray, &self.point, &self.normal, t_min, t_max)?;
+        let offset = ray.at(t) - self.point;
+        let uv = (dot(&offset, &self.tangent), dot(&offset, &self.bitangent));
+
+        Some(HitRecord::from(t, ray, self.normal, uv, self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// Flat circular disk facing along `normal`. U runs once around the disk and v from
+/// the center out to the rim.
+pub struct Disk<T: Material> {
+    center: Point3,
+    normal: Vec3,
+    radius: f32,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Arc<T>,
+}
+
+impl<T: Material> Disk<T> {
+    pub fn from(center: Point3, normal: Vec3, radius: f32, material: Arc<T>) -> Self {
+        let normal = unit_vector(normal);
+        let (tangent, bitangent) = tangents(&normal);
+        Disk {center, normal, radius, tangent, bitangent, material}
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Disk<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = plane_hit(ray, &self.center, &self.normal, t_min, t_max)?;
+        let offset = ray.at(t) - self.center;
+        let r = offset.length();
+        if r > self.radius {
+            return None;
+        }
+
+        let phi = dot(&offset, &self.bitangent).atan2(dot(&offset, &self.tangent));
+        let uv = (phi.rem_euclid(2. * PI) / (2. * PI), r / self.radius);
+
+        Some(HitRecord::from(t, ray, self.normal, uv, self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // A circle's extent along each axis shrinks as its normal turns toward that axis
+        let n = self.normal;
+        let extent = self.radius * Vec3::from((1. - n.x() * n.x()).max(0.).sqrt(),
+                                              (1. - n.y() * n.y()).max(0.).sqrt(),
+                                              (1. - n.z() * n.z()).max(0.).sqrt());
+        Some(Aabb::from(self.center - extent, self.center + extent))
+    }
+}
+
+/// Parallelogram with one corner at `corner` and sides along the edge vectors `u` and
+/// `v`. The normal follows the right hand rule from u to v, and UVs run from 0 to 1
+/// along each edge.
+pub struct Quad<T: Material> {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    // Scaled normal which turns cross products with the edges into edge coordinates
+    w: Vec3,
+    material: Arc<T>,
+}
+
+impl<T: Material> Quad<T> {
+    pub fn from(corner: Point3, u: Vec3, v: Vec3, material: Arc<T>) -> Self {
+        let n = cross(&u, &v);
+        Quad {
+            corner,
+            u,
+            v,
+            normal: unit_vector(n),
+            w: n / dot(&n, &n),
+            material,
+        }
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Quad<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let t = plane_hit(ray, &self.corner, &self.normal, t_min, t_max)?;
+        let offset = ray.at(t) - self.corner;
+        let alpha = dot(&self.w, &cross(&offset, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &offset));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+
+        Some(HitRecord::from(t, ray, self.normal, (alpha, beta), self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::around(&[self.corner, self.corner + self.u, self.corner + self.v,
+                            self.corner + self.u + self.v]))
+    }
+}
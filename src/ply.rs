@@ -0,0 +1,473 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::SplitAsciiWhitespace;
+
+use crate::vec3::{Color, Vec3};
+use crate::color::srgb_to_linear;
+use crate::mesh::PolygonMesh;
+
+// Vertex properties the mesh uses, in the order they're kept while reading
+const VERTEX_PROPERTIES: [&[&str]; 11] = [
+    &["x"], &["y"], &["z"],
+    &["nx"], &["ny"], &["nz"],
+    &["red", "diffuse_red"], &["green", "diffuse_green"], &["blue", "diffuse_blue"],
+    &["u", "s", "texture_u"], &["v", "t", "texture_v"],
+];
+
+/// Reads a PLY file in ASCII or either binary byte order. Vertices give their position as
+/// `x`, `y` and `z`, and may also give normals (`nx`, `ny`, `nz`), colors (`red`, `green`,
+/// `blue`) and texture coordinates (`u` and `v` or `s` and `t`). Faces are lists of vertex
+/// indices, and any other elements in the file are skipped.
+///
+/// Colors stored as integers are scaled by the largest value of their type, so 255 for
+/// 8-bit colors and 65535 for 16-bit ones, and are taken to be sRGB encoded, like images,
+/// and decoded to linear values. Floating point colors are used as they are.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<PolygonMesh, Error> {
+    parse(&fs::read(path)?)
+}
+
+pub fn parse(data: &[u8]) -> Result<PolygonMesh, Error> {
+    let (header, body) = Header::parse(data)?;
+    let mut body = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(body).map_err(|_| invalid("body is not text"))?;
+            Body::Ascii(text.split_ascii_whitespace())
+        },
+        Format::BinaryLittleEndian => Body::Binary {data: body, big_endian: false},
+        Format::BinaryBigEndian => Body::Binary {data: body, big_endian: true},
+    };
+
+    let mut vertices: Vec<[f64; 11]> = Vec::new();
+    let mut faces: Vec<Vec<u32>> = Vec::new();
+    let mut found = [false; 11];
+    // Largest value of the type colors are stored as, for integer colors
+    let mut color_max: Option<f64> = None;
+
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => {
+                // Where each property read goes among the vertex properties, if anywhere
+                let slots: Vec<Option<usize>> = element.properties.iter().map(|property| {
+                    VERTEX_PROPERTIES.iter().position(|names| names.contains(&&*property.name))
+                }).collect();
+                for (property, slot) in element.properties.iter().zip(&slots) {
+                    if let (Some(slot), Kind::Scalar(scalar)) = (slot, property.kind) {
+                        found[*slot] = true;
+                        if (6..9).contains(slot) {
+                            color_max = scalar.max();
+                        }
+                    }
+                }
+                // Checked before reading too, since vertices with nothing to read would
+                // never run out of data
+                if !found[..3].iter().all(|&f| f) {
+                    return Err(invalid("vertices have no x, y and z"));
+                }
+
+                vertices.reserve(element.count.min(data.len() / element.min_size()));
+                for _ in 0..element.count {
+                    let mut vertex = [0.; 11];
+                    for (property, slot) in element.properties.iter().zip(&slots) {
+                        match (property.kind, slot) {
+                            (Kind::Scalar(scalar), Some(slot)) => {
+                                vertex[*slot] = body.read(scalar)?
+                            },
+                            (kind, _) => body.skip(kind)?,
+                        }
+                    }
+                    vertices.push(vertex);
+                }
+            },
+            "face" => {
+                let indices = element.properties.iter().position(|property| {
+                    property.name == "vertex_indices" || property.name == "vertex_index"
+                }).ok_or_else(|| invalid("faces have no vertex_indices"))?;
+
+                faces.reserve(element.count.min(data.len() / element.min_size()));
+                for f in 0..element.count {
+                    for (p, property) in element.properties.iter().enumerate() {
+                        match property.kind {
+                            Kind::List(count, item) if p == indices => {
+                                let n = body.read(count)? as usize;
+                                if n < 3 {
+                                    return Err(invalid(&format!("face {} has {} corners",
+                                                                f, n)));
+                                }
+                                let face = (0..n).map(|_| {
+                                    let i = body.read(item)?;
+                                    if i < 0. || i.fract() != 0. || i > u32::MAX as f64 {
+                                        return Err(invalid(&format!("face {}: bad index {}",
+                                                                    f, i)));
+                                    }
+                                    Ok(i as u32)
+                                }).collect::<Result<Vec<u32>, Error>>()?;
+                                faces.push(face);
+                            },
+                            kind => body.skip(kind)?,
+                        }
+                    }
+                }
+            },
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        body.skip(property.kind)?;
+                    }
+                }
+            },
+        }
+    }
+
+    if !found[..3].iter().all(|&f| f) {
+        return Err(invalid("vertices have no x, y and z"));
+    }
+    if faces.is_empty() {
+        return Err(invalid("no faces"));
+    }
+    for (f, face) in faces.iter().enumerate() {
+        if let Some(i) = face.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(invalid(&format!("face {}: vertex index {} out of range", f, i)));
+        }
+    }
+
+    let mut mesh = PolygonMesh {
+        positions: Vec::with_capacity(vertices.len()),
+        ..Default::default()
+    };
+    for (i, vertex) in vertices.iter().enumerate() {
+        let p = Vec3::from(vertex[0] as f32, vertex[1] as f32, vertex[2] as f32);
+        if !(p.x().is_finite() && p.y().is_finite() && p.z().is_finite()) {
+            return Err(invalid(&format!("vertex {} is not finite", i)));
+        }
+        mesh.positions.push(p);
+    }
+    if found[3..6].iter().all(|&f| f) {
+        mesh.normals = Some(vertices.iter().map(|vertex| {
+            Vec3::from(vertex[3] as f32, vertex[4] as f32, vertex[5] as f32)
+        }).collect());
+    }
+    if found[6..9].iter().all(|&f| f) {
+        mesh.colors = Some(vertices.iter().map(|vertex| {
+            let [r, g, b] = [vertex[6], vertex[7], vertex[8]].map(|c| {
+                match color_max {
+                    Some(max) => srgb_to_linear((c / max) as f32),
+                    None => c as f32,
+                }
+            });
+            Color::from(r, g, b)
+        }).collect());
+    }
+    if found[9..].iter().all(|&f| f) {
+        mesh.uvs = Some(faces.iter().map(|face| {
+            face.iter().map(|&i| {
+                let vertex = &vertices[i as usize];
+                (vertex[9] as f32, vertex[10] as f32)
+            }).collect()
+        }).collect());
+    }
+    mesh.faces = faces;
+
+    mesh.validate().map_err(|e| invalid(&e))?;
+
+    Ok(mesh)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid PLY file: {}", msg))
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn from(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::Int8,
+            "uchar" | "uint8" => Scalar::UInt8,
+            "short" | "int16" => Scalar::Int16,
+            "ushort" | "uint16" => Scalar::UInt16,
+            "int" | "int32" => Scalar::Int32,
+            "uint" | "uint32" => Scalar::UInt32,
+            "float" | "float32" => Scalar::Float32,
+            "double" | "float64" => Scalar::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::UInt8 => 1,
+            Scalar::Int16 | Scalar::UInt16 => 2,
+            Scalar::Int32 | Scalar::UInt32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    // Largest value of an integer type, or None for floating point
+    fn max(self) -> Option<f64> {
+        match self {
+            Scalar::Int8 => Some(i8::MAX as f64),
+            Scalar::UInt8 => Some(u8::MAX as f64),
+            Scalar::Int16 => Some(i16::MAX as f64),
+            Scalar::UInt16 => Some(u16::MAX as f64),
+            Scalar::Int32 => Some(i32::MAX as f64),
+            Scalar::UInt32 => Some(u32::MAX as f64),
+            Scalar::Float32 | Scalar::Float64 => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Scalar(Scalar),
+    // Type of the item count followed by the type of the items
+    List(Scalar, Scalar),
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    // Fewest bytes each item takes up in a binary body, which bounds how many items a file
+    // can really hold however many its header claims. ASCII bodies may pack a few more.
+    fn min_size(&self) -> usize {
+        self.properties.iter().map(|property| match property.kind {
+            Kind::Scalar(scalar) | Kind::List(scalar, _) => scalar.size(),
+        }).sum::<usize>().max(1)
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl Header {
+    // Reads the header, returning it along with the data which follows
+    fn parse(data: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut start = 0;
+        let mut first = true;
+
+        loop {
+            let end = data[start..].iter().position(|&b| b == b'\n')
+                                   .ok_or_else(|| invalid("header has no end_header"))?;
+            let line = std::str::from_utf8(&data[start..start + end])
+                .map_err(|_| invalid("header is not text"))?
+                .trim();
+            start += end + 1;
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if first {
+                if line != "ply" {
+                    return Err(invalid("missing ply signature"));
+                }
+                first = false;
+                continue;
+            }
+
+            match fields.as_slice() {
+                ["end_header"] => break,
+                ["format", name, _version] => {
+                    format = Some(match *name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(invalid(&format!("unknown format \"{}\"", name))),
+                    });
+                },
+                ["element", name, count] => {
+                    let count = count.parse().map_err(|_| {
+                        invalid(&format!("bad count for element {}", name))
+                    })?;
+                    elements.push(Element {name: name.to_string(), count,
+                                           properties: Vec::new()});
+                },
+                ["property", rest @ ..] => {
+                    let element = elements.last_mut()
+                                          .ok_or_else(|| invalid("property before element"))?;
+                    let scalar = |name: &str| {
+                        Scalar::from(name).ok_or_else(|| {
+                            invalid(&format!("unknown type \"{}\"", name))
+                        })
+                    };
+                    let (name, kind) = match rest {
+                        ["list", count, item, name] => {
+                            (name, Kind::List(scalar(count)?, scalar(item)?))
+                        },
+                        [scalar_type, name] => (name, Kind::Scalar(scalar(scalar_type)?)),
+                        _ => return Err(invalid(&format!("bad property \"{}\"", line))),
+                    };
+                    element.properties.push(Property {name: name.to_string(), kind});
+                },
+                ["comment", ..] | ["obj_info", ..] | [] => (),
+                _ => return Err(invalid(&format!("unknown header line \"{}\"", line))),
+            }
+        }
+
+        let format = format.ok_or_else(|| invalid("missing format"))?;
+        Ok((Header {format, elements}, &data[start..]))
+    }
+}
+
+// Element data following the header, read one value at a time
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {data: &'a [u8], big_endian: bool},
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, Error> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(|| invalid("unexpected end of data"))?;
+                word.parse().map_err(|_| invalid(&format!("bad value \"{}\"", word)))
+            },
+            Body::Binary {data, big_endian} => {
+                let size = scalar.size();
+                if data.len() < size {
+                    return Err(invalid("unexpected end of data"));
+                }
+                let (bytes, rest) = data.split_at(size);
+                *data = rest;
+
+                let mut buffer = [0; 8];
+                buffer[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+                Ok(match scalar {
+                    Scalar::Int8 => b0 as i8 as f64,
+                    Scalar::UInt8 => b0 as f64,
+                    Scalar::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::UInt16 => u16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::UInt32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::Float64 => f64::from_le_bytes(buffer),
+                })
+            },
+        }
+    }
+
+    fn skip(&mut self, kind: Kind) -> Result<(), Error> {
+        match kind {
+            Kind::Scalar(scalar) => {
+                self.read(scalar)?;
+            },
+            Kind::List(count, item) => {
+                for _ in 0..self.read(count)? as usize {
+                    self.read(item)?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square as a single quad, with 8-bit colors and an extra property to skip
+    fn binary_square(big_endian: bool) -> Vec<u8> {
+        let order = if big_endian {"big"} else {"little"};
+        let mut data = format!("ply\nformat binary_{}_endian 1.0\nelement vertex 4\n\
+                                property float x\nproperty float y\nproperty float z\n\
+                                property uchar red\nproperty uchar green\n\
+                                property uchar blue\nproperty short extra\n\
+                                element face 1\nproperty list uchar int vertex_indices\n\
+                                end_header\n", order).into_bytes();
+        let float = |v: f32| if big_endian {v.to_be_bytes()} else {v.to_le_bytes()};
+        for (x, y) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            for v in [x, y, 0.] {
+                data.extend_from_slice(&float(v));
+            }
+            data.extend_from_slice(&[255, 0, 0, 7, 7]);
+        }
+        data.push(4);
+        for i in 0..4i32 {
+            data.extend_from_slice(&if big_endian {i.to_be_bytes()} else {i.to_le_bytes()});
+        }
+        data
+    }
+
+    #[test]
+    fn binary_files_are_read_in_either_order() {
+        for big_endian in [false, true] {
+            let mesh = parse(&binary_square(big_endian)).unwrap();
+            assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+            let p = mesh.positions[2];
+            assert_eq!((p.x(), p.y(), p.z()), (1., 1., 0.));
+            let c = mesh.colors.unwrap()[3];
+            assert_eq!((c.x(), c.y(), c.z()), (1., 0., 0.));
+            assert!(mesh.normals.is_none() && mesh.uvs.is_none());
+        }
+    }
+
+    #[test]
+    fn truncated_binary_is_rejected() {
+        for big_endian in [false, true] {
+            let data = binary_square(big_endian);
+            let body = data.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+            for len in 0..data.len() {
+                let error = parse(&data[..len]).err().unwrap();
+                assert_eq!(error.kind(), ErrorKind::InvalidData);
+                if len >= body {
+                    assert!(error.to_string().contains("unexpected end of data"), "{}", error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn counts_larger_than_the_file_are_rejected() {
+        let data = b"ply\nformat binary_little_endian 1.0\nelement vertex 4000000000000\n\
+                     property double x\nproperty double y\nproperty double z\n\
+                     element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                     \0\0\0\0";
+        let error = parse(data).err().unwrap();
+        assert!(error.to_string().contains("unexpected end of data"), "{}", error);
+    }
+
+    #[test]
+    fn ascii_faces_are_checked() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uchar int vertex_indices\nend_header\n\
+                      0 0 0\n1 0 0\n0 1 0\n";
+        assert_eq!(parse(format!("{}3 0 1 2\n", header).as_bytes()).unwrap().faces,
+                   vec![vec![0, 1, 2]]);
+        for (face, message) in [("3 0 1 3", "out of range"), ("2 0 1", "has 2 corners"),
+                                ("3 0 1 -1", "bad index"), ("3 0 1", "end of data")] {
+            let error = parse(format!("{}{}\n", header, face).as_bytes()).err().unwrap();
+            assert!(error.to_string().contains(message), "{}: {}", face, error);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Real roots of low degree polynomials, shared by the shapes which need them. Solving
+//! happens in `f64`, and roots of cubics and quartics are polished with Newton's method
+//! on the original polynomial, since the closed form solutions lose precision easily.
+
+// Coefficients smaller than this are treated as zero
+const EPSILON: f64 = 1e-12;
+
+// Relative size of the rounding error allowed in a quadratic's discriminant before a
+// nearly touching pair of roots is given up on
+const TOUCHING: f64 = 1e-9;
+
+// Most Newton iterations used to polish each root
+const POLISH_STEPS: u32 = 8;
+
+/// Real roots of a x² + b x + c in ascending order. Roots which only miss being real
+/// through rounding error are returned as a double root.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return if b.abs() < EPSILON {Vec::new()} else {vec![-c / b]};
+    }
+
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < -TOUCHING * b * b {
+        return Vec::new();
+    }
+    if discriminant <= 0. {
+        return vec![-b / (2. * a)];
+    }
+
+    // Avoids cancellation between b and the square root
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (r0, r1) = (q / a, c / q);
+    if r0 < r1 {vec![r0, r1]} else {vec![r1, r0]}
+}
+
+/// Real roots of a x³ + b x² + c x + d in ascending order
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_quadratic(b, c, d);
+    }
+
+    // Normalize to x³ + A x² + B x + C and substitute x = y - A/3 to reach the depressed
+    // cubic y³ + 3p y + 2q
+    let (a2, a1, a0) = (b / a, c / a, d / a);
+    let shift = a2 / 3.;
+    let p = (3. * a1 - a2 * a2) / 9.;
+    let q = (2. * a2 * a2 * a2 - 9. * a2 * a1 + 27. * a0) / 54.;
+    let discriminant = q * q + p * p * p;
+
+    let mut roots = if discriminant.abs() < EPSILON {
+        if q.abs() < EPSILON {
+            vec![0.]
+        } else {
+            let u = (-q).cbrt();
+            vec![2. * u, -u]
+        }
+    } else if discriminant < 0. {
+        // Three real roots, found with the trigonometric method
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1., 1.).acos() / 3.;
+        let t = 2. * (-p).sqrt();
+        vec![t * phi.cos(),
+             -t * (phi + std::f64::consts::PI / 3.).cos(),
+             -t * (phi - std::f64::consts::PI / 3.).cos()]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root = polish(&[a, b, c, d], *root - shift);
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of a x⁴ + b x³ + c x² + d x + e in ascending order, using Ferrari's
+/// method through a resolvent cubic
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a.abs() < EPSILON {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // Normalize to x⁴ + A x³ + B x² + C x + D and substitute x = y - A/4 to reach the
+    // depressed quartic y⁴ + p y² + q y + r
+    let (a3, a2, a1, a0) = (b / a, c / a, d / a, e / a);
+    let shift = a3 / 4.;
+    let sq = a3 * a3;
+    let p = -3. / 8. * sq + a2;
+    let q = sq * a3 / 8. - a3 * a2 / 2. + a1;
+    let r = -3. / 256. * sq * sq + sq * a2 / 16. - a3 * a1 / 4. + a0;
+
+    let mut roots = if r.abs() < EPSILON {
+        // y (y³ + p y + q) = 0
+        let mut roots = solve_cubic(1., 0., p, q);
+        roots.push(0.);
+        roots
+    } else {
+        // A real root z of the resolvent cubic splits the quartic into
+        // (y² + v y + z - u)(y² - v y + z + u), where v² = 2z - p, u² = z² - r and
+        // q = 2uv. The largest root is the one which keeps both square roots real.
+        let resolvent = solve_cubic(1., -p / 2., -r, r * p / 2. - q * q / 8.);
+        let z = resolvent[resolvent.len() - 1];
+        let u2 = z * z - r;
+        let v2 = 2. * z - p;
+        if u2 < -EPSILON || v2 < -EPSILON {
+            return Vec::new();
+        }
+        let v = v2.max(0.).sqrt();
+        let v = if q < 0. {-v} else {v};
+
+        // z² - r cancels badly when u is small next to z, so take u from q whenever v
+        // is the larger of the two
+        let u = if v2 > u2.abs() {q / (2. * v)} else {u2.max(0.).sqrt()};
+
+        let mut roots = solve_quadratic(1., v, z - u);
+        roots.extend(solve_quadratic(1., -v, z + u));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        *root = polish(&[a, b, c, d, e], *root - shift);
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Refines a root of the polynomial with the given coefficients, highest power first
+fn polish(coefficients: &[f64], mut x: f64) -> f64 {
+    for _ in 0..POLISH_STEPS {
+        let (mut value, mut derivative) = (0., 0.);
+        for &k in coefficients {
+            derivative = derivative * x + value;
+            value = value * x + k;
+        }
+        if derivative.abs() < EPSILON {
+            break;
+        }
+        let step = value / derivative;
+        x -= step;
+        if step.abs() <= f64::EPSILON * x.abs() {
+            break;
+        }
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coefficients of scale (x - r0)(x - r1)..., highest power first
+    fn from_roots(scale: f64, roots: &[f64]) -> Vec<f64> {
+        let mut coefficients = vec![scale];
+        for &root in roots {
+            coefficients.push(0.);
+            for i in (1..coefficients.len()).rev() {
+                coefficients[i] -= root * coefficients[i - 1];
+            }
+        }
+        coefficients
+    }
+
+    fn quartic(scale: f64, roots: [f64; 4]) -> Vec<f64> {
+        let k = from_roots(scale, &roots);
+        solve_quartic(k[0], k[1], k[2], k[3], k[4])
+    }
+
+    // Whether every root found is close to an expected one and every expected one found
+    fn matches(found: &[f64], expected: &[f64], tolerance: f64) -> bool {
+        found.windows(2).all(|pair| pair[0] <= pair[1])
+            && found.iter().all(|x| expected.iter().any(|r| (x - r).abs() < tolerance))
+            && expected.iter().all(|r| found.iter().any(|x| (x - r).abs() < tolerance))
+    }
+
+    #[test]
+    fn quadratic_and_cubic_roots() {
+        assert!(matches(&solve_quadratic(2., -2., -12.), &[-2., 3.], 1e-12));
+        assert!(matches(&solve_quadratic(1., -2., 1.), &[1.], 1e-12));
+        assert!(solve_quadratic(1., 0., 1.).is_empty());
+        assert!(matches(&solve_quadratic(1., 0., 0.), &[0.], 1e-12));
+        let k = from_roots(-0.5, &[-4., 0.25, 7.]);
+        assert!(matches(&solve_cubic(k[0], k[1], k[2], k[3]), &[-4., 0.25, 7.], 1e-9));
+        assert!(matches(&solve_cubic(1., 0., 1., 0.), &[0.], 1e-12));
+    }
+
+    #[test]
+    fn quartic_with_distinct_roots() {
+        for (scale, roots) in [(1., [-3., -1., 0.5, 2.]), (-2.5, [-0.1, 0.2, 10., 30.]),
+                               (0.01, [1., 1.5, 2., 2.5]), (3., [-7., -6., -5.5, 4.])] {
+            let found = quartic(scale, roots);
+            assert_eq!(found.len(), 4, "{:?}", found);
+            assert!(matches(&found, &roots, 1e-9), "{:?} for {:?}", found, roots);
+        }
+    }
+
+    #[test]
+    fn quartic_with_repeated_roots() {
+        for roots in [[1., 1., 2., -1.], [-1., -1., 1., 1.], [0., 0., 3., 5.],
+                      [2., 2., 2., -3.]] {
+            let found = quartic(1., roots);
+            assert!(!found.is_empty());
+            assert!(matches(&found, &roots, 1e-4), "{:?} for {:?}", found, roots);
+        }
+    }
+
+    #[test]
+    fn quartic_with_complex_roots() {
+        // (x² + 1)(x² + 2x + 5)
+        assert!(solve_quartic(1., 2., 6., 2., 5.).is_empty());
+        // (x² + 4)(x - 1)(x - 3), which keeps only its real pair
+        let found = solve_quartic(1., -4., 7., -16., 12.);
+        assert!(matches(&found, &[1., 3.], 1e-9), "{:?}", found);
+    }
+
+    #[test]
+    fn quartic_without_leading_term() {
+        let k = from_roots(2., &[-1., 0.5, 4.]);
+        let found = solve_quartic(0., k[0], k[1], k[2], k[3]);
+        assert!(matches(&found, &[-1., 0.5, 4.], 1e-9), "{:?}", found);
+        assert!(matches(&solve_quartic(0., 0., 1., -3., 2.), &[1., 2.], 1e-12));
+        assert!(solve_quartic(0., 0., 0., 0., 1.).is_empty());
+    }
+}
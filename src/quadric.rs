@@ -0,0 +1,343 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, dot, tangents, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+
+/// Surfaces a `Quadric` can take, each running up its axis from the base
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuadricKind {
+    /// Constant radius along the whole height
+    Cylinder,
+    /// Full radius at the base, narrowing to a point at the top
+    Cone,
+    /// Bowl with its vertex at the base, widening to full radius at the top
+    Paraboloid,
+}
+
+/// Cylinder, cone or paraboloid standing on `base` and reaching along `axis`, which
+/// also gives its height. Surfaces are intersected analytically rather than tessellated.
+///
+/// Side UVs run around the axis and then up it, while caps use the angle around the
+/// axis and distance out from it.
+pub struct Quadric<T: Material> {
+    kind: QuadricKind,
+    base: Point3,
+    // Local frame, with z running up the axis
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    height: f32,
+    radius: f32,
+    phi_max: f32,
+    capped: bool,
+    material: Arc<T>,
+}
+
+impl<T: Material> Quadric<T> {
+    fn new(kind: QuadricKind, base: Point3, axis: Vec3, radius: f32, material: Arc<T>) -> Self {
+        let z = unit_vector(axis);
+        let (x, y) = tangents(&z);
+        Quadric {
+            kind,
+            base,
+            x,
+            y,
+            z,
+            height: axis.length(),
+            radius,
+            phi_max: 2. * PI,
+            capped: false,
+            material,
+        }
+    }
+
+    pub fn cylinder(base: Point3, axis: Vec3, radius: f32, material: Arc<T>) -> Self {
+        Self::new(QuadricKind::Cylinder, base, axis, radius, material)
+    }
+
+    pub fn cone(base: Point3, axis: Vec3, radius: f32, material: Arc<T>) -> Self {
+        Self::new(QuadricKind::Cone, base, axis, radius, material)
+    }
+
+    pub fn paraboloid(base: Point3, axis: Vec3, radius: f32, material: Arc<T>) -> Self {
+        Self::new(QuadricKind::Paraboloid, base, axis, radius, material)
+    }
+
+    /// Only sweeps the surface `degrees` around the axis, leaving a wedge shaped gap
+    pub fn sweep(mut self, degrees: f32) -> Self {
+        self.phi_max = degrees.clamp(0., 360.).to_radians();
+        self
+    }
+
+    /// Closes the open ends of the surface with flat caps. Cones have no cap at their
+    /// point and paraboloids none at their vertex.
+    pub fn capped(mut self) -> Self {
+        self.capped = true;
+        self
+    }
+
+    // Converts a world space vector into the local frame
+    fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::from(dot(v, &self.x), dot(v, &self.y), dot(v, &self.z))
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.x + v.y() * self.y + v.z() * self.z
+    }
+
+    // Angle around the axis from 0 to 2π, if it lies within the sweep
+    fn angle(&self, p: &Point3) -> Option<f32> {
+        let phi = p.y().atan2(p.x()).rem_euclid(2. * PI);
+        if phi <= self.phi_max {Some(phi)} else {None}
+    }
+
+    // Coefficients of the quadratic in t for a local space ray
+    fn coefficients(&self, o: &Point3, d: &Vec3) -> (f32, f32, f32) {
+        let (r, h) = (self.radius, self.height);
+        match self.kind {
+            QuadricKind::Cylinder => {
+                (d.x() * d.x() + d.y() * d.y(),
+                 2. * (d.x() * o.x() + d.y() * o.y()),
+                 o.x() * o.x() + o.y() * o.y() - r * r)
+            },
+            QuadricKind::Cone => {
+                let k = (r / h) * (r / h);
+                let oz = o.z() - h;
+                (d.x() * d.x() + d.y() * d.y() - k * d.z() * d.z(),
+                 2. * (d.x() * o.x() + d.y() * o.y() - k * d.z() * oz),
+                 o.x() * o.x() + o.y() * o.y() - k * oz * oz)
+            },
+            QuadricKind::Paraboloid => {
+                let k = h / (r * r);
+                (k * (d.x() * d.x() + d.y() * d.y()),
+                 2. * k * (d.x() * o.x() + d.y() * o.y()) - d.z(),
+                 k * (o.x() * o.x() + o.y() * o.y()) - o.z())
+            },
+        }
+    }
+
+    // Outward facing local space normal of the side at p
+    fn side_normal(&self, p: &Point3) -> Vec3 {
+        match self.kind {
+            QuadricKind::Cylinder => Vec3::from(p.x(), p.y(), 0.),
+            QuadricKind::Cone => {
+                let k = (self.radius / self.height) * (self.radius / self.height);
+                let normal = Vec3::from(p.x(), p.y(), k * (self.height - p.z()));
+                // The point has no normal of its own, so it faces up the axis
+                if normal.near_zero() {Vec3::from(0., 0., 1.)} else {normal}
+            },
+            QuadricKind::Paraboloid => {
+                let k = self.height / (self.radius * self.radius);
+                Vec3::from(2. * k * p.x(), 2. * k * p.y(), -1.)
+            },
+        }
+    }
+
+    // Nearest hit on the curved side, as (t, local normal, uv)
+    fn hit_side(&self, o: &Point3, d: &Vec3, t_min: f32,
+                t_max: f32) -> Option<(f32, Vec3, (f32, f32))> {
+        let (a, b, c) = self.coefficients(o, d);
+        let (t0, t1) = if a.abs() < 1e-12 {
+            // Rays parallel to a paraboloid's axis or a cone's slope only cross it once
+            if b.abs() < 1e-12 {
+                return None;
+            }
+            (-c / b, f32::INFINITY)
+        } else {
+            let discriminant = b * b - 4. * a * c;
+            if discriminant < 0. {
+                return None;
+            }
+
+            // Numerically stable form of the quadratic formula. q is only zero for a
+            // double root at zero, where c / q would be NaN.
+            let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+            let (t0, t1) = (q / a, if q == 0. {0.} else {c / q});
+            if t0 < t1 {(t0, t1)} else {(t1, t0)}
+        };
+
+        [t0, t1].into_iter().find_map(|t| {
+            if t < t_min || t > t_max {
+                return None;
+            }
+            let p = *o + t * *d;
+            if p.z() < 0. || p.z() > self.height {
+                return None;
+            }
+            let phi = self.angle(&p)?;
+            Some((t, self.side_normal(&p), (phi / self.phi_max, p.z() / self.height)))
+        })
+    }
+
+    // Nearest hit on either cap, as (t, local normal, uv)
+    fn hit_caps(&self, o: &Point3, d: &Vec3, t_min: f32,
+                t_max: f32) -> Option<(f32, Vec3, (f32, f32))> {
+        if !self.capped || d.z().abs() < 1e-12 {
+            return None;
+        }
+
+        let caps: &[(f32, f32)] = match self.kind {
+            QuadricKind::Cylinder => &[(0., -1.), (self.height, 1.)],
+            QuadricKind::Cone => &[(0., -1.)],
+            QuadricKind::Paraboloid => &[(self.height, 1.)],
+        };
+        caps.iter().filter_map(|&(z, facing)| {
+            let t = (z - o.z()) / d.z();
+            if t < t_min || t > t_max {
+                return None;
+            }
+            let p = *o + t * *d;
+            let r = (p.x() * p.x() + p.y() * p.y()).sqrt();
+            if r > self.radius {
+                return None;
+            }
+            let phi = self.angle(&p)?;
+            Some((t, Vec3::from(0., 0., facing), (phi / self.phi_max, r / self.radius)))
+        }).min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Quadric<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let o = self.to_local(&(*ray.origin() - self.base));
+        let d = self.to_local(ray.direction());
+
+        let side = self.hit_side(&o, &d, t_min, t_max);
+        let cap = self.hit_caps(&o, &d, t_min, side.map_or(t_max, |s| s.0));
+        let (t, normal, uv) = cap.or(side)?;
+
+        let normal = unit_vector(self.to_world(&normal));
+        Some(HitRecord::from(t, ray, normal, uv, self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius;
+        let corners: Vec<Point3> = [(-r, -r), (-r, r), (r, -r), (r, r)].iter()
+            .flat_map(|&(x, y)| [0., self.height].map(|z| Vec3::from(x, y, z)))
+            .map(|p| self.base + self.to_world(&p))
+            .collect();
+        Some(Aabb::around(&corners))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn material() -> Arc<Lambertian> {
+        Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5)))
+    }
+
+    fn upright(kind: QuadricKind) -> Quadric<Lambertian> {
+        // Radius 1 and height 2, standing on the origin
+        Quadric::new(kind, Vec3::new(), Vec3::from(0., 0., 2.), 1., material())
+    }
+
+    // Distance, outward normal and uv of the first hit along a ray
+    fn first_hit<T: Material + Sync + Send>(quadric: &Quadric<T>, from: Point3,
+                                            direction: Vec3) -> Option<(f32, Vec3, (f32, f32))> {
+        let hit = quadric.hit(&Ray::from(from, direction), 0.001, f32::INFINITY)?;
+        let normal = if hit.front_face {hit.normal} else {-hit.normal};
+        Some((hit.t, normal, (hit.u, hit.v)))
+    }
+
+    fn assert_near(v: Vec3, expected: [f32; 3]) {
+        let found = [v.x(), v.y(), v.z()];
+        assert!(found.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-4),
+                "{:?} instead of {:?}", found, expected);
+    }
+
+    #[test]
+    fn side_normals_point_outward() {
+        let from = Vec3::from(5., 0., 0.5);
+        let side = Vec3::from(-1., 0., 0.);
+        let (t, normal, _) = first_hit(&upright(QuadricKind::Cylinder), from, side).unwrap();
+        assert!((t - 4.).abs() < 1e-4);
+        assert_near(normal, [1., 0., 0.]);
+
+        // Radius 0.75 at this height, sloping in toward the point
+        let (t, normal, _) = first_hit(&upright(QuadricKind::Cone), from, side).unwrap();
+        assert!((t - 4.25).abs() < 1e-4);
+        assert_near(normal, [2. / 5f32.sqrt(), 0., 1. / 5f32.sqrt()]);
+
+        // Radius 0.5 at this height, with the bowl's outside facing down
+        let (t, normal, _) = first_hit(&upright(QuadricKind::Paraboloid), from, side).unwrap();
+        assert!((t - 4.5).abs() < 1e-4);
+        assert_near(normal, [2. / 5f32.sqrt(), 0., -1. / 5f32.sqrt()]);
+    }
+
+    #[test]
+    fn cone_point_faces_up_the_axis() {
+        let cone = upright(QuadricKind::Cone);
+        let (t, normal, _) = first_hit(&cone, Vec3::from(0., 0., 5.),
+                                       Vec3::from(0., 0., -1.)).unwrap();
+        assert!((t - 3.).abs() < 1e-4);
+        assert_near(normal, [0., 0., 1.]);
+
+        // Leaving the point sideways gives a double root at zero
+        let ray = Ray::from(Vec3::from(0., 0., 2.), Vec3::from(1., 0., 0.));
+        let hit = cone.hit(&ray, -1., f32::INFINITY).unwrap();
+        assert_eq!(hit.t, 0.);
+        assert_near(if hit.front_face {hit.normal} else {-hit.normal}, [0., 0., 1.]);
+    }
+
+    #[test]
+    fn caps_close_the_ends() {
+        let down = Vec3::from(0., 0., -1.);
+        let above = Vec3::from(0.5, 0., 5.);
+        let below = Vec3::from(0.5, 0., -5.);
+
+        // Rays along the axis pass straight through an open cylinder
+        assert!(first_hit(&upright(QuadricKind::Cylinder), above, down).is_none());
+        let cylinder = upright(QuadricKind::Cylinder).capped();
+        let (t, normal, (_, v)) = first_hit(&cylinder, above, down).unwrap();
+        assert!((t - 3.).abs() < 1e-4 && (v - 0.5).abs() < 1e-4);
+        assert_near(normal, [0., 0., 1.]);
+        let (t, normal, _) = first_hit(&cylinder, below, -down).unwrap();
+        assert!((t - 5.).abs() < 1e-4);
+        assert_near(normal, [0., 0., -1.]);
+
+        // Cones are only capped at their base and paraboloids at their rim
+        let cone = upright(QuadricKind::Cone).capped();
+        let (t, normal, _) = first_hit(&cone, above, down).unwrap();
+        assert!((t - 4.).abs() < 1e-4);
+        assert_near(normal, [2. / 5f32.sqrt(), 0., 1. / 5f32.sqrt()]);
+        assert_near(first_hit(&cone, below, -down).unwrap().1, [0., 0., -1.]);
+        let paraboloid = upright(QuadricKind::Paraboloid).capped();
+        assert_near(first_hit(&paraboloid, above, down).unwrap().1, [0., 0., 1.]);
+        assert!((first_hit(&paraboloid, below, -down).unwrap().0 - 5.5).abs() < 1e-4);
+        // Rays parallel to one side of a cone cross the other side only once
+        let (t, normal, _) = first_hit(&upright(QuadricKind::Cone), Vec3::from(0.5, 0., -1.),
+                                       Vec3::from(-1., 0., 2.)).unwrap();
+        assert!((t - 1.).abs() < 1e-4);
+        assert_near(normal, [-2. / 5f32.sqrt(), 0., 1. / 5f32.sqrt()]);
+    }
+
+    #[test]
+    fn partial_sweep_leaves_a_gap() {
+        // The sweep starts from the local x axis, which points along -y for an upright
+        // axis, so half a turn covers the side facing +x and leaves the side facing -x
+        let cylinder = upright(QuadricKind::Cylinder).sweep(180.).capped();
+        let (t, normal, (u, _)) = first_hit(&cylinder, Vec3::from(5., 0., 1.),
+                                            Vec3::from(-1., 0., 0.)).unwrap();
+        assert!((t - 4.).abs() < 1e-4 && (u - 0.5).abs() < 1e-4);
+        assert_near(normal, [1., 0., 0.]);
+
+        // Through the gap the ray only finds the inside of the far wall
+        let (t, normal, _) = first_hit(&cylinder, Vec3::from(-5., 0., 1.),
+                                       Vec3::from(1., 0., 0.)).unwrap();
+        assert!((t - 6.).abs() < 1e-4);
+        assert_near(normal, [1., 0., 0.]);
+
+        // The caps are cut away with the side
+        let down = Vec3::from(0., 0., -1.);
+        assert!(first_hit(&cylinder, Vec3::from(-0.5, 0., 5.), down).is_none());
+        assert!(first_hit(&cylinder, Vec3::from(0.5, 0., 5.), down).is_some());
+    }
+}
@@ -34,3 +34,9 @@ impl Ray {
     }
 }
 
+impl Default for Ray {
+    fn default() -> Self {
+        Ray::new()
+    }
+}
+
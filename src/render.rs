@@ -0,0 +1,347 @@
+use rand::prelude::*;
+use rayon::prelude::*;
+
+use crate::vec3::{Color, unit_vector};
+use crate::ray::Ray;
+use crate::hittable::{HitRecord, Hittable};
+use crate::camera::CameraModel;
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::aov::{AovFilm, AovSample, FirstHit, GeometryPixel};
+
+pub const TILE_SIZE: u32 = 32;
+
+/// Rectangular block of pixels which is sampled as a unit
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub samples: u32,
+    /// Seed for the positions sampled on the film. Lens, aperture and material sampling
+    /// use the thread's own generator, so a resumed tile picks up with fresh positions
+    /// but isn't an exact replay of an uninterrupted render.
+    pub rng_state: u64,
+}
+
+/// Everything needed to continue a partially completed render
+pub struct RenderState {
+    pub film: Film,
+    pub tiles: Vec<Tile>,
+    pub filter: Filter,
+    pub scene_seed: u64,
+    pub aovs: Option<AovFilm>,
+}
+
+impl RenderState {
+    pub fn new(width: u32, height: u32, filter: Filter, aovs: bool) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut tiles = Vec::new();
+        for y0 in (0..height).step_by(TILE_SIZE as usize) {
+            for x0 in (0..width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + TILE_SIZE).min(width),
+                    y1: (y0 + TILE_SIZE).min(height),
+                    samples: 0,
+                    rng_state: rng.gen(),
+                });
+            }
+        }
+
+        RenderState {
+            film: Film::new(width, height),
+            tiles,
+            filter,
+            scene_seed: rng.gen(),
+            aovs: if aovs {Some(AovFilm::new(width, height))} else {None},
+        }
+    }
+
+    // Fewest samples taken by any tile
+    pub fn min_samples(&self) -> u32 {
+        self.tiles.iter().map(|tile| tile.samples).min().unwrap_or(0)
+    }
+
+    /// Takes up to `samples` more samples per pixel in every tile, stopping each
+    /// tile once it has reached `target` samples
+    pub fn render_pass<T: Hittable>(&mut self, world: &T, camera: &dyn CameraModel,
+                                    samples: u32, target: u32, max_depth: u32) {
+        let width = self.film.width();
+        let height = self.film.height();
+        let filter = self.filter;
+        let aovs = self.aovs.is_some();
+
+        // Samples may be splatted onto pixels outside of the tile they were taken in
+        let margin = (filter.radius - 0.5).max(0.).ceil() as u32;
+
+        let results: Vec<(Splat, Option<AovSplat>, u32)> = self.tiles.par_iter_mut().map(|tile| {
+            let count = samples.min(target.saturating_sub(tile.samples));
+            let mut rng = StdRng::seed_from_u64(tile.rng_state);
+            let bounds = (tile.x0.saturating_sub(margin),
+                          tile.y0.saturating_sub(margin),
+                          (tile.x1 + margin).min(width),
+                          (tile.y1 + margin).min(height));
+            let mut splat = Splat::new(bounds);
+            let mut aov_splat = if aovs {Some(AovSplat::new(bounds, tile))} else {None};
+
+            for y in tile.y0..tile.y1 {
+                for x in tile.x0..tile.x1 {
+                    for _ in 0..count {
+                        // Position on the film, measured in pixels from the top left
+                        let px = x as f32 + rng.gen::<f32>();
+                        let py = y as f32 + rng.gen::<f32>();
+                        let u = px / (width - 1) as f32;
+                        let v = (height as f32 - py) / (height - 1) as f32;
+
+                        // Film positions the camera has no ray for are left black
+                        let ray = camera.get_ray(u, v);
+                        match aov_splat.as_mut() {
+                            Some(aov_splat) => {
+                                let sample = match ray {
+                                    Some(ray) => trace_aovs(&ray, world, max_depth),
+                                    None => AovSample::new(),
+                                };
+                                splat.add(&filter, px, py, sample.color());
+                                aov_splat.add(&filter, x, y, px, py, &sample);
+                            },
+                            None => {
+                                let color = match ray {
+                                    Some(ray) => ray_color(&ray, world, max_depth),
+                                    None => Color::new(),
+                                };
+                                splat.add(&filter, px, py, color);
+                            },
+                        }
+                    }
+                }
+            }
+
+            tile.samples += count;
+            tile.rng_state = rng.gen();
+            (splat, aov_splat, count)
+        }).collect();
+
+        // Fold the tile results back into the film
+        for (splat, aov_splat, count) in results {
+            if count == 0 {
+                continue;
+            }
+            splat.merge_into(&mut self.film);
+            if let (Some(aov_splat), Some(aov_film)) = (aov_splat, self.aovs.as_mut()) {
+                aov_splat.merge_into(aov_film);
+            }
+        }
+    }
+}
+
+// Filtered samples gathered over one tile and its surrounding margin
+struct Splat {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+    abs_weights: Vec<f32>,
+}
+
+impl Splat {
+    fn new((x0, y0, x1, y1): (u32, u32, u32, u32)) -> Self {
+        let len = ((x1 - x0) * (y1 - y0)) as usize;
+        Splat {
+            x0,
+            y0,
+            x1,
+            y1,
+            sums: vec![Color::new(); len],
+            weights: vec![0.; len],
+            abs_weights: vec![0.; len],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y - self.y0) * (self.x1 - self.x0) + (x - self.x0)) as usize
+    }
+
+    fn merge_into(&self, film: &mut Film) {
+        for y in self.y0..self.y1 {
+            for x in self.x0..self.x1 {
+                let idx = self.index(x, y);
+                film.accumulate(x, y, self.sums[idx], self.weights[idx], self.abs_weights[idx]);
+            }
+        }
+    }
+
+    // Adds a sample to every pixel whose center lies within the filter's radius
+    fn add(&mut self, filter: &Filter, px: f32, py: f32, color: Color) {
+        let min_x = ((px - 0.5 - filter.radius).ceil().max(self.x0 as f32)) as u32;
+        let max_x = ((px - 0.5 + filter.radius).floor() as i64).min(self.x1 as i64 - 1);
+        let min_y = ((py - 0.5 - filter.radius).ceil().max(self.y0 as f32)) as u32;
+        let max_y = ((py - 0.5 + filter.radius).floor() as i64).min(self.y1 as i64 - 1);
+
+        for y in min_y as i64..=max_y {
+            for x in min_x as i64..=max_x {
+                let (x, y) = (x as u32, y as u32);
+                let weight = filter.evaluate(px - (x as f32 + 0.5), py - (y as f32 + 0.5));
+                if weight != 0. {
+                    let idx = self.index(x, y);
+                    self.sums[idx] += weight * color;
+                    self.weights[idx] += weight;
+                    self.abs_weights[idx] += weight.abs();
+                }
+            }
+        }
+    }
+}
+
+// Lighting and first hit data gathered over one tile
+struct AovSplat {
+    direct: Splat,
+    indirect: Splat,
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    geometry: Vec<GeometryPixel>,
+}
+
+impl AovSplat {
+    fn new(bounds: (u32, u32, u32, u32), tile: &Tile) -> Self {
+        let len = ((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize;
+        AovSplat {
+            direct: Splat::new(bounds),
+            indirect: Splat::new(bounds),
+            x0: tile.x0,
+            y0: tile.y0,
+            x1: tile.x1,
+            y1: tile.y1,
+            geometry: vec![GeometryPixel::default(); len],
+        }
+    }
+
+    // Lighting is filtered like the beauty pass, first hit data stays in its pixel
+    fn add(&mut self, filter: &Filter, x: u32, y: u32, px: f32, py: f32, sample: &AovSample) {
+        self.direct.add(filter, px, py, sample.direct);
+        self.indirect.add(filter, px, py, sample.indirect);
+        if let Some(hit) = &sample.first_hit {
+            let idx = ((y - self.y0) * (self.x1 - self.x0) + (x - self.x0)) as usize;
+            self.geometry[idx].add(hit);
+        }
+    }
+
+    fn merge_into(self, aov_film: &mut AovFilm) {
+        self.direct.merge_into(&mut aov_film.direct);
+        self.indirect.merge_into(&mut aov_film.indirect);
+        let mut geometry = self.geometry.iter();
+        for y in self.y0..self.y1 {
+            for x in self.x0..self.x1 {
+                aov_film.geometry_mut(x, y).merge(geometry.next().unwrap());
+            }
+        }
+    }
+}
+
+pub fn ray_color<T: Hittable>(ray: &Ray, world: &T, depth: u32) -> Color {
+    // Check if we've exceeded the 'bounce limit'
+    if depth == 0 {
+        return Color::new();
+    }
+
+    match world.hit(ray, 0.001, f32::INFINITY) {
+        Some(record) => shade(ray, &record, world, depth),
+        None => background(ray),
+    }
+}
+
+// Light leaving a surface back along the ray which hit it
+fn shade<T: Hittable>(ray: &Ray, record: &HitRecord, world: &T, depth: u32) -> Color {
+    let mut scattered = Ray::new();
+    let mut attenuation = Color::new();
+
+    if record.material.scatter(ray, record, &mut attenuation, &mut scattered) {
+        attenuation * ray_color(&scattered, world, depth-1)
+    } else {
+        Color::new()
+    }
+}
+
+fn background(ray: &Ray) -> Color {
+    let unit_direction = unit_vector(*ray.direction());
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    (1.0 - t) * Color::from(1.0, 1.0, 1.0) + t * Color::from(0.5, 0.7, 1.0)
+}
+
+// Traces a camera ray just as `ray_color` does, while also recording what it first hit.
+// Light reaching the camera straight from the background, or after a single bounce,
+// counts as direct and everything else as indirect.
+fn trace_aovs<T: Hittable>(ray: &Ray, world: &T, depth: u32) -> AovSample {
+    let record = match world.hit(ray, 0.001, f32::INFINITY) {
+        Some(record) => record,
+        None => {
+            return AovSample {
+                first_hit: None,
+                direct: background(ray),
+                indirect: Color::new(),
+            };
+        },
+    };
+
+    let mut sample = AovSample {
+        first_hit: Some(FirstHit {
+            depth: record.t * ray.direction().length(),
+            normal: record.normal,
+            albedo: record.material.albedo(&record),
+            position: record.p,
+            material_id: record.material.id(),
+            object_id: record.object_id,
+        }),
+        direct: Color::new(),
+        indirect: Color::new(),
+    };
+
+    let mut scattered = Ray::new();
+    let mut attenuation = Color::new();
+    if depth > 1 && record.material.scatter(ray, &record, &mut attenuation, &mut scattered) {
+        match world.hit(&scattered, 0.001, f32::INFINITY) {
+            Some(next) => sample.indirect = attenuation * shade(&scattered, &next, world, depth-1),
+            None => sample.direct = attenuation * background(&scattered),
+        }
+    }
+
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use crate::scene::{MaterialDescription, SceneDescription, Shape};
+
+    #[test]
+    fn first_hit_fills_geometry() {
+        // An unused material first, so the sphere's material is the second
+        let mut scene = SceneDescription::new();
+        scene.add_material("unused", MaterialDescription::Dielectric {refraction: 1.5})
+             .unwrap();
+        let red = scene.add_material("red", MaterialDescription::Lambertian {
+            albedo: Color::from(0.8, 0.1, 0.1),
+        }).unwrap();
+        scene.add_object(Shape::Sphere {center: Vec3::from(0., 0., -3.), radius: 1.}, red);
+
+        // Building again must give the same IDs, so passes line up between renders
+        for world in [scene.build(), scene.build()] {
+            let ray = Ray::from(Vec3::new(), Vec3::from(0., 0., -2.));
+            let hit = trace_aovs(&ray, &world, 1).first_hit.unwrap();
+            assert!((hit.depth - 2.).abs() < 1e-5);
+            assert!((hit.position.z() + 2.).abs() < 1e-5);
+            assert!((hit.normal.z() - 1.).abs() < 1e-5);
+            assert_eq!((hit.albedo.x(), hit.albedo.y(), hit.albedo.z()), (0.8, 0.1, 0.1));
+            assert_eq!((hit.material_id, hit.object_id), (2, 1));
+
+            let ray = Ray::from(Vec3::new(), Vec3::from(0., 1., 0.));
+            assert!(trace_aovs(&ray, &world, 1).first_hit.is_none());
+        }
+    }
+}
@@ -0,0 +1,831 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::vec3::{Color, Point3, Vec3};
+use crate::animation::{CameraPath, CameraPose, Interpolation};
+use crate::camera::{PhysicalCamera, Projection};
+use crate::hittable::HittableList;
+use crate::material::{Dielectric, Lambertian, Material, Metal, Pbr};
+use crate::sphere::Sphere;
+use crate::planar::{Disk, Plane, Quad};
+use crate::cuboid::Cuboid;
+use crate::quadric::{Quadric, QuadricKind};
+use crate::torus::Torus;
+use crate::mesh::{PolygonMesh, TriangleMesh};
+use crate::{obj, ply, stl};
+
+/// Scene description read from a text file. Each line holds a keyword followed by its
+/// values, and anything after a '#' is a comment:
+///
+/// ```text
+/// frames 1 120                      # frame range to render
+/// seed 42                           # seed for the randomized scene
+/// interpolation catmull-rom         # for every property, or name one after the mode
+/// position 13 2 3                   # camera settings for the whole animation
+/// target 0 0 0
+/// key 1                             # following settings are keyed at frame 1
+/// fov 20
+/// key 120
+/// fov 35
+/// ```
+///
+/// Camera settings are `position`, `target`, `fov` (vertical, in degrees) and `focus`
+/// (distance). Any left out keep the renderer's defaults, and without a seed one is
+/// picked at random. `projection orthographic <width>` views the scene through an
+/// orthographic camera seeing `width` units across, and `projection perspective` asks
+/// for the usual camera. `lens <focal length> <sensor width> <sensor height> <f-number>
+/// <shutter> <iso>` describes the camera photographically, setting its aperture and
+/// exposure, and its field of view unless `fov` is given too. Unlike the other settings
+/// the projection and lens can't be keyed.
+///
+/// Scenes may also list their own objects, which then replace the randomized scene.
+/// Materials are named so objects can share them, and must come before their first use:
+///
+/// ```text
+/// material ground lambertian 0.5 0.5 0.5   # albedo
+/// material gold metal 0.8 0.6 0.2 0.1      # albedo and fuzz
+/// material glass dielectric 1.5            # refractive index
+/// material clay pbr 0.8 0.4 0.3 0 0.6      # base color, metallic and roughness
+/// plane ground 0 0 0 0 1 0                 # point and normal
+/// sphere glass 0 1 0 1                     # center and radius
+/// disk gold 2 0.01 0 0 1 0 0.5             # center, normal and radius
+/// quad gold -3 0 -1 1 0 0 0 1 0            # corner and two edges
+/// box ground -1 0 2 1 0.5 3                # opposite corners
+/// cylinder clay 3 0 2 0 1 0 0.5 capped     # base, axis and radius
+/// cone gold -2 0 3 0 2 0 0.5 sweep 270     # the same, swept partway around
+/// paraboloid clay 0 0 4 0 1 0 1
+/// torus gold 0 0.3 -3 0 1 0 0.6 0.2        # center, axis and both radii
+/// mesh clay models/bunny.ply               # OBJ, PLY or STL file
+/// ```
+///
+/// Cylinders, cones and paraboloids may end with `sweep <degrees>` to leave a gap around
+/// their axis and `capped` to close their ends. Mesh paths are read relative to the
+/// working directory and, like material names, must be a single word without a '#'.
+///
+/// Writing a description out with `to_string` or `save` gives back a file which reads
+/// in as the same scene.
+#[derive(Debug, Clone)]
+pub struct SceneDescription {
+    pub frames: Option<(u32, u32)>,
+    pub seed: Option<u64>,
+    pub position: Option<Point3>,
+    pub target: Option<Point3>,
+    pub fov: Option<f32>,
+    pub focus: Option<f32>,
+    pub projection: Option<Projection>,
+    /// Width of the view for an orthographic projection
+    pub view_width: Option<f32>,
+    pub lens: Option<PhysicalCamera>,
+    pub path: CameraPath,
+    /// Materials by name, which objects refer to by index
+    pub materials: Vec<(String, MaterialDescription)>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+/// Material of a scene description, with the values its constructor takes
+#[derive(Debug, Clone, Copy)]
+pub enum MaterialDescription {
+    Lambertian {albedo: Color},
+    Metal {albedo: Color, fuzz: f32},
+    Dielectric {refraction: f32},
+    Pbr {base_color: Color, metallic: f32, roughness: f32},
+}
+
+/// Shape of a scene description, with the values its constructor takes
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Sphere {center: Point3, radius: f32},
+    Plane {point: Point3, normal: Vec3},
+    Disk {center: Point3, normal: Vec3, radius: f32},
+    Quad {corner: Point3, u: Vec3, v: Vec3},
+    Cuboid {a: Point3, b: Point3},
+    /// Cylinder, cone or paraboloid, swept `sweep` degrees around its axis
+    Quadric {kind: QuadricKind, base: Point3, axis: Vec3, radius: f32, sweep: f32,
+             capped: bool},
+    Torus {center: Point3, axis: Vec3, major_radius: f32, minor_radius: f32},
+    /// Mesh read from a file, along with the path it's written out as
+    Mesh {path: String, mesh: Arc<PolygonMesh>},
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectDescription {
+    pub shape: Shape,
+    /// Index into the scene's materials
+    pub material: usize,
+}
+
+impl SceneDescription {
+    pub fn new() -> Self {
+        SceneDescription {
+            frames: None,
+            seed: None,
+            position: None,
+            target: None,
+            fov: None,
+            focus: None,
+            projection: None,
+            view_width: None,
+            lens: None,
+            path: CameraPath::new(Interpolation::Linear),
+            materials: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut scene = SceneDescription::new();
+
+        // Frame of the most recent key, if any
+        let mut key: Option<f32> = None;
+        for (n, line) in text.lines().enumerate() {
+            let invalid = |msg: &str| {
+                Error::new(ErrorKind::InvalidData,
+                           format!("invalid scene file: line {}: {}", n + 1, msg))
+            };
+
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let keyword = match fields.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let values: Vec<&str> = fields.collect();
+            let expect = |count: usize| {
+                if values.len() == count {
+                    Ok(())
+                } else {
+                    Err(invalid(&format!("\"{}\" expects {} values, found {}", keyword, count,
+                                         values.len())))
+                }
+            };
+            fn parse<T: FromStr>(value: &str,
+                                 invalid: &dyn Fn(&str) -> Error) -> Result<T, Error> {
+                value.parse().map_err(|_| invalid(&format!("bad value \"{}\"", value)))
+            }
+            let vector = || -> Result<Vec3, Error> {
+                expect(3)?;
+                Ok(Vec3::from(parse(values[0], &invalid)?,
+                              parse(values[1], &invalid)?,
+                              parse(values[2], &invalid)?))
+            };
+            let scalar = || -> Result<f32, Error> {
+                expect(1)?;
+                parse(values[0], &invalid)
+            };
+
+            match keyword {
+                "frames" => {
+                    expect(2)?;
+                    let start: u32 = parse(values[0], &invalid)?;
+                    let end: u32 = parse(values[1], &invalid)?;
+                    if end < start {
+                        return Err(invalid("last frame comes before the first"));
+                    }
+                    scene.frames = Some((start, end));
+                },
+                "seed" => {
+                    expect(1)?;
+                    scene.seed = Some(parse(values[0], &invalid)?);
+                },
+                "interpolation" => {
+                    let interpolation = match values.first() {
+                        Some(&"linear") => Interpolation::Linear,
+                        Some(&"catmull-rom") => Interpolation::CatmullRom,
+                        _ => return Err(invalid("expected linear or catmull-rom")),
+                    };
+                    let path = &mut scene.path;
+                    if values.len() == 1 {
+                        path.position.interpolation = interpolation;
+                        path.target.interpolation = interpolation;
+                        path.fov.interpolation = interpolation;
+                        path.focus.interpolation = interpolation;
+                    }
+                    for property in &values[1..] {
+                        match *property {
+                            "position" => path.position.interpolation = interpolation,
+                            "target" => path.target.interpolation = interpolation,
+                            "fov" => path.fov.interpolation = interpolation,
+                            "focus" => path.focus.interpolation = interpolation,
+                            _ => return Err(invalid(&format!("unknown property \"{}\"",
+                                                             property))),
+                        }
+                    }
+                },
+                "key" => {
+                    let frame = scalar()?;
+                    // Frames are numbered from zero, so earlier keys have no frame to land on
+                    if frame < 0. || !frame.is_finite() {
+                        return Err(invalid("key frame should not be negative"));
+                    }
+                    key = Some(frame);
+                },
+                "position" => match key {
+                    Some(frame) => scene.path.position.insert(frame, vector()?),
+                    None => scene.position = Some(vector()?),
+                },
+                "target" => match key {
+                    Some(frame) => scene.path.target.insert(frame, vector()?),
+                    None => scene.target = Some(vector()?),
+                },
+                "fov" => {
+                    let fov = scalar()?;
+                    if fov <= 0. || fov >= 180. {
+                        return Err(invalid("fov should be between 0 and 180 degrees"));
+                    }
+                    match key {
+                        Some(frame) => scene.path.fov.insert(frame, fov),
+                        None => scene.fov = Some(fov),
+                    }
+                },
+                "focus" => {
+                    let focus = scalar()?;
+                    if focus <= 0. {
+                        return Err(invalid("focus distance should be positive"));
+                    }
+                    match key {
+                        Some(frame) => scene.path.focus.insert(frame, focus),
+                        None => scene.focus = Some(focus),
+                    }
+                },
+                "projection" => match values.as_slice() {
+                    ["perspective"] => {
+                        scene.projection = Some(Projection::Perspective);
+                        scene.view_width = None;
+                    },
+                    ["orthographic", width] => {
+                        let width: f32 = parse(width, &invalid)?;
+                        if width <= 0. {
+                            return Err(invalid("orthographic view width should be positive"));
+                        }
+                        scene.projection = Some(Projection::Orthographic);
+                        scene.view_width = Some(width);
+                    },
+                    _ => return Err(invalid("expected perspective or orthographic and a \
+                                             width")),
+                },
+                "lens" => {
+                    expect(6)?;
+                    let n = values.iter().map(|value| parse(value, &invalid))
+                                  .collect::<Result<Vec<f32>, Error>>()?;
+                    if n.iter().any(|&v| v <= 0. || !v.is_finite()) {
+                        return Err(invalid("lens settings should be positive"));
+                    }
+                    scene.lens = Some(PhysicalCamera {
+                        focal_length: n[0],
+                        sensor_width: n[1],
+                        sensor_height: n[2],
+                        f_number: n[3],
+                        shutter: n[4],
+                        iso: n[5],
+                    });
+                },
+                "material" => {
+                    let (name, kind) = match values.as_slice() {
+                        [name, kind, ..] => (*name, *kind),
+                        _ => return Err(invalid("\"material\" expects a name and a type")),
+                    };
+                    let numbers = |count: usize| -> Result<Vec<f32>, Error> {
+                        if values.len() != count + 2 {
+                            return Err(invalid(&format!("{} material expects {} values, \
+                                                         found {}", kind, count,
+                                                        values.len() - 2)));
+                        }
+                        values[2..].iter().map(|value| parse(value, &invalid)).collect()
+                    };
+                    let material = match kind {
+                        "lambertian" => {
+                            let n = numbers(3)?;
+                            MaterialDescription::Lambertian {albedo: Color::from(n[0], n[1], n[2])}
+                        },
+                        "metal" => {
+                            let n = numbers(4)?;
+                            MaterialDescription::Metal {albedo: Color::from(n[0], n[1], n[2]),
+                                                        fuzz: n[3]}
+                        },
+                        "dielectric" => {
+                            MaterialDescription::Dielectric {refraction: numbers(1)?[0]}
+                        },
+                        "pbr" => {
+                            let n = numbers(5)?;
+                            MaterialDescription::Pbr {base_color: Color::from(n[0], n[1], n[2]),
+                                                      metallic: n[3], roughness: n[4]}
+                        },
+                        _ => return Err(invalid(&format!("unknown material type \"{}\"",
+                                                         kind))),
+                    };
+                    scene.add_material(name, material).map_err(|e| invalid(&e.to_string()))?;
+                },
+                "sphere" | "plane" | "disk" | "quad" | "box" | "cylinder" | "cone" | "paraboloid"
+                | "torus" => {
+                    let name = values.first().ok_or_else(|| {
+                        invalid(&format!("\"{}\" expects a material", keyword))
+                    })?;
+                    let material = scene.material(name).ok_or_else(|| {
+                        invalid(&format!("unknown material \"{}\"", name))
+                    })?;
+                    // Values run up to the first option, if there are any
+                    let end = values.iter().skip(1)
+                                    .position(|value| *value == "sweep" || *value == "capped")
+                                    .map_or(values.len(), |p| p + 1);
+                    let numbers = values[1..end].iter().map(|value| parse(value, &invalid))
+                                                .collect::<Result<Vec<f32>, Error>>()?;
+                    let mut shape = Shape::from(keyword, &numbers).ok_or_else(|| {
+                        invalid(&format!("\"{}\" expects a material and {} values, found {}",
+                                         keyword, Shape::value_count(keyword),
+                                         numbers.len()))
+                    })?;
+
+                    let mut options = values[end..].iter();
+                    while let Some(option) = options.next() {
+                        match (&mut shape, *option) {
+                            (Shape::Quadric {sweep, ..}, "sweep") => {
+                                let degrees: f32 = match options.next() {
+                                    Some(value) => parse(value, &invalid)?,
+                                    None => return Err(invalid("\"sweep\" expects degrees")),
+                                };
+                                if !(degrees > 0. && degrees <= 360.) {
+                                    return Err(invalid("sweep should be between 0 and 360 \
+                                                        degrees"));
+                                }
+                                *sweep = degrees;
+                            },
+                            (Shape::Quadric {capped, ..}, "capped") => *capped = true,
+                            _ => return Err(invalid(&format!("\"{}\" doesn't take \"{}\"",
+                                                             keyword, option))),
+                        }
+                    }
+
+                    if let Shape::Plane {normal, ..} | Shape::Disk {normal, ..} = shape {
+                        if normal.near_zero() {
+                            return Err(invalid("normal should not be zero"));
+                        }
+                    }
+                    if let Shape::Quadric {axis, ..} | Shape::Torus {axis, ..} = shape {
+                        if axis.near_zero() {
+                            return Err(invalid("axis should not be zero"));
+                        }
+                    }
+                    scene.add_object(shape, material);
+                },
+                "mesh" => {
+                    let (name, path) = match values.as_slice() {
+                        [name, path] => (*name, *path),
+                        _ => return Err(invalid("\"mesh\" expects a material and a path")),
+                    };
+                    let material = scene.material(name).ok_or_else(|| {
+                        invalid(&format!("unknown material \"{}\"", name))
+                    })?;
+                    let shape = Shape::mesh(path).map_err(|e| invalid(&e.to_string()))?;
+                    scene.add_object(shape, material);
+                },
+                _ => return Err(invalid(&format!("unknown keyword \"{}\"", keyword))),
+            }
+        }
+
+        Ok(scene)
+    }
+
+    /// Index of the material with this name
+    pub fn material(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|(n, _)| n == name)
+    }
+
+    /// Adds a material, returning the index objects use to refer to it. Names are written
+    /// out as they are, so must be a single word without a '#', and can't be reused.
+    pub fn add_material(&mut self, name: &str,
+                        material: MaterialDescription) -> Result<usize, Error> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '#') {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("material name \"{}\" should be a single word \
+                                           without '#'", name)));
+        }
+        if self.material(name).is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("material \"{}\" is defined twice", name)));
+        }
+        self.materials.push((name.to_string(), material));
+        Ok(self.materials.len() - 1)
+    }
+
+    pub fn add_object(&mut self, shape: Shape, material: usize) {
+        self.objects.push(ObjectDescription {shape, material});
+    }
+
+    /// World holding the scene's objects, in the order they're listed
+    pub fn build(&self) -> HittableList {
+        // Material IDs follow the order materials are listed in, starting from one
+        let materials = self.materials.iter().zip(1..).map(|((_, material), id)| {
+            match *material {
+                MaterialDescription::Lambertian {albedo} => {
+                    BuiltMaterial::Lambertian(Arc::new(Lambertian::from(albedo).with_id(id)))
+                },
+                MaterialDescription::Metal {albedo, fuzz} => {
+                    BuiltMaterial::Metal(Arc::new(Metal::from(albedo, fuzz).with_id(id)))
+                },
+                MaterialDescription::Dielectric {refraction} => {
+                    BuiltMaterial::Dielectric(Arc::new(Dielectric::from(refraction)
+                                                           .with_id(id)))
+                },
+                MaterialDescription::Pbr {base_color, metallic, roughness} => {
+                    BuiltMaterial::Pbr(Arc::new(Pbr::from(base_color, metallic, roughness)
+                                                    .with_id(id)))
+                },
+            }
+        }).collect::<Vec<BuiltMaterial>>();
+
+        let mut world = HittableList::new();
+        for object in &self.objects {
+            match &materials[object.material] {
+                BuiltMaterial::Lambertian(m) => object.shape.add_to(&mut world, m.clone()),
+                BuiltMaterial::Metal(m) => object.shape.add_to(&mut world, m.clone()),
+                BuiltMaterial::Dielectric(m) => object.shape.add_to(&mut world, m.clone()),
+                BuiltMaterial::Pbr(m) => object.shape.add_to(&mut world, m.clone()),
+            }
+        }
+        world
+    }
+
+    /// Frames to render, either as given or spanning every key. `None` for a still.
+    pub fn frame_range(&self) -> Option<(u32, u32)> {
+        self.frames.or_else(|| {
+            self.path.frame_range()
+                     .map(|(start, end)| (start.floor() as u32, end.ceil() as u32))
+        })
+    }
+
+    /// Camera at `frame`, starting from `base` and applying the scene's settings and
+    /// then its keys
+    pub fn camera(&self, frame: f32, base: &CameraPose) -> CameraPose {
+        let pose = CameraPose {
+            view_from: self.position.unwrap_or(base.view_from),
+            view_at: self.target.unwrap_or(base.view_at),
+            vfov: self.fov.unwrap_or(base.vfov),
+            focus_dist: self.focus.unwrap_or(base.focus_dist),
+        };
+        self.path.pose(frame, &pose)
+    }
+}
+
+impl Default for SceneDescription {
+    fn default() -> Self {
+        SceneDescription::new()
+    }
+}
+
+impl fmt::Display for SceneDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Settings without a key come first, since every line after a key belongs to it
+        if let Some((start, end)) = self.frames {
+            writeln!(f, "frames {} {}", start, end)?;
+        }
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed {}", seed)?;
+        }
+        let path = &self.path;
+        let smooth: Vec<&str> = [("position", path.position.interpolation),
+                                 ("target", path.target.interpolation),
+                                 ("fov", path.fov.interpolation),
+                                 ("focus", path.focus.interpolation)]
+            .iter()
+            .filter(|(_, interpolation)| *interpolation == Interpolation::CatmullRom)
+            .map(|(property, _)| *property)
+            .collect();
+        match smooth.len() {
+            0 => (),
+            4 => writeln!(f, "interpolation catmull-rom")?,
+            _ => writeln!(f, "interpolation catmull-rom {}", smooth.join(" "))?,
+        }
+        if let Some(position) = self.position {
+            writeln!(f, "position {}", vector(position))?;
+        }
+        if let Some(target) = self.target {
+            writeln!(f, "target {}", vector(target))?;
+        }
+        if let Some(fov) = self.fov {
+            writeln!(f, "fov {}", fov)?;
+        }
+        if let Some(focus) = self.focus {
+            writeln!(f, "focus {}", focus)?;
+        }
+        match (self.projection, self.view_width) {
+            (Some(Projection::Perspective), _) => writeln!(f, "projection perspective")?,
+            (Some(Projection::Orthographic), Some(width)) => {
+                writeln!(f, "projection orthographic {}", width)?
+            },
+            _ => (),
+        }
+        if let Some(lens) = self.lens {
+            writeln!(f, "lens {} {} {} {} {} {}", lens.focal_length, lens.sensor_width,
+                     lens.sensor_height, lens.f_number, lens.shutter, lens.iso)?;
+        }
+
+        for (name, material) in &self.materials {
+            match material {
+                MaterialDescription::Lambertian {albedo} => {
+                    writeln!(f, "material {} lambertian {}", name, vector(*albedo))?
+                },
+                MaterialDescription::Metal {albedo, fuzz} => {
+                    writeln!(f, "material {} metal {} {}", name, vector(*albedo), fuzz)?
+                },
+                MaterialDescription::Dielectric {refraction} => {
+                    writeln!(f, "material {} dielectric {}", name, refraction)?
+                },
+                MaterialDescription::Pbr {base_color, metallic, roughness} => {
+                    writeln!(f, "material {} pbr {} {} {}", name, vector(*base_color),
+                             metallic, roughness)?
+                },
+            }
+        }
+        for object in &self.objects {
+            writeln!(f, "{} {} {}", object.shape.keyword(), self.materials[object.material].0,
+                     object.shape.fields().join(" "))?;
+        }
+
+        let mut frames: Vec<f32> = path.position.keys().iter().map(|k| k.0)
+            .chain(path.target.keys().iter().map(|k| k.0))
+            .chain(path.fov.keys().iter().map(|k| k.0))
+            .chain(path.focus.keys().iter().map(|k| k.0))
+            .collect();
+        frames.sort_by(f32::total_cmp);
+        frames.dedup();
+        for frame in frames {
+            writeln!(f, "key {}", frame)?;
+            if let Some(position) = path.position.key(frame) {
+                writeln!(f, "position {}", vector(position))?;
+            }
+            if let Some(target) = path.target.key(frame) {
+                writeln!(f, "target {}", vector(target))?;
+            }
+            if let Some(fov) = path.fov.key(frame) {
+                writeln!(f, "fov {}", fov)?;
+            }
+            if let Some(focus) = path.focus.key(frame) {
+                writeln!(f, "focus {}", focus)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Components separated by spaces, each written with just enough digits to read back
+// exactly
+fn vector(v: Vec3) -> String {
+    format!("{} {} {}", v.x(), v.y(), v.z())
+}
+
+impl Shape {
+    /// Mesh read from an OBJ, PLY or STL file, as told apart by its extension
+    pub fn mesh(path: &str) -> Result<Self, Error> {
+        if path.is_empty() || path.contains(|c: char| c.is_whitespace() || c == '#') {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("mesh path \"{}\" should be a single word without \
+                                           '#'", path)));
+        }
+        let extension = Path::new(path).extension().and_then(|e| e.to_str())
+                                       .map(str::to_ascii_lowercase);
+        let mesh = match extension.as_deref() {
+            Some("obj") => obj::load(path)?,
+            Some("ply") => ply::load(path)?,
+            Some("stl") => stl::load(path)?,
+            _ => return Err(Error::new(ErrorKind::InvalidInput,
+                                       format!("mesh \"{}\" is not an OBJ, PLY or STL file",
+                                               path))),
+        };
+        Ok(Shape::Mesh {path: path.to_string(), mesh: Arc::new(mesh)})
+    }
+
+    // Shape named by a scene file keyword, from its values in the order they're written
+    fn from(keyword: &str, n: &[f32]) -> Option<Self> {
+        if n.len() != Shape::value_count(keyword) {
+            return None;
+        }
+        let v = |i: usize| Vec3::from(n[i], n[i + 1], n[i + 2]);
+        Some(match keyword {
+            "sphere" => Shape::Sphere {center: v(0), radius: n[3]},
+            "plane" => Shape::Plane {point: v(0), normal: v(3)},
+            "disk" => Shape::Disk {center: v(0), normal: v(3), radius: n[6]},
+            "quad" => Shape::Quad {corner: v(0), u: v(3), v: v(6)},
+            "box" => Shape::Cuboid {a: v(0), b: v(3)},
+            "cylinder" | "cone" | "paraboloid" => {
+                let kind = match keyword {
+                    "cylinder" => QuadricKind::Cylinder,
+                    "cone" => QuadricKind::Cone,
+                    _ => QuadricKind::Paraboloid,
+                };
+                Shape::Quadric {kind, base: v(0), axis: v(3), radius: n[6], sweep: 360.,
+                                capped: false}
+            },
+            "torus" => Shape::Torus {center: v(0), axis: v(3), major_radius: n[6],
+                                     minor_radius: n[7]},
+            _ => return None,
+        })
+    }
+
+    fn value_count(keyword: &str) -> usize {
+        match keyword {
+            "sphere" => 4,
+            "plane" | "box" => 6,
+            "disk" | "cylinder" | "cone" | "paraboloid" => 7,
+            "torus" => 8,
+            "quad" => 9,
+            _ => 0,
+        }
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            Shape::Sphere {..} => "sphere",
+            Shape::Plane {..} => "plane",
+            Shape::Disk {..} => "disk",
+            Shape::Quad {..} => "quad",
+            Shape::Cuboid {..} => "box",
+            Shape::Quadric {kind: QuadricKind::Cylinder, ..} => "cylinder",
+            Shape::Quadric {kind: QuadricKind::Cone, ..} => "cone",
+            Shape::Quadric {kind: QuadricKind::Paraboloid, ..} => "paraboloid",
+            Shape::Torus {..} => "torus",
+            Shape::Mesh {..} => "mesh",
+        }
+    }
+
+    // Everything written after the material, in the order it's read back
+    fn fields(&self) -> Vec<String> {
+        let v = |v: &Vec3| [v.x(), v.y(), v.z()];
+        let values = match self {
+            Shape::Sphere {center, radius} => [&v(center)[..], &[*radius]].concat(),
+            Shape::Plane {point, normal} => [v(point), v(normal)].concat(),
+            Shape::Disk {center, normal, radius} => {
+                [&v(center)[..], &v(normal), &[*radius]].concat()
+            },
+            Shape::Quad {corner, u, v: edge} => [v(corner), v(u), v(edge)].concat(),
+            Shape::Cuboid {a, b} => [v(a), v(b)].concat(),
+            Shape::Quadric {base, axis, radius, ..} => {
+                [&v(base)[..], &v(axis), &[*radius]].concat()
+            },
+            Shape::Torus {center, axis, major_radius, minor_radius} => {
+                [&v(center)[..], &v(axis), &[*major_radius, *minor_radius]].concat()
+            },
+            Shape::Mesh {path, ..} => return vec![path.clone()],
+        };
+
+        let mut fields: Vec<String> = values.iter().map(f32::to_string).collect();
+        if let Shape::Quadric {sweep, capped, ..} = self {
+            if *sweep < 360. {
+                fields.extend(["sweep".to_string(), sweep.to_string()]);
+            }
+            if *capped {
+                fields.push("capped".to_string());
+            }
+        }
+        fields
+    }
+
+    fn add_to<T: Material + Sync + Send + 'static>(&self, world: &mut HittableList,
+                                                     material: Arc<T>) {
+        match *self {
+            Shape::Sphere {center, radius} => world.push(Sphere::from(center, radius, material)),
+            Shape::Plane {point, normal} => world.push(Plane::from(point, normal, material)),
+            Shape::Disk {center, normal, radius} => {
+                world.push(Disk::from(center, normal, radius, material))
+            },
+            Shape::Quad {corner, u, v} => world.push(Quad::from(corner, u, v, material)),
+            Shape::Cuboid {a, b} => world.push(Cuboid::from(a, b, material)),
+            Shape::Quadric {kind, base, axis, radius, sweep, capped} => {
+                let quadric = match kind {
+                    QuadricKind::Cylinder => Quadric::cylinder(base, axis, radius, material),
+                    QuadricKind::Cone => Quadric::cone(base, axis, radius, material),
+                    QuadricKind::Paraboloid => Quadric::paraboloid(base, axis, radius, material),
+                };
+                let quadric = quadric.sweep(sweep);
+                world.push(if capped {quadric.capped()} else {quadric})
+            },
+            Shape::Torus {center, axis, major_radius, minor_radius} => {
+                world.push(Torus::from(center, axis, major_radius, minor_radius, material))
+            },
+            Shape::Mesh {ref mesh, ..} => world.push(TriangleMesh::from(mesh, material)),
+        }
+    }
+}
+
+// Materials made for `build`, kept as their own types since shapes are generic over them
+enum BuiltMaterial {
+    Lambertian(Arc<Lambertian>),
+    Metal(Arc<Metal>),
+    Dielectric(Arc<Dielectric>),
+    Pbr(Arc<Pbr>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+    use crate::hittable::Hittable;
+
+    #[test]
+    fn negative_key_frames_are_rejected() {
+        assert!(SceneDescription::parse("key -1\nfov 30\n").is_err());
+        let scene = SceneDescription::parse("key 0\nfov 30\nkey 24\nfov 40\n").unwrap();
+        assert_eq!(scene.frame_range(), Some((0, 24)));
+    }
+
+    #[test]
+    fn every_shape_survives_saving() {
+        let path = std::env::temp_dir().join(format!("scene-mesh-{}.obj",
+                                                     std::process::id()));
+        fs::write(&path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        let mesh = Shape::mesh(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+
+        let mut scene = SceneDescription::new();
+        let materials = [
+            MaterialDescription::Lambertian {albedo: Color::from(0.5, 0.25, 0.125)},
+            MaterialDescription::Metal {albedo: Color::from(0.8, 0.6, 0.2), fuzz: 0.1},
+            MaterialDescription::Dielectric {refraction: 1.5},
+            MaterialDescription::Pbr {base_color: Color::from(0.9, 0.1, 0.3), metallic: 0.25,
+                                      roughness: 0.7},
+        ].map(|material| {
+            let name = format!("m{}", scene.materials.len());
+            scene.add_material(&name, material).unwrap()
+        });
+
+        let (p, axis) = (Point3::from(0.1, -2., 3.3), Vec3::from(0., 1.5, 0.25));
+        let quadric = |kind, sweep, capped| {
+            Shape::Quadric {kind, base: p, axis, radius: 0.3, sweep, capped}
+        };
+        let shapes = [
+            Shape::Sphere {center: p, radius: 1.1},
+            Shape::Plane {point: p, normal: axis},
+            Shape::Disk {center: p, normal: axis, radius: 0.7},
+            Shape::Quad {corner: p, u: axis, v: Vec3::from(1., 0., 0.)},
+            Shape::Cuboid {a: p, b: Point3::from(1., 2., 3.)},
+            quadric(QuadricKind::Cylinder, 360., false),
+            quadric(QuadricKind::Cylinder, 90., true),
+            quadric(QuadricKind::Cone, 270.5, false),
+            quadric(QuadricKind::Paraboloid, 360., true),
+            Shape::Torus {center: p, axis, major_radius: 0.6, minor_radius: 0.2},
+            mesh,
+        ];
+        for (i, shape) in shapes.iter().enumerate() {
+            scene.add_object(shape.clone(), materials[i % materials.len()]);
+        }
+
+        // Meshes are written as their path, so the same file has to be there to read
+        fs::write(&path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        let text = scene.to_string();
+        let reloaded = SceneDescription::parse(&text);
+        fs::remove_file(&path).unwrap();
+        let reloaded = reloaded.unwrap();
+
+        assert_eq!(reloaded.to_string(), text);
+        assert_eq!(format!("{:?}", reloaded.materials), format!("{:?}", scene.materials));
+        assert_eq!(format!("{:?}", reloaded.objects), format!("{:?}", scene.objects));
+        assert_eq!(reloaded.objects.len(), shapes.len());
+        if let Shape::Mesh {mesh, ..} = &reloaded.objects[10].shape {
+            assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+        } else {
+            panic!("expected a mesh");
+        }
+        // Nothing else lies between the mesh and a ray coming down onto it
+        let ray = Ray::from(Point3::from(0.5, 0.5, 1.), Vec3::from(0., 0., -1.));
+        let world = reloaded.build();
+        let hit = world.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shape_options_are_checked() {
+        let text = "material m lambertian 1 1 1\n";
+        let scene = SceneDescription::parse(&format!("{}cone m 0 0 0 0 1 0 1 capped sweep 45\n",
+                                                     text)).unwrap();
+        assert!(matches!(scene.objects[0].shape, Shape::Quadric {kind: QuadricKind::Cone,
+                                                                 sweep: 45., capped: true,
+                                                                 ..}));
+        for (line, message) in [
+            ("sphere m 0 0 0 1 capped", "doesn't take \"capped\""),
+            ("cylinder m 0 0 0 0 1 0 1 sweep", "expects degrees"),
+            ("cylinder m 0 0 0 0 1 0 1 sweep 400", "between 0 and 360"),
+            ("cylinder m 0 0 0 0 1 0 1 round", "bad value"),
+            ("torus m 0 0 0 0 0 0 1 0.5", "axis should not be zero"),
+            ("torus m 0 0 0 0 1 0 1", "expects a material and 8 values"),
+            ("mesh m model.fbx", "not an OBJ, PLY or STL file"),
+            ("mesh m", "expects a material and a path"),
+            ("material p pbr 1 1 1 0", "pbr material expects 5 values"),
+        ] {
+            let error = SceneDescription::parse(&format!("{}{}\n", text, line)).unwrap_err();
+            assert!(error.to_string().contains(message), "{}: {}", line, error);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::sphere::spherical_uv;
+
+// Distance from the surface at which a ray counts as having reached it
+const HIT_DISTANCE: f32 = 1e-4;
+
+// Offset used to estimate normals from central differences
+const NORMAL_STEP: f32 = 1e-4;
+
+// Most steps a ray takes before giving up on finding the surface
+const MAX_STEPS: u32 = 512;
+
+// Furthest a ray is followed when the shape has no bounds to stop it
+const MAX_DISTANCE: f32 = 1e4;
+
+/// Tree of signed distance functions, negative inside the shape and positive outside.
+/// Leaves are centered on the origin and are moved and combined with the methods below.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere {radius: f32},
+    /// Box reaching `half_extents` out from the origin along each axis
+    Cuboid {half_extents: Vec3},
+    /// Ring lying in the xz plane around the y axis
+    Torus {major_radius: f32, minor_radius: f32},
+    Translate {offset: Vec3, sdf: Box<Sdf>},
+    /// Union which blends the shapes together where they come within `k` of each other
+    SmoothUnion {a: Box<Sdf>, b: Box<Sdf>, k: f32},
+    /// Copies of the shape every `spacing` along each axis, `limit` copies out either
+    /// side of the original. An infinite limit repeats forever and a spacing of zero
+    /// leaves that axis alone.
+    Repeat {spacing: Vec3, limit: Vec3, sdf: Box<Sdf>},
+    /// Shape turned around the y axis by `rate` radians per unit of height
+    Twist {rate: f32, sdf: Box<Sdf>},
+}
+
+impl Sdf {
+    pub fn sphere(radius: f32) -> Self {
+        Sdf::Sphere {radius}
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        Sdf::Cuboid {half_extents}
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Self {
+        Sdf::Torus {major_radius, minor_radius}
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        Sdf::Translate {offset, sdf: Box::new(self)}
+    }
+
+    /// A `k` of zero gives a plain union with a sharp crease
+    pub fn smooth_union(self, other: Sdf, k: f32) -> Self {
+        Sdf::SmoothUnion {a: Box::new(self), b: Box::new(other), k}
+    }
+
+    /// Each copy needs to fit inside its own cell of the repetition for the distances
+    /// to stay correct
+    pub fn repeat(self, spacing: Vec3, limit: Vec3) -> Self {
+        Sdf::Repeat {spacing, limit, sdf: Box::new(self)}
+    }
+
+    pub fn twist(self, rate: f32) -> Self {
+        Sdf::Twist {rate, sdf: Box::new(self)}
+    }
+
+    /// Signed distance from `p` to the surface. Twisting stretches space, so there it's
+    /// only an estimate and `lipschitz` says how far it may overshoot.
+    pub fn distance(&self, p: &Point3) -> f32 {
+        match self {
+            Sdf::Sphere {radius} => p.length() - radius,
+            Sdf::Cuboid {half_extents} => {
+                let q = Vec3::from(p.x().abs() - half_extents.x(),
+                                   p.y().abs() - half_extents.y(),
+                                   p.z().abs() - half_extents.z());
+                let outside = Vec3::from(q.x().max(0.), q.y().max(0.), q.z().max(0.));
+                outside.length() + q.x().max(q.y()).max(q.z()).min(0.)
+            },
+            Sdf::Torus {major_radius, minor_radius} => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major_radius;
+                (ring * ring + p.y() * p.y()).sqrt() - minor_radius
+            },
+            Sdf::Translate {offset, sdf} => sdf.distance(&(*p - *offset)),
+            Sdf::SmoothUnion {a, b, k} => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                if *k <= 0. {
+                    return a.min(b);
+                }
+                // Polynomial smooth minimum, which never dips more than k/4 below min
+                let h = (k - (a - b).abs()).max(0.) / k;
+                a.min(b) - h * h * k / 4.
+            },
+            Sdf::Repeat {spacing, limit, sdf} => {
+                let wrap = |p: f32, spacing: f32, limit: f32| {
+                    if spacing > 0. {
+                        p - spacing * (p / spacing).round().clamp(-limit, limit)
+                    } else {
+                        p
+                    }
+                };
+                sdf.distance(&Vec3::from(wrap(p.x(), spacing.x(), limit.x()),
+                                         wrap(p.y(), spacing.y(), limit.y()),
+                                         wrap(p.z(), spacing.z(), limit.z())))
+            },
+            Sdf::Twist {rate, sdf} => {
+                let (sin, cos) = (rate * p.y()).sin_cos();
+                sdf.distance(&Vec3::from(cos * p.x() + sin * p.z(),
+                                         p.y(),
+                                         cos * p.z() - sin * p.x()))
+            },
+        }
+    }
+
+    /// Most the distance can change per unit moved, which is one for exact distances.
+    /// Rays step by the distance divided by this so they never pass through the surface.
+    pub fn lipschitz(&self) -> f32 {
+        match self {
+            Sdf::Sphere {..} | Sdf::Cuboid {..} | Sdf::Torus {..} => 1.,
+            Sdf::Translate {sdf, ..} | Sdf::Repeat {sdf, ..} => sdf.lipschitz(),
+            Sdf::SmoothUnion {a, b, ..} => a.lipschitz().max(b.lipschitz()),
+            Sdf::Twist {rate, sdf} => {
+                // Points further from the axis are swept along faster
+                let reach = sdf.bounds().map_or(0., |aabb| axis_reach(&aabb));
+                sdf.lipschitz() * (1. + rate * rate * reach * reach).sqrt()
+            },
+        }
+    }
+
+    /// Box which is sure to contain the whole shape, or `None` if it goes on forever
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Sdf::Sphere {radius} => {
+                let extent = Vec3::from(*radius, *radius, *radius);
+                Some(Aabb::from(-extent, extent))
+            },
+            Sdf::Cuboid {half_extents} => Some(Aabb::from(-*half_extents, *half_extents)),
+            Sdf::Torus {major_radius, minor_radius} => {
+                let reach = major_radius + minor_radius;
+                let extent = Vec3::from(reach, *minor_radius, reach);
+                Some(Aabb::from(-extent, extent))
+            },
+            Sdf::Translate {offset, sdf} => {
+                let aabb = sdf.bounds()?;
+                Some(Aabb::from(aabb.min() + *offset, aabb.max() + *offset))
+            },
+            Sdf::SmoothUnion {a, b, k} => {
+                let aabb = a.bounds()?.merge(&b.bounds()?);
+                let grow = Vec3::from(1., 1., 1.) * (k.max(0.) / 4.);
+                Some(Aabb::from(aabb.min() - grow, aabb.max() + grow))
+            },
+            Sdf::Repeat {spacing, limit, sdf} => {
+                let aabb = sdf.bounds()?;
+                let reach = |spacing: f32, limit: f32| {
+                    if spacing > 0. {spacing * limit} else {0.}
+                };
+                let grow = Vec3::from(reach(spacing.x(), limit.x()),
+                                      reach(spacing.y(), limit.y()),
+                                      reach(spacing.z(), limit.z()));
+                if !grow.length_squared().is_finite() {
+                    return None;
+                }
+                Some(Aabb::from(aabb.min() - grow, aabb.max() + grow))
+            },
+            Sdf::Twist {sdf, ..} => {
+                // Any turn stays within the circle around the axis reaching the corners
+                let aabb = sdf.bounds()?;
+                let reach = axis_reach(&aabb);
+                Some(Aabb::from(Vec3::from(-reach, aabb.min().y(), -reach),
+                                Vec3::from(reach, aabb.max().y(), reach)))
+            },
+        }
+    }
+}
+
+// Furthest any point in the box can be from the y axis
+fn axis_reach(aabb: &Aabb) -> f32 {
+    let (min, max) = (aabb.min(), aabb.max());
+    let x = min.x().abs().max(max.x().abs());
+    let z = min.z().abs().max(max.z().abs());
+    (x * x + z * z).sqrt()
+}
+
+/// Shape given by a signed distance function placed at `center`, rendered by sphere
+/// tracing: rays step forward by the distance to the nearest surface until they reach
+/// it. Normals come from central differences of the distance, and UVs are a spherical
+/// projection around the center.
+pub struct SdfObject<T: Material> {
+    center: Point3,
+    sdf: Sdf,
+    lipschitz: f32,
+    bounds: Option<Aabb>,
+    material: Arc<T>,
+}
+
+impl<T: Material> SdfObject<T> {
+    pub fn from(center: Point3, sdf: Sdf, material: Arc<T>) -> Self {
+        let lipschitz = sdf.lipschitz().max(1.);
+        let bounds = sdf.bounds().map(|aabb| Aabb::from(aabb.min() + center,
+                                                         aabb.max() + center));
+        SdfObject {center, sdf, lipschitz, bounds, material}
+    }
+
+    fn distance(&self, p: &Point3) -> f32 {
+        self.sdf.distance(&(*p - self.center))
+    }
+
+    fn normal(&self, p: &Point3) -> Vec3 {
+        let difference = |axis: Vec3| {
+            let offset = NORMAL_STEP * axis;
+            self.distance(&(*p + offset)) - self.distance(&(*p - offset))
+        };
+        unit_vector(Vec3::from(difference(Vec3::from(1., 0., 0.)),
+                               difference(Vec3::from(0., 1., 0.)),
+                               difference(Vec3::from(0., 0., 1.))))
+    }
+
+    // Narrows down where the surface lies between two samples on either side of it
+    fn refine(&self, ray: &Ray, mut t0: f32, mut t1: f32, d0: f32) -> f32 {
+        for _ in 0..16 {
+            let mid = (t0 + t1) / 2.;
+            if self.distance(&ray.at(mid)).signum() == d0.signum() {
+                t0 = mid;
+            } else {
+                t1 = mid;
+            }
+        }
+        (t0 + t1) / 2.
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for SdfObject<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (start, end) = match &self.bounds {
+            Some(aabb) => aabb.clip(ray, t_min, t_max)?,
+            None => (t_min, t_max.min(MAX_DISTANCE)),
+        };
+
+        // Distances are in world units, so steps along the ray shrink with its length
+        let scale = 1. / (ray.direction().length() * self.lipschitz);
+        let mut t = start;
+        let mut distance = self.distance(&ray.at(t));
+        let mut found = None;
+        for _ in 0..MAX_STEPS {
+            let step = (distance.abs() * scale).max(HIT_DISTANCE * scale);
+            let next = t + step;
+            if next > end {
+                break;
+            }
+            let next_distance = self.distance(&ray.at(next));
+
+            // Crossing the surface between samples, which estimated distances can
+            // cause, or closing in on it. A ray leaving a surface it started on moves
+            // away from it, so isn't caught here.
+            if next_distance.signum() != distance.signum() {
+                found = Some(self.refine(ray, t, next, distance));
+                break;
+            }
+            if next_distance.abs() < HIT_DISTANCE && next_distance.abs() < distance.abs() {
+                found = Some(next);
+                break;
+            }
+            t = next;
+            distance = next_distance;
+        }
+
+        let t = found?;
+        let p = ray.at(t);
+        let uv = spherical_uv(&unit_vector(p - self.center));
+        Some(HitRecord::from(t, ray, self.normal(&p), uv, self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn object(center: Point3, sdf: Sdf) -> SdfObject<Lambertian> {
+        SdfObject::from(center, sdf, Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn distances_of_leaves() {
+        let p = Vec3::from(3., 0., 4.);
+        assert!((Sdf::sphere(1.).distance(&p) - 4.).abs() < 1e-6);
+        assert!((Sdf::cuboid(Vec3::from(1., 1., 1.)).distance(&Vec3::new()) + 1.).abs() < 1e-6);
+        assert!((Sdf::torus(2., 0.5).distance(&Vec3::from(2., 0., 0.)) + 0.5).abs() < 1e-6);
+        // Copies repeat every 4 units, out to one either side
+        let row = Sdf::sphere(1.).repeat(Vec3::from(4., 0., 0.), Vec3::from(1., 0., 0.));
+        assert!(row.distance(&Vec3::from(4., 0., 0.)) < 0.);
+        assert!((row.distance(&Vec3::from(9., 0., 0.)) - 4.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ray_hits_the_surface() {
+        let sphere = object(Vec3::from(0., 0., -5.), Sdf::sphere(1.));
+        let ray = Ray::from(Vec3::new(), Vec3::from(0., 0., -2.));
+        let hit = sphere.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        // The ray's direction is two units long, so it takes t = 2 to reach the surface
+        assert!((hit.t - 2.).abs() < 1e-3, "{}", hit.t);
+        assert!(hit.front_face);
+        assert!((hit.normal.z() - 1.).abs() < 1e-3);
+
+        // Spheres which only touch at the origin, blended so the seam between them fills
+        // in up to a height of 0.75, where both are k / 4 away
+        let pair = Sdf::sphere(1.).translate(Vec3::from(-1., 0., 0.))
+                      .smooth_union(Sdf::sphere(1.).translate(Vec3::from(1., 0., 0.)), 1.);
+        let blended = object(Vec3::new(), pair);
+        let ray = Ray::from(Vec3::from(0., 5., 0.), Vec3::from(0., -1., 0.));
+        let hit = blended.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.25).abs() < 1e-3, "{}", hit.t);
+    }
+
+    #[test]
+    fn ray_misses_the_surface() {
+        let sphere = object(Vec3::from(0., 0., -5.), Sdf::sphere(1.));
+        let ray = Ray::from(Vec3::from(1.01, 0., 0.), Vec3::from(0., 0., -1.));
+        assert!(sphere.hit(&ray, 0.001, f32::INFINITY).is_none());
+        // Nor is anything found before t_max or behind the ray
+        let ray = Ray::from(Vec3::new(), Vec3::from(0., 0., -1.));
+        assert!(sphere.hit(&ray, 0.001, 3.5).is_none());
+        assert!(sphere.hit(&Ray::from(Vec3::new(), Vec3::from(0., 0., 1.)), 0.001,
+                           f32::INFINITY).is_none());
+    }
+}
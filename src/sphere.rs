@@ -1,6 +1,12 @@
+use std::f32::consts::PI;
 use std::sync::Arc;
 
-use crate::{Point3, Ray, HitRecord, Hittable, dot, Material};
+use crate::vec3::{Point3, Vec3, dot};
+use crate::ray::Ray;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::csg::{Solid, Span};
 
 pub struct Sphere<T: Material> {
     center: Point3,
@@ -12,10 +18,9 @@ impl<T: Material> Sphere<T> {
     pub fn from(center: Point3, radius: f32, material: Arc<T>) -> Self {
         Sphere {center, radius, material}
     }
-}
 
-impl<T: Material + std::marker::Sync + std::marker::Send> Hittable for Sphere<T> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    // Both places the ray's line crosses the sphere, nearest first
+    fn roots(&self, ray: &Ray) -> Option<(f32, f32)> {
         let oc = *ray.origin() - self.center;
         let a = ray.direction().length_squared();
         let half_b = dot(&oc, ray.direction());
@@ -25,19 +30,48 @@ impl<T: Material + std::marker::Sync + std::marker::Send> Hittable for Sphere<T>
         if discriminant < 0. {
             return None;
         }
-        
-        let mut root = (-half_b - discriminant.sqrt()) / a;
-        if root < t_min || root > t_max {
-            root = (-half_b + discriminant.sqrt()) / a;
-            if root < t_min || root > t_max {
-                return None;
-            }
-        }
+        let sqrt_d = discriminant.sqrt();
+
+        Some(((-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a))
+    }
+
+    fn record(&self, ray: &Ray, t: f32) -> HitRecord<'_> {
+        let normal = (ray.at(t) - self.center) / self.radius;
+        let uv = spherical_uv(&((ray.at(t) - self.center) / self.radius.abs()));
+        HitRecord::from(t, ray, normal, uv, self.material.clone())
+    }
+}
+
+impl<T: Material + std::marker::Sync + std::marker::Send> Hittable for Sphere<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t0, t1) = self.roots(ray)?;
+        let root = [t0, t1].into_iter().find(|t| *t >= t_min && *t <= t_max)?;
+
+        Some(self.record(ray, root))
+    }
 
-        let normal = (ray.at(root) - self.center) / self.radius;
-        let record = HitRecord::from(root, ray, normal, self.material.clone());
-        
-        Some(record)
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::from(self.radius, self.radius, self.radius);
+        Some(Aabb::from(self.center - extent, self.center + extent))
     }
 }
 
+impl<T: Material + std::marker::Sync + std::marker::Send> Solid for Sphere<T> {
+    fn spans(&self, ray: &Ray) -> Vec<Span<'_>> {
+        match self.roots(ray) {
+            Some((t0, t1)) => {
+                vec![Span {enter: self.record(ray, t0), exit: self.record(ray, t1)}]
+            },
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Longitude around the y axis and latitude from the bottom pole, both from 0 to 1, of a
+/// point on the unit sphere
+pub(crate) fn spherical_uv(p: &Point3) -> (f32, f32) {
+    let theta = (-p.y()).clamp(-1., 1.).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+
+    (phi / (2. * PI), theta / PI)
+}
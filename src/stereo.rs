@@ -0,0 +1,166 @@
+use crate::vec3::*;
+use crate::ray::Ray;
+use crate::camera::{Camera, CameraModel};
+use crate::panorama::Equirectangular;
+use crate::film::Film;
+
+/// One side of a stereo pair
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Eye::Left => "left",
+            Eye::Right => "right",
+        }
+    }
+
+    // Which way along the camera's u axis this eye sits from the center of the rig
+    fn side(&self) -> f32 {
+        match self {
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        }
+    }
+}
+
+/// How the two eyes are made to agree on the convergence distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Convergence {
+    /// Both eyes rotate inward to look at the convergence point. Simple, but adds
+    /// vertical parallax toward the corners of the image.
+    ToeIn,
+    /// Both eyes keep looking straight ahead and shift their image windows so that they
+    /// line up at the convergence distance
+    OffAxis,
+}
+
+/// How a stereo pair is arranged in the output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    Separate,
+    SideBySide,
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// Combines the two eyes into a single film, or `None` if they should be kept apart.
+    /// Side by side puts the left eye on the left, top bottom puts it on top.
+    pub fn pack(&self, left: &Film, right: &Film) -> Option<Film> {
+        let (width, height) = (left.width(), left.height());
+        let (packed_width, packed_height, dx, dy) = match self {
+            StereoLayout::Separate => return None,
+            StereoLayout::SideBySide => (2 * width, height, width, 0),
+            StereoLayout::TopBottom => (width, 2 * height, 0, height),
+        };
+
+        let mut packed = Film::new(packed_width, packed_height);
+        for y in 0..height {
+            for x in 0..width {
+                packed.set(x, y, left.sum(x, y), left.weight(x, y), left.abs_weight(x, y));
+                packed.set(x + dx, y + dy, right.sum(x, y), right.weight(x, y),
+                           right.abs_weight(x, y));
+            }
+        }
+
+        Some(packed)
+    }
+}
+
+/// Pair of eyes `ipd` world units apart, converging at `distance` units in front of the
+/// rig. Zero parallax falls at the convergence distance, so objects closer than it
+/// appear in front of the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct StereoRig {
+    pub ipd: f32,
+    pub distance: f32,
+    pub convergence: Convergence,
+}
+
+impl StereoRig {
+    pub fn new(ipd: f32, distance: f32, convergence: Convergence) -> Self {
+        StereoRig {ipd, distance, convergence}
+    }
+
+    // Offset of an eye from the center of the rig along the camera's u axis
+    fn offset(&self, eye: Eye) -> f32 {
+        eye.side() * self.ipd / 2.
+    }
+
+    /// Perspective camera for one eye of a rig centered on `view_from`, taking the
+    /// same arguments as `Camera::new`
+    #[allow(clippy::too_many_arguments)]
+    pub fn perspective(&self, eye: Eye, view_from: Point3, view_at: Point3, view_up: Vec3,
+                       vfov: f32, aspect_ratio: f32, aperture: f32, focus_dist: f32) -> Camera {
+        let (u, _, w) = Camera::basis(view_from, view_at, view_up);
+        let offset = self.offset(eye);
+        let eye_from = view_from + offset * u;
+
+        match self.convergence {
+            Convergence::ToeIn => {
+                let target = view_from - self.distance * w;
+                Camera::new(eye_from, target, view_up, vfov, aspect_ratio, aperture, focus_dist)
+            },
+            Convergence::OffAxis => {
+                // Slide the window back toward the center line by the eye's offset as
+                // seen at the convergence distance
+                let view_width = 2. * (vfov.to_radians() / 2.).tan() * aspect_ratio;
+                Camera::new(eye_from, view_at + offset * u, view_up, vfov, aspect_ratio,
+                            aperture, focus_dist)
+                    .shift(-offset / (self.distance * view_width), 0.)
+            },
+        }
+    }
+
+    /// Omnidirectional stereo panorama for one eye of a rig centered on `view_from`
+    pub fn omnidirectional(&self, eye: Eye, view_from: Point3, view_at: Point3,
+                           view_up: Vec3) -> OmniStereo {
+        let (u, _, w) = Camera::basis(view_from, view_at, view_up);
+        OmniStereo {
+            panorama: Equirectangular::new(view_from, view_at, view_up),
+            center: view_from,
+            u,
+            w,
+            offset: self.offset(eye),
+            distance: self.distance,
+            convergence: self.convergence,
+        }
+    }
+}
+
+/// Equirectangular panorama for one eye of an omnidirectional stereo (ODS) rig. Every
+/// ray starts on a circle of radius ipd / 2 around the rig, tangent to the direction it
+/// travels in, so each column of the image sees the scene with the right parallax for
+/// a viewer turned to face it.
+pub struct OmniStereo {
+    panorama: Equirectangular,
+    center: Point3,
+    u: Vec3,
+    w: Vec3,
+    offset: f32,
+    distance: f32,
+    convergence: Convergence,
+}
+
+impl CameraModel for OmniStereo {
+    fn get_ray(&self, u: f32, v: f32) -> Option<Ray> {
+        let (longitude, latitude) = Equirectangular::angles(u, v);
+        let direction = self.panorama.direction(longitude, latitude);
+
+        // Sideways direction for a viewer facing this longitude
+        let side = longitude.cos() * self.u + longitude.sin() * self.w;
+        let origin = self.center + self.offset * side;
+
+        match self.convergence {
+            Convergence::ToeIn => {
+                let target = self.center + self.distance * direction;
+                Some(Ray::from(origin, target - origin))
+            },
+            Convergence::OffAxis => Some(Ray::from(origin, direction)),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::vec3::{Point3, Vec3};
+use crate::mesh::PolygonMesh;
+
+/// Reads an STL file, either binary or ASCII. STL keeps every triangle separate, without
+/// shared vertices, so the mesh comes out with flat shading and its facets kept sharp,
+/// as suits models from CAD. The normals stored in the file are ignored in favour of the
+/// winding of each triangle.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<PolygonMesh, Error> {
+    parse(&fs::read(path)?)
+}
+
+pub fn parse(data: &[u8]) -> Result<PolygonMesh, Error> {
+    // Binary files may also begin with "solid", so they're told apart by whether their
+    // triangle count matches their length
+    let binary = data.len() >= 84 && {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as u64;
+        data.len() as u64 == 84 + 50 * count
+    };
+    let triangles = if binary {
+        parse_binary(data)
+    } else if data.trim_ascii_start().starts_with(b"solid") {
+        parse_ascii(data)?
+    } else {
+        return Err(invalid("neither binary nor ASCII STL"));
+    };
+
+    if triangles.is_empty() {
+        return Err(invalid("no triangles"));
+    }
+    for (i, triangle) in triangles.iter().enumerate() {
+        if triangle.iter().any(|p| !(p.x().is_finite() && p.y().is_finite()
+                                     && p.z().is_finite())) {
+            return Err(invalid(&format!("triangle {} is not finite", i)));
+        }
+    }
+
+    Ok(PolygonMesh {
+        positions: triangles.iter().flatten().copied().collect(),
+        faces: (0..triangles.len() as u32).map(|i| vec![3 * i, 3 * i + 1, 3 * i + 2])
+                                          .collect(),
+        ..Default::default()
+    })
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid STL file: {}", msg))
+}
+
+// Each triangle is a normal and three vertices as little endian floats, followed by two
+// bytes of attributes
+fn parse_binary(data: &[u8]) -> Vec<[Point3; 3]> {
+    data[84..].chunks_exact(50).map(|record| {
+        let float = |i: usize| {
+            let offset = 12 + 4 * i;
+            f32::from_le_bytes([record[offset], record[offset + 1],
+                                record[offset + 2], record[offset + 3]])
+        };
+        [0, 1, 2].map(|v| Vec3::from(float(3 * v), float(3 * v + 1), float(3 * v + 2)))
+    }).collect()
+}
+
+fn parse_ascii(data: &[u8]) -> Result<Vec<[Point3; 3]>, Error> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid("ASCII file is not text"))?;
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+
+    for (n, line) in text.lines().enumerate() {
+        let line_error = |msg: &str| invalid(&format!("line {}: {}", n + 1, msg));
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["vertex", coords @ ..] => {
+                let coords = coords.iter().map(|value| {
+                    value.parse::<f32>().map_err(|_| {
+                        line_error(&format!("bad value \"{}\"", value))
+                    })
+                }).collect::<Result<Vec<f32>, Error>>()?;
+                if coords.len() != 3 {
+                    return Err(line_error("vertex needs x, y and z"));
+                }
+                if corners.len() == 3 {
+                    return Err(line_error("facet has more than 3 vertices"));
+                }
+                corners.push(Vec3::from(coords[0], coords[1], coords[2]));
+            },
+            ["endfacet", ..] => {
+                if corners.len() != 3 {
+                    return Err(line_error("facet needs 3 vertices"));
+                }
+                triangles.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+            },
+            _ => (),
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two triangles, with a header starting "solid" as many exporters write
+    fn binary() -> Vec<u8> {
+        let mut data = b"solid exported".to_vec();
+        data.resize(80, b' ');
+        data.extend_from_slice(&2u32.to_le_bytes());
+        for t in 0..2 {
+            let mut record = vec![0; 12]; // Normal, which is ignored
+            for v in [t as f32, 0., 0., 1., 0., 0., 0., 1., 0.] {
+                record.extend_from_slice(&v.to_le_bytes());
+            }
+            record.extend_from_slice(&[0, 0]);
+            data.extend(record);
+        }
+        data
+    }
+
+    #[test]
+    fn binary_triangles_keep_their_own_vertices() {
+        let mesh = parse(&binary()).unwrap();
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2], vec![3, 4, 5]]);
+        assert_eq!(mesh.positions.len(), 6);
+        let p = mesh.positions[3];
+        assert_eq!((p.x(), p.y(), p.z()), (1., 0., 0.));
+    }
+
+    #[test]
+    fn truncated_binary_is_rejected() {
+        let data = binary();
+        for len in 0..data.len() {
+            let error = parse(&data[..len]).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", len);
+        }
+        // Without the "solid" prefix the file can't be taken for ASCII either
+        let mut data = data;
+        data[..5].copy_from_slice(b"model");
+        let error = parse(&data[..data.len() - 1]).err().unwrap();
+        assert!(error.to_string().contains("neither binary nor ASCII"), "{}", error);
+    }
+
+    #[test]
+    fn ascii_facets_need_three_vertices() {
+        let facet = "facet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                     vertex 0 1 0\nendloop\nendfacet\n";
+        let mesh = parse(format!("solid a\n{}{}endsolid a\n", facet, facet).as_bytes())
+            .unwrap();
+        assert_eq!(mesh.faces.len(), 2);
+
+        let short = facet.replace("vertex 0 1 0\n", "");
+        let error = parse(format!("solid a\n{}endsolid a\n", short).as_bytes()).err().unwrap();
+        assert!(error.to_string().contains("line 7: facet needs 3 vertices"), "{}", error);
+        let error = parse(b"solid a\nendsolid a\n").err().unwrap();
+        assert!(error.to_string().contains("no triangles"), "{}", error);
+    }
+}
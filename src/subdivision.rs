@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+
+use crate::vec3::Vec3;
+use crate::mesh::PolygonMesh;
+use crate::texture::Texture;
+
+/// Scheme used to refine a mesh. Both smooth the surface towards a limit which stays
+/// close to the original mesh, and both keep open edges as smooth curves through their
+/// own vertices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subdivision {
+    /// Loop subdivision, which splits each triangle into four. Other polygons are split
+    /// into triangles first.
+    Loop,
+    /// Catmull–Clark subdivision, which splits each polygon into quads, one per corner
+    CatmullClark,
+}
+
+impl PolygonMesh {
+    /// Refines the mesh `levels` times, each level multiplying the number of faces by
+    /// about four. UVs are carried along linearly within the original faces, while vertex
+    /// colors are smoothed just like positions. Every face has to pass `validate`, as
+    /// loaded meshes do.
+    pub fn subdivide(&self, scheme: Subdivision, levels: u32) -> PolygonMesh {
+        let mut mesh = self.clone();
+        if scheme == Subdivision::Loop && levels > 0 {
+            mesh = mesh.triangulated();
+        }
+        for _ in 0..levels {
+            mesh = match scheme {
+                Subdivision::Loop => mesh.loop_step(),
+                Subdivision::CatmullClark => mesh.catmull_clark_step(),
+            };
+        }
+        mesh
+    }
+
+    /// Moves each vertex along its normal by `scale` times the brightness of `texture`
+    /// there, so black stays put and white moves the full distance. Vertices on a UV
+    /// seam look the texture up with the UVs of the first face they belong to.
+    pub fn displace(&mut self, texture: &dyn Texture, scale: f32) {
+        let mut vertex_uvs = vec![None; self.positions.len()];
+        if let Some(uvs) = &self.uvs {
+            for (face, corners) in self.faces.iter().zip(uvs) {
+                for (&i, &uv) in face.iter().zip(corners) {
+                    vertex_uvs[i as usize].get_or_insert(uv);
+                }
+            }
+        }
+
+        let normals = self.vertex_normals();
+        for (i, p) in self.positions.iter_mut().enumerate() {
+            let (u, v) = vertex_uvs[i].unwrap_or((0., 0.));
+            let value = texture.value(u, v, p);
+            let height = (value.x() + value.y() + value.z()) / 3.;
+            *p += scale * height * normals[i];
+        }
+        self.normals = None;
+    }
+
+    // Same mesh with every polygon split into a fan of triangles
+    fn triangulated(&self) -> PolygonMesh {
+        let mut mesh = PolygonMesh {
+            positions: self.positions.clone(),
+            normals: self.normals.clone(),
+            colors: self.colors.clone(),
+            ..Default::default()
+        };
+        let mut uvs = self.uvs.as_ref().map(|_| Vec::new());
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 1..face.len().saturating_sub(1) {
+                mesh.faces.push(vec![face[0], face[k], face[k + 1]]);
+                if let (Some(uvs), Some(corners)) = (uvs.as_mut(), self.uvs.as_ref()) {
+                    let corners = &corners[f];
+                    uvs.push(vec![corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+        }
+        mesh.uvs = uvs;
+        mesh
+    }
+
+    fn loop_step(&self) -> PolygonMesh {
+        let topology = Topology::from(self);
+        let vertex_count = self.positions.len();
+
+        // Vertex colors are refined with the same weights as positions
+        let refine = |values: &[Vec3]| -> Vec<Vec3> {
+            let vertex_points = (0..vertex_count).map(|v| {
+                let p = values[v];
+                if let Some(boundary) = topology.boundary_point(values, v) {
+                    return boundary;
+                }
+                let neighbours = &topology.neighbours[v];
+                let n = neighbours.len() as f32;
+                if neighbours.is_empty() {
+                    return p;
+                }
+                let beta = if neighbours.len() == 3 {3. / 16.} else {3. / (8. * n)};
+                let sum = neighbours.iter().fold(Vec3::new(), |s, &i| s + values[i as usize]);
+                (1. - n * beta) * p + beta * sum
+            });
+
+            // Edge points, weighted towards the edge and away from the opposite corners
+            let edge_points = topology.ends.iter().enumerate().map(|(e, &(a, b))| {
+                let (pa, pb) = (values[a as usize], values[b as usize]);
+                let faces = &topology.edge_faces[e];
+                if faces.len() != 2 {
+                    return (pa + pb) / 2.;
+                }
+                let opposite = |f: u32| {
+                    let face = &self.faces[f as usize];
+                    let v = face.iter().find(|&&v| v != a && v != b).copied().unwrap_or(a);
+                    values[v as usize]
+                };
+                3. / 8. * (pa + pb) + 1. / 8. * (opposite(faces[0]) + opposite(faces[1]))
+            });
+
+            vertex_points.chain(edge_points).collect()
+        };
+
+        let mut mesh = PolygonMesh {
+            positions: refine(&self.positions),
+            faces: Vec::with_capacity(4 * self.faces.len()),
+            uvs: self.uvs.as_ref().map(|_| Vec::with_capacity(4 * self.faces.len())),
+            normals: None,
+            colors: self.colors.as_deref().map(refine),
+        };
+
+        for (f, face) in self.faces.iter().enumerate() {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            let edge = |x: u32, y: u32| vertex_count as u32 + topology.edge(x, y);
+            let (ab, bc, ca) = (edge(a, b), edge(b, c), edge(c, a));
+            mesh.faces.extend([vec![a, ab, ca], vec![b, bc, ab], vec![c, ca, bc],
+                               vec![ab, bc, ca]]);
+
+            if let (Some(uvs), Some(corners)) = (mesh.uvs.as_mut(), self.uvs.as_ref()) {
+                let [ua, ub, uc] = [corners[f][0], corners[f][1], corners[f][2]];
+                let (uab, ubc, uca) = (mid(ua, ub), mid(ub, uc), mid(uc, ua));
+                uvs.extend([vec![ua, uab, uca], vec![ub, ubc, uab], vec![uc, uca, ubc],
+                            vec![uab, ubc, uca]]);
+            }
+        }
+
+        mesh
+    }
+
+    fn catmull_clark_step(&self) -> PolygonMesh {
+        let topology = Topology::from(self);
+        let (vertex_count, face_count) = (self.positions.len(), self.faces.len());
+
+        // Vertex colors are refined with the same weights as positions
+        let refine = |values: &[Vec3]| -> Vec<Vec3> {
+            let face_points: Vec<Vec3> = self.faces.iter().map(|face| {
+                face.iter().fold(Vec3::new(), |s, &i| s + values[i as usize]) / face.len() as f32
+            }).collect();
+
+            // Each vertex moves towards the average of the faces and edge midpoints around it
+            let vertex_points = (0..vertex_count).map(|v| {
+                let p = values[v];
+                if let Some(boundary) = topology.boundary_point(values, v) {
+                    return boundary;
+                }
+                let (faces, neighbours) = (&topology.vertex_faces[v], &topology.neighbours[v]);
+                if faces.is_empty() || neighbours.is_empty() {
+                    return p;
+                }
+                let n = neighbours.len() as f32;
+                let q = faces.iter().fold(Vec3::new(), |s, &f| s + face_points[f as usize])
+                    / faces.len() as f32;
+                let r = neighbours.iter().fold(Vec3::new(), |s, &i| {
+                    s + (p + values[i as usize]) / 2.
+                }) / n;
+                (q + 2. * r + (n - 3.) * p) / n
+            });
+
+            let edge_points = topology.ends.iter().enumerate().map(|(e, &(a, b))| {
+                let (pa, pb) = (values[a as usize], values[b as usize]);
+                let faces = &topology.edge_faces[e];
+                if faces.len() != 2 {
+                    return (pa + pb) / 2.;
+                }
+                (pa + pb + face_points[faces[0] as usize] + face_points[faces[1] as usize]) / 4.
+            });
+
+            let mut points: Vec<Vec3> = vertex_points.collect();
+            points.extend(face_points.iter().copied());
+            points.extend(edge_points);
+            points
+        };
+
+        let mut mesh = PolygonMesh {
+            positions: refine(&self.positions),
+            faces: Vec::with_capacity(4 * face_count),
+            uvs: self.uvs.as_ref().map(|_| Vec::with_capacity(4 * face_count)),
+            normals: None,
+            colors: self.colors.as_deref().map(refine),
+        };
+
+        let edge_base = (vertex_count + face_count) as u32;
+        for (f, face) in self.faces.iter().enumerate() {
+            let n = face.len();
+            let center = (vertex_count + f) as u32;
+            let center_uv = self.uvs.as_ref().map(|uvs| {
+                let sum = uvs[f].iter().fold((0., 0.), |s, uv| (s.0 + uv.0, s.1 + uv.1));
+                (sum.0 / n as f32, sum.1 / n as f32)
+            });
+
+            for k in 0..n {
+                let (prev, this, next) = (face[(k + n - 1) % n], face[k], face[(k + 1) % n]);
+                mesh.faces.push(vec![this,
+                                     edge_base + topology.edge(this, next),
+                                     center,
+                                     edge_base + topology.edge(prev, this)]);
+
+                if let (Some(uvs), Some(corners), Some(center_uv))
+                        = (mesh.uvs.as_mut(), self.uvs.as_ref(), center_uv) {
+                    let corners = &corners[f];
+                    let uv = corners[k];
+                    uvs.push(vec![uv,
+                                  mid(uv, corners[(k + 1) % n]),
+                                  center_uv,
+                                  mid(corners[(k + n - 1) % n], uv)]);
+                }
+            }
+        }
+
+        mesh
+    }
+}
+
+fn mid(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    ((a.0 + b.0) / 2., (a.1 + b.1) / 2.)
+}
+
+// How the faces of a mesh connect through their edges and vertices
+struct Topology {
+    // Index of each edge, by its vertices in ascending order
+    index: HashMap<(u32, u32), u32>,
+    ends: Vec<(u32, u32)>,
+    edge_faces: Vec<Vec<u32>>,
+    // Vertices joined to each vertex by an edge, and the faces around it
+    neighbours: Vec<Vec<u32>>,
+    vertex_faces: Vec<Vec<u32>>,
+}
+
+impl Topology {
+    fn from(mesh: &PolygonMesh) -> Self {
+        let mut topology = Topology {
+            index: HashMap::new(),
+            ends: Vec::new(),
+            edge_faces: Vec::new(),
+            neighbours: vec![Vec::new(); mesh.positions.len()],
+            vertex_faces: vec![Vec::new(); mesh.positions.len()],
+        };
+
+        for (f, face) in mesh.faces.iter().enumerate() {
+            for k in 0..face.len() {
+                let (a, b) = (face[k], face[(k + 1) % face.len()]);
+                let key = (a.min(b), a.max(b));
+                let e = match topology.index.get(&key) {
+                    Some(&e) => e,
+                    None => {
+                        let e = topology.ends.len() as u32;
+                        topology.index.insert(key, e);
+                        topology.ends.push(key);
+                        topology.edge_faces.push(Vec::new());
+                        topology.neighbours[a as usize].push(b);
+                        topology.neighbours[b as usize].push(a);
+                        e
+                    },
+                };
+                topology.edge_faces[e as usize].push(f as u32);
+                topology.vertex_faces[a as usize].push(f as u32);
+            }
+        }
+
+        topology
+    }
+
+    fn edge(&self, a: u32, b: u32) -> u32 {
+        self.index[&(a.min(b), a.max(b))]
+    }
+
+    // New position of a vertex on an open edge, which follows a curve through the edge's
+    // vertices alone, or None for vertices inside the surface. Corners where more than
+    // two open edges meet stay where they are.
+    fn boundary_point(&self, values: &[Vec3], v: usize) -> Option<Vec3> {
+        let rim: Vec<u32> = self.neighbours[v].iter().copied().filter(|&n| {
+            self.edge_faces[self.edge(v as u32, n) as usize].len() != 2
+        }).collect();
+
+        let p = values[v];
+        match rim.len() {
+            0 => None,
+            2 => Some(3. / 4. * p + 1. / 8. * (values[rim[0] as usize] + values[rim[1] as usize])),
+            _ => Some(p),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Point3;
+
+    fn cube() -> PolygonMesh {
+        PolygonMesh {
+            positions: (0..8).map(|i| {
+                Point3::from((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32)
+            }).collect(),
+            faces: vec![vec![0, 2, 3, 1], vec![4, 5, 7, 6], vec![0, 1, 5, 4],
+                        vec![2, 6, 7, 3], vec![0, 4, 6, 2], vec![1, 3, 7, 5]],
+            ..Default::default()
+        }
+    }
+
+    fn tetrahedron() -> PolygonMesh {
+        PolygonMesh {
+            positions: vec![Point3::from(1., 1., 1.), Point3::from(1., -1., -1.),
+                            Point3::from(-1., 1., -1.), Point3::from(-1., -1., 1.)],
+            faces: vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]],
+            ..Default::default()
+        }
+    }
+
+    // Square two units across in the plane z = 0, made of four quads
+    fn grid() -> PolygonMesh {
+        PolygonMesh {
+            positions: (0..9).map(|i| Point3::from((i % 3) as f32, (i / 3) as f32, 0.))
+                             .collect(),
+            faces: vec![vec![0, 1, 4, 3], vec![1, 2, 5, 4], vec![3, 4, 7, 6],
+                        vec![4, 5, 8, 7]],
+            ..Default::default()
+        }
+    }
+
+    // Number of vertices, edges and faces, and how many edges have only one face
+    fn counts(mesh: &PolygonMesh) -> (usize, usize, usize, usize) {
+        let topology = Topology::from(mesh);
+        let open = topology.edge_faces.iter().filter(|faces| faces.len() == 1).count();
+        (mesh.positions.len(), topology.ends.len(), mesh.faces.len(), open)
+    }
+
+    #[test]
+    fn catmull_clark_counts() {
+        let once = cube().subdivide(Subdivision::CatmullClark, 1);
+        assert_eq!(counts(&once), (8 + 6 + 12, 48, 24, 0));
+        assert!(once.faces.iter().all(|face| face.len() == 4));
+        assert_eq!(counts(&cube().subdivide(Subdivision::CatmullClark, 2)),
+                   (26 + 24 + 48, 192, 96, 0));
+        // Triangles are split into three quads
+        assert_eq!(counts(&tetrahedron().subdivide(Subdivision::CatmullClark, 1)),
+                   (4 + 4 + 6, 24, 12, 0));
+    }
+
+    #[test]
+    fn loop_counts() {
+        let once = tetrahedron().subdivide(Subdivision::Loop, 1);
+        assert_eq!(counts(&once), (4 + 6, 24, 16, 0));
+        assert!(once.faces.iter().all(|face| face.len() == 3));
+        assert_eq!(counts(&tetrahedron().subdivide(Subdivision::Loop, 2)),
+                   (10 + 24, 96, 64, 0));
+        // Quads are split into two triangles first
+        assert_eq!(counts(&cube().subdivide(Subdivision::Loop, 1)), (8 + 18, 72, 48, 0));
+        assert_eq!(tetrahedron().subdivide(Subdivision::Loop, 0).faces.len(), 4);
+    }
+
+    #[test]
+    fn closed_surfaces_shrink_inside_their_hull() {
+        for scheme in [Subdivision::Loop, Subdivision::CatmullClark] {
+            let mesh = cube().subdivide(scheme, 2);
+            for p in &mesh.positions {
+                for v in [p.x(), p.y(), p.z()] {
+                    assert!(v > 0. && v < 1., "{:?} {:?}", scheme, p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn boundary_stays_on_the_boundary() {
+        for scheme in [Subdivision::Loop, Subdivision::CatmullClark] {
+            let mesh = grid().subdivide(scheme, 1);
+            // Every open edge is split in two, and the surface stays flat
+            assert_eq!(counts(&mesh).3, 16, "{:?}", scheme);
+            assert!(mesh.positions.iter().all(|p| p.z() == 0.));
+
+            // Open edges only join points from the rim of the grid, where vertices along
+            // a straight side stay on it and corners are pulled in towards their sides
+            let topology = Topology::from(&mesh);
+            let on_rim = |i: u32| {
+                let (x, y) = (mesh.positions[i as usize].x(), mesh.positions[i as usize].y());
+                x == 0. || x == 2. || y == 0. || y == 2.
+                    || (x - 1.).abs() == 0.875 && (y - 1.).abs() == 0.875
+            };
+            for (e, &(a, b)) in topology.ends.iter().enumerate() {
+                if topology.edge_faces[e].len() == 1 {
+                    assert!(on_rim(a) && on_rim(b), "{:?} {} {}", scheme, a, b);
+                }
+            }
+            // Midpoint of a side, and midpoint of the edge from it to the corner
+            let found = |x: f32, y: f32| mesh.positions.iter().any(|p| p.x() == x && p.y() == y);
+            assert!(found(1., 0.) && found(0.5, 0.), "{:?}", scheme);
+            let corner = mesh.positions[0];
+            assert_eq!((corner.x(), corner.y()), (0.125, 0.125), "{:?}", scheme);
+        }
+    }
+
+    #[test]
+    fn faces_repeating_a_vertex_are_rejected() {
+        let mut mesh = tetrahedron();
+        assert_eq!(mesh.validate(), Ok(()));
+        mesh.faces[2] = vec![0, 2, 0];
+        assert!(mesh.validate().unwrap_err().contains("repeats"));
+        mesh.faces[2] = vec![0, 2, 4];
+        assert!(mesh.validate().unwrap_err().contains("out of range"));
+        mesh.faces[2] = vec![0, 2];
+        assert!(mesh.validate().is_err());
+
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nf 1 2 1\n";
+        let error = crate::obj::parse(text).err().unwrap().to_string();
+        assert!(error.contains("line 5") && error.contains("repeats"), "{}", error);
+        let text = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                    property float y\nproperty float z\nelement face 1\n\
+                    property list uchar int vertex_indices\nend_header\n\
+                    0 0 0\n1 0 0\n0 1 0\n3 0 1 1\n";
+        let error = crate::ply::parse(text.as_bytes()).err().unwrap().to_string();
+        assert!(error.contains("repeats"), "{}", error);
+    }
+}
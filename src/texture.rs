@@ -0,0 +1,101 @@
+use std::io::Error;
+use std::path::Path;
+
+use crate::vec3::{Color, Point3};
+use crate::color::srgb_to_linear;
+use crate::image::Image;
+use crate::hittable::HitRecord;
+
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+
+    // Value at a hit, which is where materials look textures up. Most textures only
+    // need the UVs and position, but some read more of the record.
+    fn value_at(&self, record: &HitRecord) -> Color {
+        self.value(record.u, record.v, &record.p)
+    }
+}
+
+/// Texture with a single constant color
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn from(color: Color) -> Self {
+        SolidColor {color}
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// Texture taking its color from the vertices of meshes loaded with per-vertex colors,
+/// such as scanned PLY files. Surfaces without vertex colors get the fallback color.
+pub struct VertexColor {
+    fallback: Color,
+}
+
+impl VertexColor {
+    pub fn from(fallback: Color) -> Self {
+        VertexColor {fallback}
+    }
+}
+
+impl Texture for VertexColor {
+    fn value(&self, _u: f32, _v: f32, _p: &Point3) -> Color {
+        self.fallback
+    }
+
+    fn value_at(&self, record: &HitRecord) -> Color {
+        record.color.unwrap_or(self.fallback)
+    }
+}
+
+/// Texture looked up from an image using surface UV coordinates, repeating outside
+/// the range 0 to 1
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    /// Loads a color texture. 8-bit images are assumed to be sRGB encoded and are
+    /// decoded to linear values here so the renderer only ever sees linear color.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(ImageTexture::from(Image::load(path)?))
+    }
+
+    /// Color texture from an image already loaded, decoded from sRGB just like `load`
+    pub fn from(mut image: Image) -> Self {
+        if image.bit_depth() == 8 {
+            for pixel in image.pixels_mut() {
+                *pixel = Color::from(srgb_to_linear(pixel.x()),
+                                     srgb_to_linear(pixel.y()),
+                                     srgb_to_linear(pixel.z()));
+            }
+        }
+
+        ImageTexture {image}
+    }
+
+    /// Texture holding data other than color, such as roughness, whose values are used
+    /// exactly as stored
+    pub fn data(image: Image) -> Self {
+        ImageTexture {image}
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Point3) -> Color {
+        let u = u.rem_euclid(1.);
+        let v = 1. - v.rem_euclid(1.); // Image rows start from the top
+
+        let x = ((u * self.image.width() as f32) as u32).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f32) as u32).min(self.image.height() - 1);
+
+        self.image.pixel(x, y)
+    }
+}
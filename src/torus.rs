@@ -0,0 +1,172 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3, dot, tangents, unit_vector};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::polynomial::solve_quartic;
+
+/// Ring around `center` lying in the plane perpendicular to `axis`. The tube of radius
+/// `minor_radius` follows a circle of radius `major_radius`.
+///
+/// U runs around the axis and v around the tube, starting from its outer edge.
+pub struct Torus<T: Material> {
+    center: Point3,
+    // Local frame, with z along the axis
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Arc<T>,
+}
+
+impl<T: Material> Torus<T> {
+    pub fn from(center: Point3, axis: Vec3, major_radius: f32, minor_radius: f32,
+                material: Arc<T>) -> Self {
+        let z = unit_vector(axis);
+        let (x, y) = tangents(&z);
+        Torus {center, x, y, z, major_radius, minor_radius, material}
+    }
+
+    fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::from(dot(v, &self.x), dot(v, &self.y), dot(v, &self.z))
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.x + v.y() * self.y + v.z() * self.z
+    }
+}
+
+impl<T: Material + Sync + Send> Hittable for Torus<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (big_r, small_r) = (self.major_radius as f64, self.minor_radius as f64);
+        let o = self.to_local(&(*ray.origin() - self.center));
+        let d = self.to_local(ray.direction());
+        let (mut o, d) = ([o.x() as f64, o.y() as f64, o.z() as f64],
+                          [d.x() as f64, d.y() as f64, d.z() as f64]);
+        let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        let d = [d[0] / len, d[1] / len, d[2] / len];
+        let dot3 = |a: &[f64; 3], b: &[f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+        // Bring the origin up to the bounding sphere first, since the quartic's
+        // coefficients grow quickly with distance and swamp the roots
+        let bound = big_r + small_r;
+        let half_b = dot3(&o, &d);
+        let discriminant = half_b * half_b - (dot3(&o, &o) - bound * bound);
+        if discriminant < 0. {
+            return None;
+        }
+        let shift = (-half_b - discriminant.sqrt()).max(0.);
+        for i in 0..3 {
+            o[i] += shift * d[i];
+        }
+
+        // (|p|² + R² - r²)² = 4R² (x² + y²) along the ray, with a unit direction
+        let f = dot3(&o, &d);
+        let e = dot3(&o, &o) + big_r * big_r - small_r * small_r;
+        let four_r2 = 4. * big_r * big_r;
+        let roots = solve_quartic(
+            1.,
+            4. * f,
+            2. * e + 4. * f * f - four_r2 * (d[0] * d[0] + d[1] * d[1]),
+            4. * f * e - 2. * four_r2 * (o[0] * d[0] + o[1] * d[1]),
+            e * e - four_r2 * (o[0] * o[0] + o[1] * o[1]),
+        );
+
+        // Convert back to distances along the original, unnormalized ray
+        let t = roots.into_iter()
+                     .map(|root| ((root + shift) / len) as f32)
+                     .find(|t| *t >= t_min && *t <= t_max)?;
+
+        // Normal points away from the nearest point on the circle the tube follows
+        let p = self.to_local(&(ray.at(t) - self.center));
+        let ring = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let core = if ring > 0. {
+            self.major_radius / ring * Vec3::from(p.x(), p.y(), 0.)
+        } else {
+            Vec3::from(self.major_radius, 0., 0.)
+        };
+        let normal = unit_vector(self.to_world(&(p - core)));
+
+        let u = p.y().atan2(p.x()).rem_euclid(2. * PI) / (2. * PI);
+        let v = p.z().atan2(ring - self.major_radius).rem_euclid(2. * PI) / (2. * PI);
+
+        Some(HitRecord::from(t, ray, normal, (u, v), self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (big_r, small_r) = (self.major_radius + self.minor_radius, self.minor_radius);
+        let corners: Vec<Point3> = [(-big_r, -big_r), (-big_r, big_r), (big_r, -big_r),
+                                    (big_r, big_r)].iter()
+            .flat_map(|&(x, y)| [-small_r, small_r].map(|z| Vec3::from(x, y, z)))
+            .map(|p| self.center + self.to_world(&p))
+            .collect();
+        Some(Aabb::around(&corners))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    // Ring of radius 2 around the z axis with a tube of radius 0.5
+    fn ring() -> Torus<Lambertian> {
+        Torus::from(Vec3::new(), Vec3::from(0., 0., 1.), 2., 0.5,
+                    Arc::new(Lambertian::from(Color::from(0.5, 0.5, 0.5))))
+    }
+
+    #[test]
+    fn ray_through_the_hole_misses() {
+        let torus = ring();
+        let ray = Ray::from(Vec3::from(0., 0., 5.), Vec3::from(0., 0., -1.));
+        assert!(torus.hit(&ray, 0.001, f32::INFINITY).is_none());
+        let ray = Ray::from(Vec3::from(1.4, 0., 5.), Vec3::from(0., 0., -1.));
+        assert!(torus.hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn ray_across_the_ring_crosses_the_tube_four_times() {
+        let torus = ring();
+        let ray = Ray::from(Vec3::from(-5., 0., 0.), Vec3::from(1., 0., 0.));
+        let mut t_min = 0.001;
+        for (t, normal) in [(2.5, -1.), (3.5, 1.), (6.5, -1.), (7.5, 1.)] {
+            let hit = torus.hit(&ray, t_min, f32::INFINITY).unwrap();
+            assert!((hit.t - t).abs() < 1e-4, "{} instead of {}", hit.t, t);
+            // Outward normals, before facing the ray, point away from the tube's core
+            let outward = if hit.front_face {hit.normal} else {-hit.normal};
+            assert!((outward.x() - normal).abs() < 1e-4);
+            t_min = hit.t + 0.01;
+        }
+        assert!(torus.hit(&ray, t_min, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn grazing_ray_touches_the_top() {
+        let torus = ring();
+        let ray = Ray::from(Vec3::from(-5., 0., 0.5), Vec3::from(1., 0., 0.));
+        let hit = torus.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((hit.t - 3.).abs() < 0.05, "{}", hit.t);
+        // The normal is square to the ray, so which way it's turned to face it is arbitrary
+        assert!((hit.normal.z().abs() - 1.).abs() < 1e-3);
+        let ray = Ray::from(Vec3::from(-5., 0., 0.501), Vec3::from(1., 0., 0.));
+        assert!(torus.hit(&ray, 0.001, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn distant_ray_hits_precisely() {
+        let torus = ring();
+        for distance in [1e3, 1e4, 1e5] {
+            let ray = Ray::from(Vec3::from(0., -distance, 0.2), Vec3::from(0., 1., 0.));
+            let hit = torus.hit(&ray, 0.001, f32::INFINITY).unwrap();
+            // The tube's outside at height 0.2 is sqrt(0.25 - 0.04) beyond the core circle
+            let expected = distance - 2. - 0.21f32.sqrt();
+            assert!((hit.t - expected).abs() < 1e-6 * distance, "{} at {}", hit.t, distance);
+            assert!(hit.normal.y() < -0.9);
+        }
+    }
+}
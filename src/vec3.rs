@@ -194,3 +194,10 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
     let len = v.length();
     v / len
 }
+
+/// Two unit vectors which together with the unit vector `n` form an orthonormal basis
+pub fn tangents(n: &Vec3) -> (Vec3, Vec3) {
+    let helper = if n.0.abs() > 0.9 {Vec3(0., 1., 0.)} else {Vec3(1., 0., 0.)};
+    let tangent = unit_vector(cross(&helper, n));
+    (tangent, cross(n, &tangent))
+}